@@ -0,0 +1,512 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use ast::{Node, NodeList, DomAttr, Operator, Constant, Visitor, VisitResult};
+use token::{Token, TokenKind};
+
+/// 上下文数据的类型描述，用于模板的类型检查。
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    /// 未知类型，不做检查
+    Any,
+    Null,
+    Bool,
+    Int,
+    Float,
+    String,
+    /// 元素类型相同的数组
+    Array(Box<Type>),
+    /// 字段固定的对象，访问未声明的字段会被报告
+    Object(BTreeMap<String, Type>),
+    /// 键任意、值类型相同的键值对集合
+    Map(Box<Type>),
+    /// 可以为 null 的值
+    Optional(Box<Type>),
+    /// 整数区间
+    Range,
+    /// 箭头函数或宿主函数
+    Function,
+    /// 循环体内的 `loop` 对象
+    Loop,
+}
+
+impl Type {
+    /// 由字段列表创建对象类型。
+    pub fn object(fields: Vec<(&str, Type)>) -> Type {
+        Type::Object(fields.into_iter().map(|(name, t)| (name.to_string(), t)).collect())
+    }
+
+    pub fn array(item: Type) -> Type {
+        Type::Array(Box::new(item))
+    }
+
+    pub fn map(value: Type) -> Type {
+        Type::Map(Box::new(value))
+    }
+
+    pub fn optional(inner: Type) -> Type {
+        Type::Optional(Box::new(inner))
+    }
+
+    /// 去掉可空包装后的类型。
+    fn strip(&self) -> &Type {
+        match self {
+            &Type::Optional(ref inner) => inner.strip(),
+            t => t,
+        }
+    }
+
+    fn is_any(&self) -> bool {
+        self.strip() == &Type::Any
+    }
+
+    fn is_numeric(&self) -> bool {
+        match self.strip() {
+            &Type::Any | &Type::Int | &Type::Float => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Type::Any => write!(f, "any"),
+            &Type::Null => write!(f, "null"),
+            &Type::Bool => write!(f, "bool"),
+            &Type::Int => write!(f, "int"),
+            &Type::Float => write!(f, "float"),
+            &Type::String => write!(f, "string"),
+            &Type::Array(ref item) => write!(f, "array<{}>", item),
+            &Type::Object(_) => write!(f, "object"),
+            &Type::Map(ref value) => write!(f, "map<{}>", value),
+            &Type::Optional(ref inner) => write!(f, "{}?", inner),
+            &Type::Range => write!(f, "range"),
+            &Type::Function => write!(f, "function"),
+            &Type::Loop => write!(f, "loop"),
+        }
+    }
+}
+
+/// 类型检查发现的问题。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// 问题在源中的偏移
+    pub offset: usize,
+}
+
+/// 按上下文的类型描述检查模板，schema 通常是 `Type::Object`。
+pub fn check(list: &NodeList, schema: &Type) -> Vec<Diagnostic> {
+    let mut checker = Checker::new(schema.clone());
+    checker.visit_list(list).unwrap();
+    return checker.diagnostics;
+}
+
+/// 推导表达式类型并报告类型错误的访问器。
+///
+/// 报告未声明的变量和字段、运算符的操作数类型错误、`for` 中不可迭代的值以及非布尔的条件。
+pub struct Checker {
+    schema: Type,
+    scopes: Vec<HashMap<String, Type>>,
+    stack: Vec<Type>,
+    /// 最近一个带位置的标记，用于没有标记的节点
+    position: usize,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    pub fn new(schema: Type) -> Checker {
+        Checker { schema: schema, scopes: vec![], stack: vec![], position: 0, diagnostics: vec![] }
+    }
+
+    fn report(&mut self, node: &Node, message: String) {
+        let offset = node.offset().unwrap_or(self.position);
+        self.diagnostics.push(Diagnostic { message: message, offset: offset });
+    }
+
+    fn type_of(&mut self, node: &Node) -> Type {
+        if let &Node::Empty = node {
+            return Type::Null;
+        }
+        let depth = self.stack.len();
+        self.visit(node).unwrap();
+        if self.stack.len() > depth {
+            return self.stack.pop().unwrap();
+        }
+        return Type::Any;
+    }
+
+    fn bind(&mut self, name: &Token, t: Type) {
+        if name.kind() == &TokenKind::Ignore {
+            return;
+        }
+        let last = self.scopes.len() - 1;
+        self.scopes[last].insert(name.value_str().to_string(), t);
+    }
+
+    /// 检查条件表达式必须为布尔值。
+    fn check_condition(&mut self, node: &Node) {
+        let t = self.type_of(node);
+        self.expect_bool(node, &t);
+    }
+
+    fn expect_bool(&mut self, node: &Node, t: &Type) {
+        match t.strip() {
+            &Type::Any | &Type::Bool => {}
+            _ => self.report(node, format!("condition must be bool, found {}", t)),
+        }
+    }
+
+    fn check_int(&mut self, node: &Node, what: &str) {
+        let t = self.type_of(node);
+        match t.strip() {
+            &Type::Any | &Type::Int | &Type::Null => {}
+            _ => self.report(node, format!("{} must be int, found {}", what, t)),
+        }
+    }
+
+    /// 成员的类型，未知成员返回 None。
+    fn member_type(&self, object: &Type, key: &Node) -> Option<Type> {
+        let name = match key {
+            &Node::Const(Constant::String(_, ref name)) => Some(name.as_str()),
+            _ => None,
+        };
+        let t = match (object.strip(), name) {
+            (&Type::Any, _) => Type::Any,
+            (&Type::Object(ref fields), Some(name)) => { return fields.get(name).cloned(); }
+            (&Type::Object(_), None) => Type::Any,
+            (&Type::Map(ref value), _) => Type::optional((**value).clone()),
+            (&Type::Array(_), Some("length")) | (&Type::String, Some("length")) | (&Type::Range, Some("length")) => Type::Int,
+            (&Type::Array(ref item), None) => (**item).clone(),
+            (&Type::Range, None) => Type::Int,
+            (&Type::Loop, Some("parent")) => Type::optional(Type::Loop),
+            (&Type::Loop, Some("first")) | (&Type::Loop, Some("last")) => Type::Bool,
+            (&Type::Loop, Some("index")) | (&Type::Loop, Some("index0")) | (&Type::Loop, Some("length")) |
+            (&Type::Loop, Some("revindex")) | (&Type::Loop, Some("revindex0")) => Type::Int,
+            _ => { return None; }
+        };
+        return Some(t);
+    }
+
+    /// 推导二元运算的类型，node 为左操作数，用于定位。
+    fn binary_type(&mut self, node: &Node, left: &Type, right: &Type, operator: &Operator) -> Type {
+        match operator {
+            &Operator::And | &Operator::Or => Type::Bool,
+            &Operator::Eq | &Operator::NotEq => Type::Bool,
+            &Operator::NullCond => {
+                match left {
+                    &Type::Optional(ref inner) => (**inner).clone(),
+                    _ => left.clone(),
+                }
+            }
+            &Operator::Gt | &Operator::Gte | &Operator::Lt | &Operator::Lte => {
+                let comparable = (left.is_numeric() && right.is_numeric())
+                    || (left.is_any() || left.strip() == &Type::String) && (right.is_any() || right.strip() == &Type::String);
+                if !comparable {
+                    self.report(node, format!("cannot compare {} with {}", left, right));
+                }
+                Type::Bool
+            }
+            &Operator::In | &Operator::NotIn => {
+                match right.strip() {
+                    &Type::Any | &Type::Array(_) | &Type::Map(_) | &Type::Object(_) | &Type::String | &Type::Range => {}
+                    _ => self.report(node, format!("cannot test membership in {}", right)),
+                }
+                Type::Bool
+            }
+            &Operator::Add => {
+                match (left.strip(), right.strip()) {
+                    (&Type::String, _) | (_, &Type::String) => Type::String,
+                    (&Type::Array(_), &Type::Array(_)) => left.strip().clone(),
+                    _ => self.arithmetic_type(node, left, right, operator),
+                }
+            }
+            _ => self.arithmetic_type(node, left, right, operator),
+        }
+    }
+
+    fn arithmetic_type(&mut self, node: &Node, left: &Type, right: &Type, operator: &Operator) -> Type {
+        if !left.is_numeric() || !right.is_numeric() {
+            self.report(node, format!("unsupported operand types for {:?}: {} and {}", operator, left, right));
+            return Type::Any;
+        }
+        match (left.strip(), right.strip(), operator) {
+            // 负指数的结果为浮点数
+            (&Type::Int, &Type::Int, &Operator::Pow) => Type::Any,
+            (&Type::Int, &Type::Int, _) => Type::Int,
+            (&Type::Any, _, _) | (_, &Type::Any, _) => Type::Any,
+            _ => Type::Float,
+        }
+    }
+}
+
+impl Visitor for Checker {
+    fn visit_literal(&mut self, _tok: &Token) -> VisitResult {
+        Ok(())
+    }
+
+    fn visit_dom_tag(&mut self, name: &Token, attrs: &Vec<DomAttr>, children: &NodeList) -> VisitResult {
+        self.position = name.offset();
+        for attr in attrs {
+            self.visit_list(&attr.value)?;
+        }
+        return self.visit_list(children);
+    }
+
+    fn visit_ternary(&mut self, expr: &Node, left: &Node, right: &Node) -> VisitResult {
+        self.check_condition(expr);
+        let left = self.type_of(left);
+        let right = self.type_of(right);
+        self.stack.push(if left == right { left } else { Type::Any });
+        return Ok(());
+    }
+
+    fn visit_binary(&mut self, left: &Node, right: &Node, operator: &Operator) -> VisitResult {
+        let lhs = self.type_of(left);
+        let rhs = self.type_of(right);
+        if operator == &Operator::And || operator == &Operator::Or {
+            self.expect_bool(left, &lhs);
+            self.expect_bool(right, &rhs);
+        }
+        let t = self.binary_type(left, &lhs, &rhs, operator);
+        self.stack.push(t);
+        return Ok(());
+    }
+
+    fn visit_unary(&mut self, body: &Node, operator: &Operator) -> VisitResult {
+        if operator == &Operator::Not {
+            self.check_condition(body);
+            self.stack.push(Type::Bool);
+            return Ok(());
+        }
+        let t = self.type_of(body);
+        if !t.is_numeric() {
+            self.report(body, format!("unsupported operand type for {:?}: {}", operator, t));
+            self.stack.push(Type::Any);
+            return Ok(());
+        }
+        self.stack.push(t.strip().clone());
+        return Ok(());
+    }
+
+    fn visit_property(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult {
+        let object = self.type_of(obj);
+        for param in params {
+            self.type_of(param);
+        }
+        self.position = operator.offset();
+        let key = match params.first() {
+            Some(key) => key,
+            None => {
+                self.stack.push(Type::Any);
+                return Ok(());
+            }
+        };
+        let t = match self.member_type(&object, key) {
+            Some(t) => t,
+            None => {
+                let name = match key {
+                    &Node::Const(Constant::String(_, ref name)) => name.clone(),
+                    _ => format!("[...]"),
+                };
+                self.diagnostics.push(Diagnostic { message: format!("unknown field {} on {}", name, object), offset: operator.offset() });
+                Type::Any
+            }
+        };
+        self.stack.push(t);
+        return Ok(());
+    }
+
+    fn visit_method(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult {
+        match obj {
+            // 方法调用只检查接收者
+            &Node::Property(ref receiver, _, _) | &Node::OptionalProperty(ref receiver, _, _) => { self.type_of(receiver); }
+            _ => {
+                let t = self.type_of(obj);
+                match t.strip() {
+                    &Type::Any | &Type::Function => {}
+                    _ => self.report(obj, format!("{} is not callable", t)),
+                }
+            }
+        }
+        for param in params {
+            self.type_of(param);
+        }
+        self.position = operator.offset();
+        self.stack.push(Type::Any);
+        return Ok(());
+    }
+
+    fn visit_optional_property(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult {
+        self.visit_property(obj, params, operator)?;
+        let t = self.stack.pop().unwrap();
+        self.stack.push(Type::optional(t));
+        return Ok(());
+    }
+
+    fn visit_optional_method(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult {
+        self.visit_method(obj, params, operator)
+    }
+
+    fn visit_range(&mut self, start: &Node, end: &Node, step: &Node, _inclusive: &bool) -> VisitResult {
+        self.check_int(start, "range start");
+        self.check_int(end, "range end");
+        self.check_int(step, "range step");
+        self.stack.push(Type::Range);
+        return Ok(());
+    }
+
+    fn visit_slice(&mut self, obj: &Node, start: &Node, end: &Node, step: &Node, operator: &Token) -> VisitResult {
+        let t = self.type_of(obj);
+        self.position = operator.offset();
+        self.check_int(start, "slice start");
+        self.check_int(end, "slice end");
+        self.check_int(step, "slice step");
+        match t.strip() {
+            &Type::Any | &Type::Array(_) | &Type::String | &Type::Range => {}
+            _ => self.report(obj, format!("{} cannot be sliced", t)),
+        }
+        self.stack.push(t.strip().clone());
+        return Ok(());
+    }
+
+    fn visit_const(&mut self, tok: &Constant) -> VisitResult {
+        let t = match tok {
+            &Constant::Break(_) | &Constant::Continue(_) => { return Ok(()); }
+            &Constant::None => Type::Null,
+            &Constant::True | &Constant::False => Type::Bool,
+            &Constant::String(_, _) => Type::String,
+            &Constant::Integer(_, _) => Type::Int,
+            &Constant::Float(_, _) => Type::Float,
+        };
+        self.stack.push(t);
+        return Ok(());
+    }
+
+    fn visit_template(&mut self, parts: &NodeList) -> VisitResult {
+        for part in parts {
+            self.type_of(part);
+        }
+        self.stack.push(Type::String);
+        return Ok(());
+    }
+
+    fn visit_identifier(&mut self, tok: &Token) -> VisitResult {
+        self.position = tok.offset();
+        let name = tok.value_str();
+        for scope in self.scopes.iter().rev() {
+            if let Some(t) = scope.get(name) {
+                self.stack.push(t.clone());
+                return Ok(());
+            }
+        }
+        let t = match self.schema.strip() {
+            &Type::Object(ref fields) => fields.get(name).cloned(),
+            _ => Some(Type::Any),
+        };
+        match t {
+            Some(t) => self.stack.push(t),
+            None => {
+                self.diagnostics.push(Diagnostic { message: format!("unknown variable {}", name), offset: tok.offset() });
+                self.stack.push(Type::Any);
+            }
+        }
+        return Ok(());
+    }
+
+    fn visit_if(&mut self, condition: &Node, body: &NodeList, branches: &NodeList, _is_else_if: &bool) -> VisitResult {
+        self.check_condition(condition);
+        self.visit_list(body)?;
+        return self.visit_list(branches);
+    }
+
+    fn visit_for(&mut self, key: &Token, value: &Token, iter: &Node, body: &NodeList, for_else: &Node) -> VisitResult {
+        self.position = key.offset();
+        let t = self.type_of(iter);
+        let (key_type, item_type) = match t.strip() {
+            &Type::Any | &Type::Object(_) => (Type::Any, Type::Any),
+            &Type::Null => (Type::Null, Type::Null),
+            &Type::Array(ref item) => (Type::Int, (**item).clone()),
+            &Type::Map(ref item) => (Type::String, (**item).clone()),
+            &Type::String => (Type::Int, Type::String),
+            &Type::Range => (Type::Int, Type::Int),
+            _ => {
+                self.report(iter, format!("{} is not iterable", t));
+                (Type::Any, Type::Any)
+            }
+        };
+        self.scopes.push(HashMap::new());
+        if value.kind() == &TokenKind::Ignore {
+            self.bind(key, item_type);
+        } else {
+            self.bind(key, key_type);
+            self.bind(value, item_type);
+        }
+        self.scopes.last_mut().unwrap().insert("loop".to_string(), Type::Loop);
+        let result = self.visit_list(body);
+        self.scopes.pop();
+        result?;
+        return self.visit(for_else);
+    }
+
+    fn visit_with(&mut self, expr: &Node, alias: &Token, body: &NodeList, with_else: &Node) -> VisitResult {
+        let t = self.type_of(expr);
+        self.scopes.push(HashMap::new());
+        self.bind(alias, t.strip().clone());
+        let result = self.visit_list(body);
+        self.scopes.pop();
+        result?;
+        return self.visit(with_else);
+    }
+
+    fn visit_print(&mut self, body: &Node, _escape: &bool) -> VisitResult {
+        self.type_of(body);
+        return Ok(());
+    }
+
+    fn visit_array(&mut self, items: &NodeList) -> VisitResult {
+        let mut item_type: Option<Type> = None;
+        for item in items {
+            let t = self.type_of(item);
+            item_type = match item_type {
+                None => Some(t),
+                Some(ref prev) if prev == &t => Some(t),
+                Some(_) => Some(Type::Any),
+            };
+        }
+        self.stack.push(Type::array(item_type.unwrap_or(Type::Any)));
+        return Ok(());
+    }
+
+    fn visit_map(&mut self, entries: &NodeList) -> VisitResult {
+        let mut fields = BTreeMap::new();
+        for entry in entries {
+            if let &Node::MapEntry(ref key, ref value) = entry {
+                let t = self.type_of(value);
+                fields.insert(key.value_str().to_string(), t);
+            }
+        }
+        self.stack.push(Type::Object(fields));
+        return Ok(());
+    }
+
+    fn visit_map_entry(&mut self, _key: &Token, value: &Node) -> VisitResult {
+        self.visit(value)
+    }
+
+    fn visit_lambda(&mut self, params: &Vec<Token>, body: &Node) -> VisitResult {
+        self.scopes.push(HashMap::new());
+        for param in params {
+            self.bind(param, Type::Any);
+        }
+        self.type_of(body);
+        self.scopes.pop();
+        self.stack.push(Type::Function);
+        return Ok(());
+    }
+
+    fn visit_include(&mut self, tok: &Token, _name: &String) -> VisitResult {
+        self.position = tok.offset();
+        return Ok(());
+    }
+}
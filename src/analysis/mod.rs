@@ -0,0 +1,7 @@
+//! 模板的静态分析。
+
+mod variables;
+mod checker;
+
+pub use self::variables::{Analyzer, Report, analyze};
+pub use self::checker::{Checker, Type, Diagnostic, check};
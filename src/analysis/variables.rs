@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use ast::{Node, NodeList, DomAttr, Operator, Constant, Visitor, VisitResult};
+use token::{Token, TokenKind};
+
+/// 模板静态分析的结果，各列表按首次出现的顺序排列且不重复。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// 模板从上下文读取的顶层变量，不含循环变量、`with` 别名和箭头函数参数
+    pub variables: Vec<String>,
+    /// 访问的属性路径，如：`order.customer.name`、`items[].price`，
+    /// 通过 `with` 别名或循环变量的访问会被还原为上下文中的路径，已被更长路径包含的前缀不会列出
+    pub properties: Vec<String>,
+    /// 以 `name(...)` 形式调用的函数
+    pub functions: Vec<String>,
+    /// `{{include}}` 引入的模板
+    pub includes: Vec<String>,
+}
+
+fn push_unique(list: &mut Vec<String>, item: String) {
+    if !list.contains(&item) {
+        list.push(item);
+    }
+}
+
+/// 分析模板中引用的变量、属性路径、函数和引入的模板。
+pub fn analyze(list: &NodeList) -> Report {
+    let mut analyzer = Analyzer::new();
+    analyzer.visit_list(list).unwrap();
+    return analyzer.report();
+}
+
+/// 收集模板依赖的访问器。
+pub struct Analyzer {
+    report: Report,
+    /// 局部绑定的作用域链，值为绑定对应的上下文路径，如果可以确定的话
+    scopes: Vec<HashMap<String, Option<String>>>,
+}
+
+impl Analyzer {
+    pub fn new() -> Analyzer {
+        Analyzer { report: Report::default(), scopes: vec![] }
+    }
+
+    /// 获取分析结果。
+    pub fn report(&self) -> Report {
+        let mut report = self.report.clone();
+        let all = report.properties.clone();
+        report.properties.retain(|path| {
+            !all.iter().any(|other| other.len() > path.len() && other.starts_with(path.as_str())
+                && (other[path.len()..].starts_with('.') || other[path.len()..].starts_with('[')))
+        });
+        return report;
+    }
+
+    fn resolve(&self, name: &str) -> Option<&Option<String>> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(path) = scope.get(name) {
+                return Some(path);
+            }
+        }
+        return None;
+    }
+
+    fn bind(&mut self, name: &Token, path: Option<String>) {
+        if name.kind() == &TokenKind::Ignore {
+            return;
+        }
+        let last = self.scopes.len() - 1;
+        self.scopes[last].insert(name.value_str().to_string(), path);
+    }
+
+    /// 计算以变量开始、由常量成员组成的访问路径，无法确定时返回 None。
+    fn path(&self, node: &Node) -> Option<String> {
+        match node {
+            &Node::Identifier(ref tok) => {
+                match self.resolve(tok.value_str()) {
+                    Some(path) => path.clone(),
+                    None => Some(tok.value_str().to_string()),
+                }
+            }
+            &Node::Property(ref obj, ref params, _) | &Node::OptionalProperty(ref obj, ref params, _) => {
+                self.property_path(obj, params)
+            }
+            &Node::Slice(ref obj, _, _, _, _) => self.path(obj),
+            _ => None,
+        }
+    }
+
+    /// 计算属性访问的路径，常量字符串键记为 `.name`，其它索引记为 `[]`。
+    fn property_path(&self, obj: &Node, params: &NodeList) -> Option<String> {
+        let base = self.path(obj)?;
+        match params.first() {
+            Some(&Node::Const(Constant::String(_, ref name))) => Some(format!("{}.{}", base, name)),
+            _ => Some(format!("{}[]", base)),
+        }
+    }
+}
+
+impl Visitor for Analyzer {
+    fn visit_literal(&mut self, _tok: &Token) -> VisitResult {
+        Ok(())
+    }
+
+    fn visit_dom_tag(&mut self, _name: &Token, attrs: &Vec<DomAttr>, children: &NodeList) -> VisitResult {
+        for attr in attrs {
+            self.visit_list(&attr.value)?;
+        }
+        return self.visit_list(children);
+    }
+
+    fn visit_ternary(&mut self, expr: &Node, left: &Node, right: &Node) -> VisitResult {
+        self.visit(expr)?;
+        self.visit(left)?;
+        return self.visit(right);
+    }
+
+    fn visit_binary(&mut self, left: &Node, right: &Node, _operator: &Operator) -> VisitResult {
+        self.visit(left)?;
+        return self.visit(right);
+    }
+
+    fn visit_unary(&mut self, body: &Node, _operator: &Operator) -> VisitResult {
+        self.visit(body)
+    }
+
+    fn visit_property(&mut self, obj: &Node, params: &NodeList, _operator: &Token) -> VisitResult {
+        if let Some(path) = self.property_path(obj, params) {
+            push_unique(&mut self.report.properties, path);
+        }
+        self.visit(obj)?;
+        return self.visit_list(params);
+    }
+
+    fn visit_method(&mut self, obj: &Node, params: &NodeList, _operator: &Token) -> VisitResult {
+        match obj {
+            &Node::Identifier(ref name) if self.resolve(name.value_str()).is_none() => {
+                push_unique(&mut self.report.functions, name.value_str().to_string());
+            }
+            // 方法调用只记录接收者，如：loop.cycle(...) 中的 loop
+            &Node::Property(ref receiver, _, _) | &Node::OptionalProperty(ref receiver, _, _) => {
+                self.visit(receiver)?;
+            }
+            _ => { self.visit(obj)?; }
+        }
+        return self.visit_list(params);
+    }
+
+    fn visit_optional_property(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult {
+        self.visit_property(obj, params, operator)
+    }
+
+    fn visit_optional_method(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult {
+        self.visit_method(obj, params, operator)
+    }
+
+    fn visit_range(&mut self, start: &Node, end: &Node, step: &Node, _inclusive: &bool) -> VisitResult {
+        self.visit(start)?;
+        self.visit(end)?;
+        return self.visit(step);
+    }
+
+    fn visit_slice(&mut self, obj: &Node, start: &Node, end: &Node, step: &Node, _operator: &Token) -> VisitResult {
+        self.visit(obj)?;
+        self.visit(start)?;
+        self.visit(end)?;
+        return self.visit(step);
+    }
+
+    fn visit_const(&mut self, _tok: &Constant) -> VisitResult {
+        Ok(())
+    }
+
+    fn visit_template(&mut self, parts: &NodeList) -> VisitResult {
+        self.visit_list(parts)
+    }
+
+    fn visit_identifier(&mut self, tok: &Token) -> VisitResult {
+        match self.resolve(tok.value_str()).cloned() {
+            None => push_unique(&mut self.report.variables, tok.value_str().to_string()),
+            // with 别名或循环变量对应的上下文路径
+            Some(Some(path)) => push_unique(&mut self.report.properties, path),
+            Some(None) => {}
+        }
+        return Ok(());
+    }
+
+    fn visit_if(&mut self, condition: &Node, body: &NodeList, branches: &NodeList, _is_else_if: &bool) -> VisitResult {
+        self.visit(condition)?;
+        self.visit_list(body)?;
+        return self.visit_list(branches);
+    }
+
+    fn visit_for(&mut self, key: &Token, value: &Token, iter: &Node, body: &NodeList, for_else: &Node) -> VisitResult {
+        self.visit(iter)?;
+        let item = self.path(iter).map(|path| format!("{}[]", path));
+        self.scopes.push(HashMap::new());
+        if value.kind() == &TokenKind::Ignore {
+            self.bind(key, item);
+        } else {
+            self.bind(key, None);
+            self.bind(value, item);
+        }
+        self.scopes.last_mut().unwrap().insert("loop".to_string(), None);
+        let result = self.visit_list(body);
+        self.scopes.pop();
+        result?;
+        return self.visit(for_else);
+    }
+
+    fn visit_with(&mut self, expr: &Node, alias: &Token, body: &NodeList, with_else: &Node) -> VisitResult {
+        self.visit(expr)?;
+        let path = self.path(expr);
+        self.scopes.push(HashMap::new());
+        self.bind(alias, path);
+        let result = self.visit_list(body);
+        self.scopes.pop();
+        result?;
+        return self.visit(with_else);
+    }
+
+    fn visit_print(&mut self, body: &Node, _escape: &bool) -> VisitResult {
+        self.visit(body)
+    }
+
+    fn visit_array(&mut self, items: &NodeList) -> VisitResult {
+        self.visit_list(items)
+    }
+
+    fn visit_map(&mut self, entries: &NodeList) -> VisitResult {
+        self.visit_list(entries)
+    }
+
+    fn visit_map_entry(&mut self, _key: &Token, value: &Node) -> VisitResult {
+        self.visit(value)
+    }
+
+    fn visit_lambda(&mut self, params: &Vec<Token>, body: &Node) -> VisitResult {
+        self.scopes.push(HashMap::new());
+        for param in params {
+            self.bind(param, None);
+        }
+        let result = self.visit(body);
+        self.scopes.pop();
+        return result;
+    }
+
+    fn visit_include(&mut self, _tok: &Token, name: &String) -> VisitResult {
+        push_unique(&mut self.report.includes, name.clone());
+        return Ok(());
+    }
+}
@@ -31,6 +31,14 @@ pub enum Node {
     Property(Box<Node>, NodeList, Token),
     /// 访问成员方法(object, parameters, operator)
     Method(Box<Node>, NodeList, Token),
+    /// 可选链属性访问，如：a?.b、a?[0]，对象为 null 时整个访问链的结果为 null。
+    OptionalProperty(Box<Node>, NodeList, Token),
+    /// 可选调用，如：f?.(x)，函数为 null 时结果为 null。
+    OptionalMethod(Box<Node>, NodeList, Token),
+    /// 区间表达式(start, end, step, is-inclusive)，未指定步长时 step 为 Empty。
+    Range(Box<Node>, Box<Node>, Box<Node>, bool),
+    /// 切片访问(object, start, end, step, operator)，省略的部分为 Empty。
+    Slice(Box<Node>, Box<Node>, Box<Node>, Box<Node>, Token),
     /// 表示一个标示符，如：变量名。
     Identifier(Token),
     /// if/else-if条件表达式(condition, body, branch-blocks,is-else-if)
@@ -39,13 +47,48 @@ pub enum Node {
     Else(NodeList),
     /// for表达式(key-name, value-name, iter, body,else)
     For(Token, Token, Box<Node>, NodeList, Box<Node>),
+    /// with表达式(expression, alias, body, else)，当表达式的值为 null 时执行 else 分支。
+    With(Box<Node>, Token, NodeList, Box<Node>),
     Print(Box<Node>, bool),
     /// 表示一个常量
     Const(Constant),
+    /// 模板字符串，由字符串常量和内嵌表达式依次组成。
+    Template(NodeList),
     /// 表示一个数组。
     Array(NodeList),
     MapEntry(Token, Box<Node>),
     Map(NodeList),
+    /// 箭头函数，如：(x) => x.price，依次为参数列表、函数体。
+    Lambda(Vec<Token>, Box<Node>),
+    /// 引入另一个模板，如：{{include 'header.html'}}，依次为名称标记、解码后的名称。
+    Include(Token, String),
+    /// 注释，标记为 `TokenKind::Comment` 的模板注释或 `TokenKind::DomComment` 的 DOM 注释，不产生输出。
+    Comment(Token),
+}
+
+impl Node {
+    /// 查找节点中第一个带位置的标记的偏移，节点不含标记时返回 None。
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            &Node::Literal(ref tok) | &Node::DomTag(ref tok, _, _) | &Node::Identifier(ref tok) |
+            &Node::MapEntry(ref tok, _) | &Node::For(ref tok, _, _, _, _) | &Node::Include(ref tok, _) |
+            &Node::Comment(ref tok) => Some(tok.offset()),
+            &Node::Property(ref obj, _, ref tok) | &Node::OptionalProperty(ref obj, _, ref tok) |
+            &Node::Method(ref obj, _, ref tok) | &Node::OptionalMethod(ref obj, _, ref tok) |
+            &Node::Slice(ref obj, _, _, _, ref tok) => obj.offset().or(Some(tok.offset())),
+            &Node::Binary(ref left, ref right, _) => left.offset().or(right.offset()),
+            &Node::Unary(ref body, _) | &Node::Print(ref body, _) => body.offset(),
+            &Node::Ternary(ref expr, _, _) | &Node::If(ref expr, _, _, _) | &Node::With(ref expr, _, _, _) => expr.offset(),
+            &Node::Range(ref start, ref end, _, _) => start.offset().or(end.offset()),
+            &Node::Const(Constant::String(ref tok, _)) | &Node::Const(Constant::Integer(ref tok, _)) |
+            &Node::Const(Constant::Float(ref tok, _)) | &Node::Const(Constant::Break(ref tok)) |
+            &Node::Const(Constant::Continue(ref tok)) => Some(tok.offset()),
+            &Node::Root(ref list) | &Node::List(ref list) | &Node::Statement(ref list) | &Node::Else(ref list) |
+            &Node::Template(ref list) | &Node::Array(ref list) | &Node::Map(ref list) => list.iter().filter_map(|n| n.offset()).next(),
+            &Node::Lambda(ref params, ref body) => params.first().map(|p| p.offset()).or(body.offset()),
+            _ => None,
+        }
+    }
 }
 
 /// 表示一个 DOM 节点的属性，如： id。
@@ -72,20 +115,26 @@ pub enum Constant {
     None,
     True,
     False,
-    /// 表示一个字符串。
-    String(Token),
-    /// 表示一个无符号整数常量。
-    Integer(Token),
-    /// 表示一个无符号浮点数常量(integer,decimal)。
-    Float(Token, Token),
+    /// 表示一个字符串(token, 转义后的值)。
+    String(Token, String),
+    /// 表示一个有符号 64 位整数常量(token, value)，负号作为一元运算符单独解析。
+    Integer(Token, i64),
+    /// 表示一个 64 位浮点数常量(token, value)，负号作为一元运算符单独解析。
+    Float(Token, f64),
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Operator {
-    /// + or +x
+    /// +
     Add,
-    /// - or -x
+    /// -
     Sub,
+    /// -x
+    Neg,
+    /// +x
+    Pos,
+    /// **
+    Pow,
     /// *
     Mul,
     /// /
@@ -104,6 +153,10 @@ pub enum Operator {
     Eq,
     /// !=
     NotEq,
+    /// in
+    In,
+    /// not in
+    NotIn,
     /// &&
     And,
     /// ||
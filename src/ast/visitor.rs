@@ -18,15 +18,24 @@ pub trait Visitor {
             &Node::Unary(ref body, ref operator) => self.visit_unary(body, operator),
             &Node::Property(ref obj, ref params, ref operator) => self.visit_property(obj, params, operator),
             &Node::Method(ref obj, ref params, ref operator) => self.visit_method(obj, params, operator),
+            &Node::OptionalProperty(ref obj, ref params, ref operator) => self.visit_optional_property(obj, params, operator),
+            &Node::OptionalMethod(ref obj, ref params, ref operator) => self.visit_optional_method(obj, params, operator),
+            &Node::Range(ref start, ref end, ref step, ref inclusive) => self.visit_range(start, end, step, inclusive),
+            &Node::Slice(ref obj, ref start, ref end, ref step, ref operator) => self.visit_slice(obj, start, end, step, operator),
             &Node::Const(ref inner) => self.visit_const(inner),
+            &Node::Template(ref parts) => self.visit_template(parts),
             &Node::Identifier(ref inner) => self.visit_identifier(inner),
             &Node::If(ref condition, ref body, ref branches, ref is_else_if) => self.visit_if(condition, body, branches, is_else_if),
             &Node::Else(ref body) => self.visit_else(body),
             &Node::For(ref key, ref val, ref iter, ref body, ref for_else) => self.visit_for(key, val, iter, body, for_else),
+            &Node::With(ref expr, ref alias, ref body, ref with_else) => self.visit_with(expr, alias, body, with_else),
             &Node::Print(ref body, ref escape) => self.visit_print(body, escape),
             &Node::Array(ref inner) => self.visit_array(inner),
             &Node::Map(ref inner) => self.visit_map(inner),
             &Node::MapEntry(ref key,ref val) => self.visit_map_entry(key,val),
+            &Node::Lambda(ref params, ref body) => self.visit_lambda(params, body),
+            &Node::Include(ref tok, ref name) => self.visit_include(tok, name),
+            &Node::Comment(ref tok) => self.visit_comment(tok),
             _ => self.visit_undefined(node)
         }
     }
@@ -35,7 +44,7 @@ pub trait Visitor {
     fn visit_undefined(&mut self, node: &Node) -> VisitResult {
         match node {
             &Node::Empty => {}
-            _ => println!("warning: undefined visit node {:?}", node)
+            _ => trace!("warning: undefined visit node {:?}", node)
         }
         return Ok(());
     }
@@ -63,15 +72,28 @@ pub trait Visitor {
     fn visit_unary(&mut self, body: &Node, operator: &Operator) -> VisitResult;
     fn visit_property(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult;
     fn visit_method(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult;
+    fn visit_optional_property(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult;
+    fn visit_optional_method(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult;
+    fn visit_range(&mut self, start: &Node, end: &Node, step: &Node, inclusive: &bool) -> VisitResult;
+    fn visit_slice(&mut self, obj: &Node, start: &Node, end: &Node, step: &Node, operator: &Token) -> VisitResult;
     fn visit_const(&mut self, tok: &Constant) -> VisitResult;
+    fn visit_template(&mut self, parts: &NodeList) -> VisitResult;
     fn visit_identifier(&mut self, tok: &Token) -> VisitResult;
     fn visit_if(&mut self, condition: &Node, body: &NodeList, branches: &NodeList, is_else_if: &bool) -> VisitResult;
     fn visit_else(&mut self, body: &NodeList) -> VisitResult {
         self.visit_list(body)
     }
+    /// 注释不产生任何输出。
+    #[allow(unused_variables)]
+    fn visit_comment(&mut self, tok: &Token) -> VisitResult {
+        Ok(())
+    }
     fn visit_for(&mut self, key: &Token, value: &Token, iter: &Node, body: &NodeList, for_else: &Node) -> VisitResult;
+    fn visit_with(&mut self, expr: &Node, alias: &Token, body: &NodeList, with_else: &Node) -> VisitResult;
     fn visit_print(&mut self, body: &Node, escape: &bool) -> VisitResult;
     fn visit_array(&mut self, items: &NodeList) -> VisitResult;
     fn visit_map(&mut self, entries: &NodeList) -> VisitResult;
     fn visit_map_entry(&mut self, key: &Token, value: &Node) -> VisitResult;
+    fn visit_lambda(&mut self, params: &Vec<Token>, body: &Node) -> VisitResult;
+    fn visit_include(&mut self, tok: &Token, name: &String) -> VisitResult;
 }
\ No newline at end of file
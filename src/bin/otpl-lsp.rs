@@ -0,0 +1,19 @@
+//! 模板的语言服务器，通过标准输入输出与编辑器通信。
+
+extern crate otpl;
+
+use std::io;
+use std::process;
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let code = match otpl::lsp::run(&mut stdin.lock(), &mut stdout.lock()) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("otpl-lsp: {}", err);
+            1
+        }
+    };
+    process::exit(code);
+}
@@ -0,0 +1,16 @@
+//! 检查、渲染模板的命令行工具，用法见 `otpl help`。
+
+extern crate otpl;
+
+use std::env;
+use std::io;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let stderr = io::stderr();
+    let code = otpl::cli::run(&args, &mut stdin.lock(), &mut stdout.lock(), &mut stderr.lock());
+    process::exit(code);
+}
@@ -0,0 +1,285 @@
+//! `otpl tokens`、`otpl ast` 命令的输出：将标记和语法树转换为 JSON，或由 JSON 生成缩进的文本。
+
+use ast::{Node, NodeList, Constant};
+use json::Json;
+use scanner::{BytesScanner, Source, Tokenizer};
+use token::{Token, TokenKind};
+use {Error, NoneResult};
+
+/// 扫描源码中的所有标记（不含 EOF）到 list，出错时 list 中保留出错前的标记。
+pub fn tokens(scanner: &mut BytesScanner, list: &mut Vec<Token>) -> NoneResult {
+    loop {
+        match scanner.scan() {
+            Ok(tok) => list.push(tok),
+            Err(Error::EOF) => { return Ok(()); }
+            Err(err) => { return Err(err); }
+        }
+    }
+}
+
+/// 标记的 JSON 表示：种类、行号、列号、偏移和值。
+pub fn token_json(tok: &Token, source: &dyn Source) -> Json {
+    return Json::object(vec![
+        ("kind", format!("{:?}", tok.kind()).into()),
+        ("line", source.line(tok.offset()).into()),
+        ("column", source.column(tok.offset()).into()),
+        ("offset", tok.offset().into()),
+        ("value", tok.value_str().into()),
+    ]);
+}
+
+/// 标记的单行文本表示，如：`   1:5    Identifier       "name"`。
+pub fn token_text(tok: &Token, source: &dyn Source) -> String {
+    let position = format!("{}:{}", source.line(tok.offset()), source.column(tok.offset()));
+    return format!("{:>8}  {:<16} {}", position, format!("{:?}", tok.kind()), Json::from(tok.value_str()));
+}
+
+/// 节点列表的 JSON 表示。
+pub fn list_json(list: &NodeList, source: &dyn Source) -> Json {
+    Json::Array(list.iter().filter(|n| !is_empty(n)).map(|n| node_json(n, source)).collect())
+}
+
+fn is_empty(node: &Node) -> bool {
+    match node {
+        &Node::Empty => true,
+        _ => false,
+    }
+}
+
+/// 节点的 JSON 表示：`type` 为节点种类，带位置的节点有 `line`、`column` 字段，子节点按含义命名，
+/// 占位的空节点被省略。
+pub fn node_json(node: &Node, source: &dyn Source) -> Json {
+    let mut fields: Vec<(&str, Json)> = vec![];
+    let mut position = node.offset();
+    let kind = match node {
+        &Node::Empty => "Empty",
+        &Node::Root(ref list) => {
+            fields.push(("children", list_json(list, source)));
+            "Root"
+        }
+        &Node::Literal(ref tok) => {
+            fields.push(("value", tok.value_str().into()));
+            "Literal"
+        }
+        &Node::DomTag(ref name, ref attrs, ref children) => {
+            fields.push(("name", name.value_str().into()));
+            let attrs = attrs.iter().map(|attr| {
+                Json::object(vec![
+                    ("type", "Attribute".into()),
+                    ("name", attr.name.value_str().into()),
+                    ("line", source.line(attr.name.offset()).into()),
+                    ("column", source.column(attr.name.offset()).into()),
+                    ("value", list_json(&attr.value, source)),
+                ])
+            }).collect::<Vec<Json>>();
+            fields.push(("attributes", attrs.into()));
+            fields.push(("children", list_json(children, source)));
+            "DomTag"
+        }
+        &Node::List(ref list) => {
+            fields.push(("children", list_json(list, source)));
+            "List"
+        }
+        &Node::Statement(ref list) => {
+            fields.push(("children", list_json(list, source)));
+            "Statement"
+        }
+        &Node::Ternary(ref expr, ref left, ref right) => {
+            fields.push(("condition", node_json(expr, source)));
+            fields.push(("left", node_json(left, source)));
+            fields.push(("right", node_json(right, source)));
+            "Ternary"
+        }
+        &Node::Binary(ref left, ref right, ref op) => {
+            fields.push(("operator", format!("{:?}", op).into()));
+            fields.push(("left", node_json(left, source)));
+            fields.push(("right", node_json(right, source)));
+            "Binary"
+        }
+        &Node::Unary(ref body, ref op) => {
+            fields.push(("operator", format!("{:?}", op).into()));
+            fields.push(("operand", node_json(body, source)));
+            "Unary"
+        }
+        &Node::Property(ref obj, ref params, ref operator) | &Node::Method(ref obj, ref params, ref operator) |
+        &Node::OptionalProperty(ref obj, ref params, ref operator) | &Node::OptionalMethod(ref obj, ref params, ref operator) => {
+            position = Some(operator.offset());
+            fields.push(("object", node_json(obj, source)));
+            fields.push(("arguments", list_json(params, source)));
+            match node {
+                &Node::Property(..) => "Property",
+                &Node::Method(..) => "Method",
+                &Node::OptionalProperty(..) => "OptionalProperty",
+                _ => "OptionalMethod",
+            }
+        }
+        &Node::Range(ref start, ref end, ref step, inclusive) => {
+            fields.push(("start", node_json(start, source)));
+            fields.push(("end", node_json(end, source)));
+            if !is_empty(step) {
+                fields.push(("step", node_json(step, source)));
+            }
+            fields.push(("inclusive", inclusive.into()));
+            "Range"
+        }
+        &Node::Slice(ref obj, ref start, ref end, ref step, ref operator) => {
+            position = Some(operator.offset());
+            fields.push(("object", node_json(obj, source)));
+            for &(name, part) in [("start", start), ("end", end), ("step", step)].iter() {
+                if !is_empty(part) {
+                    fields.push((name, node_json(part, source)));
+                }
+            }
+            "Slice"
+        }
+        &Node::Identifier(ref tok) => {
+            fields.push(("name", tok.value_str().into()));
+            "Identifier"
+        }
+        &Node::If(ref condition, ref body, ref branches, is_else_if) => {
+            fields.push(("condition", node_json(condition, source)));
+            fields.push(("body", list_json(body, source)));
+            fields.push(("branches", list_json(branches, source)));
+            fields.push(("else_if", is_else_if.into()));
+            "If"
+        }
+        &Node::Else(ref body) => {
+            fields.push(("body", list_json(body, source)));
+            "Else"
+        }
+        &Node::For(ref key, ref value, ref iter, ref body, ref for_else) => {
+            fields.push(("key", key.value_str().into()));
+            if value.kind() != &TokenKind::Ignore {
+                fields.push(("value", value.value_str().into()));
+            }
+            fields.push(("iterable", node_json(iter, source)));
+            fields.push(("body", list_json(body, source)));
+            if !is_empty(for_else) {
+                fields.push(("else", node_json(for_else, source)));
+            }
+            "For"
+        }
+        &Node::With(ref expr, ref alias, ref body, ref with_else) => {
+            fields.push(("expression", node_json(expr, source)));
+            if alias.kind() != &TokenKind::Ignore {
+                fields.push(("alias", alias.value_str().into()));
+            }
+            fields.push(("body", list_json(body, source)));
+            if !is_empty(with_else) {
+                fields.push(("else", node_json(with_else, source)));
+            }
+            "With"
+        }
+        &Node::Print(ref body, escape) => {
+            fields.push(("expression", node_json(body, source)));
+            fields.push(("escape", escape.into()));
+            "Print"
+        }
+        &Node::Const(ref constant) => {
+            let value = match constant {
+                &Constant::Break(_) => "break".into(),
+                &Constant::Continue(_) => "continue".into(),
+                &Constant::None => Json::Null,
+                &Constant::True => Json::Bool(true),
+                &Constant::False => Json::Bool(false),
+                &Constant::String(_, ref s) => s.as_str().into(),
+                &Constant::Integer(_, i) => i.into(),
+                &Constant::Float(_, f) => f.into(),
+            };
+            fields.push(("value", value));
+            "Const"
+        }
+        &Node::Template(ref parts) => {
+            fields.push(("parts", list_json(parts, source)));
+            "Template"
+        }
+        &Node::Array(ref items) => {
+            fields.push(("items", list_json(items, source)));
+            "Array"
+        }
+        &Node::Map(ref entries) => {
+            fields.push(("entries", list_json(entries, source)));
+            "Map"
+        }
+        &Node::MapEntry(ref key, ref value) => {
+            fields.push(("key", key.value_str().into()));
+            fields.push(("value", node_json(value, source)));
+            "MapEntry"
+        }
+        &Node::Lambda(ref params, ref body) => {
+            fields.push(("params", params.iter().map(|p| p.value_str().into()).collect::<Vec<Json>>().into()));
+            fields.push(("body", node_json(body, source)));
+            "Lambda"
+        }
+        &Node::Include(_, ref name) => {
+            fields.push(("name", name.as_str().into()));
+            "Include"
+        }
+        &Node::Comment(ref tok) => {
+            fields.push(("text", tok.value_str().into()));
+            "Comment"
+        }
+    };
+    fields.push(("type", kind.into()));
+    if let Some(offset) = position {
+        fields.push(("line", source.line(offset).into()));
+        fields.push(("column", source.column(offset).into()));
+    }
+    return Json::object(fields);
+}
+
+/// 判断 JSON 值是否表示节点或节点列表。
+fn is_node(value: &Json) -> bool {
+    match value {
+        &Json::Object(_) => true,
+        &Json::Array(ref items) => !items.is_empty() && items.iter().all(is_node),
+        _ => false,
+    }
+}
+
+/// 将节点的 JSON 表示输出为缩进的文本：每个节点一行，依次为种类、属性和位置，子节点缩进列在所属字段下。
+///
+/// ```text
+/// DomTag name="p" @1:2
+///   children:
+///     Print escape=true @1:6
+///       expression:
+///         Identifier name="title" @1:6
+/// ```
+pub fn tree_text(node: &Json, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    out.push_str(&indent);
+    out.push_str(node.get("type").and_then(|t| t.as_str()).unwrap_or("?"));
+    if let &Json::Object(ref fields) = node {
+        for (name, value) in fields {
+            if name == "type" || name == "line" || name == "column" || is_node(value) {
+                continue;
+            }
+            // 空的子节点列表不输出
+            if let &Json::Array(ref items) = value {
+                if items.is_empty() {
+                    continue;
+                }
+            }
+            out.push_str(&format!(" {}={}", name, value));
+        }
+        if let (Some(line), Some(column)) = (node.get("line"), node.get("column")) {
+            out.push_str(&format!(" @{}:{}", line, column));
+        }
+        out.push('\n');
+        for (name, value) in fields {
+            if !is_node(value) {
+                continue;
+            }
+            out.push_str(&format!("{}  {}:\n", indent, name));
+            match value {
+                &Json::Array(ref items) => {
+                    for item in items {
+                        tree_text(item, depth + 2, out);
+                    }
+                }
+                node => tree_text(node, depth + 2, out),
+            }
+        }
+    }
+}
@@ -0,0 +1,379 @@
+//! 命令行工具 `otpl` 的实现，供不使用 Rust 的成员检查和渲染模板。
+//!
+//! - `otpl check <path>...`：解析给定的模板文件和目录（递归查找模板文件），输出诊断信息，有错误时退出码非 0；
+//! - `otpl render <template> [--data <file>] [--undefined <mode>]`：以 JSON 数据为上下文渲染模板到标准输出，
+//!   `--data -` 表示从标准输入读取数据；
+//! - `otpl tokens <file> [--json]`：列出扫描得到的所有标记及其位置；
+//! - `otpl ast <file> [--json]`：以缩进的树形式列出语法树；
+//! - `otpl watch <dir> [--render <out-dir>] [--data <file>] [--interval <ms>] [--once]`：监视目录中模板的变化，
+//!   重新检查变化的模板及依赖它们的模板，指定 `--render` 时将渲染结果写入输出目录中的同名文件。
+
+mod dump;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::result;
+use std::time::Duration;
+use engine::{Engine, FileLoader};
+use json::Json;
+use parser::Parser;
+use runtime::{Context, Undefined, Value};
+use scanner::BytesScanner;
+use watch::{diagnostic, parse_file, read_file, template_files, Event, Watcher};
+
+const USAGE: &'static str = "usage:
+    otpl check <path>...
+    otpl render <template> [--data <file>|-] [--undefined empty|debug|strict]
+    otpl tokens <file> [--json]
+    otpl ast <file> [--json]
+    otpl watch <dir> [--render <out-dir>] [--data <file>] [--interval <ms>] [--once]
+";
+
+/// 执行命令，返回进程的退出码：成功为 0，模板有错误为 1，参数错误为 2。
+pub fn run(args: &[String], input: &mut dyn Read, output: &mut dyn Write, errors: &mut dyn Write) -> i32 {
+    let command = args.first().map(|s| s.as_str()).unwrap_or("");
+    let result = match command {
+        "check" => check(&args[1..], errors),
+        "render" => render(&args[1..], input, output),
+        "tokens" => inspect(&args[1..], output, false),
+        "ast" => inspect(&args[1..], output, true),
+        "watch" => watch(&args[1..], input, output, errors),
+        "help" | "-h" | "--help" => {
+            let _ = write!(output, "{}", USAGE);
+            return 0;
+        }
+        _ => Err(Failure::Usage(format!("unknown command {:?}", command))),
+    };
+    return match result {
+        Ok(()) => 0,
+        Err(Failure::Usage(msg)) => {
+            let _ = write!(errors, "otpl: {}\n{}", msg, USAGE);
+            2
+        }
+        Err(Failure::Diagnostic(msg)) => {
+            let _ = writeln!(errors, "{}", msg);
+            1
+        }
+        Err(Failure::Reported) => 1,
+    };
+}
+
+/// 命令失败的原因。
+enum Failure {
+    /// 命令行参数错误
+    Usage(String),
+    /// 模板或数据错误，附带诊断信息
+    Diagnostic(String),
+    /// 诊断信息已经输出
+    Reported,
+}
+
+type CommandResult = result::Result<(), Failure>;
+
+fn check(args: &[String], errors: &mut dyn Write) -> CommandResult {
+    if args.is_empty() {
+        return Err(Failure::Usage(format!("check requires at least one path")));
+    }
+    let mut files = vec![];
+    for arg in args {
+        if let Err(err) = template_files(Path::new(arg), &mut files) {
+            return Err(Failure::Diagnostic(format!("{}: error: {}", arg, err)));
+        }
+    }
+    let mut failed = 0;
+    for file in files.iter() {
+        if let Err(msg) = parse_file(file) {
+            let _ = writeln!(errors, "{}", msg);
+            failed += 1;
+        }
+    }
+    let _ = writeln!(errors, "checked {} template(s), {} with errors", files.len(), failed);
+    if failed > 0 {
+        return Err(Failure::Reported);
+    }
+    return Ok(());
+}
+
+fn render(args: &[String], input: &mut dyn Read, output: &mut dyn Write) -> CommandResult {
+    let mut template = None;
+    let mut data = None;
+    let mut undefined = Undefined::Empty;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--data" | "-d" | "--undefined" if i + 1 >= args.len() => {
+                return Err(Failure::Usage(format!("{} requires a value", args[i])));
+            }
+            "--data" | "-d" => {
+                data = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--undefined" => {
+                undefined = match args[i + 1].as_str() {
+                    "empty" => Undefined::Empty,
+                    "debug" => Undefined::Debug,
+                    "strict" => Undefined::Strict,
+                    mode => { return Err(Failure::Usage(format!("unknown undefined mode {:?}", mode))); }
+                };
+                i += 1;
+            }
+            arg if template.is_none() && !arg.starts_with('-') => {
+                template = Some(arg.to_string());
+            }
+            arg => { return Err(Failure::Usage(format!("unexpected argument {:?}", arg))); }
+        }
+        i += 1;
+    }
+    let template = match template {
+        Some(template) => PathBuf::from(template),
+        None => { return Err(Failure::Usage(format!("render requires a template"))); }
+    };
+
+    let data = match data {
+        Some(data) => load_data(&data, input)?,
+        None => Json::Object(Default::default()),
+    };
+    let buf = match render_file(&template, &data, undefined) {
+        Ok(buf) => buf,
+        Err(msg) => { return Err(Failure::Diagnostic(msg)); }
+    };
+    if let Err(err) = output.write_all(&buf).and_then(|_| output.flush()) {
+        return Err(Failure::Diagnostic(format!("error: {}", err)));
+    }
+    return Ok(());
+}
+
+/// 读取 JSON 格式的上下文数据，`-` 表示标准输入，数据必须是对象。
+fn load_data(data: &str, input: &mut dyn Read) -> result::Result<Json, Failure> {
+    let mut text = String::new();
+    let read = if data == "-" {
+        input.read_to_string(&mut text)
+    } else {
+        fs::File::open(data).and_then(|mut f| f.read_to_string(&mut text))
+    };
+    if let Err(err) = read {
+        return Err(Failure::Diagnostic(format!("{}: error: {}", data, err)));
+    }
+    return match Json::parse(&text) {
+        Ok(value @ Json::Object(_)) => Ok(value),
+        Ok(_) => Err(Failure::Diagnostic(format!("{}: error: context data must be a JSON object", data))),
+        Err(err) => Err(Failure::Diagnostic(diagnostic(Path::new(data), &text, &err))),
+    };
+}
+
+/// 将 JSON 对象转换为渲染的上下文。
+fn context(data: &Json) -> Context {
+    let mut ctx = Context::new();
+    if let &Json::Object(ref entries) = data {
+        for (name, value) in entries {
+            ctx.set(name, Value::from(value.clone()));
+        }
+    }
+    return ctx;
+}
+
+/// 以给定的数据渲染模板文件，先渲染到缓冲区，出错时不输出不完整的页面。
+///
+/// 模板由以其所在目录为根的引擎加载，引入的模板名称相对该目录查找。
+fn render_file(path: &Path, data: &Json, undefined: Undefined) -> result::Result<Vec<u8>, String> {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => { return Err(format!("{}: error: invalid template path", path.display())); }
+    };
+    let mut engine = Engine::new(FileLoader::new(path.parent().unwrap_or(Path::new(""))));
+    engine.set_undefined(undefined);
+    return render_template(&engine, name, path, data);
+}
+
+/// 以给定的数据渲染引擎中名为 name 的模板，path 是模板文件的路径，用于诊断信息。
+fn render_template(engine: &Engine, name: &str, path: &Path, data: &Json) -> result::Result<Vec<u8>, String> {
+    let mut buf = vec![];
+    let rendered = engine.get_template(name).and_then(|template| template.render_to(&context(data), &mut buf));
+    if let Err(err) = rendered {
+        // 引擎返回的错误已带有行号和列号，不需要源码
+        return Err(diagnostic(path, "", &err));
+    }
+    return Ok(buf);
+}
+
+/// 输出模板的标记（`otpl tokens`）或语法树（`otpl ast`）。
+fn inspect(args: &[String], output: &mut dyn Write, ast: bool) -> CommandResult {
+    let mut file = None;
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => { json = true; }
+            arg if file.is_none() && !arg.starts_with('-') => { file = Some(PathBuf::from(arg)); }
+            arg => { return Err(Failure::Usage(format!("unexpected argument {:?}", arg))); }
+        }
+    }
+    let path = match file {
+        Some(path) => path,
+        None => { return Err(Failure::Usage(format!("a template file is required"))); }
+    };
+    let text = match read_file(&path) {
+        Ok(text) => text,
+        Err(msg) => { return Err(Failure::Diagnostic(msg)); }
+    };
+    let mut scanner = BytesScanner::new(&text, &path);
+    let mut buf = String::new();
+    let mut error = None;
+    if ast {
+        let result = Parser::new(&mut scanner).parse_all();
+        match result {
+            Ok(list) if json => {
+                buf.push_str(&dump::list_json(&list, &scanner).to_string());
+                buf.push('\n');
+            }
+            Ok(list) => {
+                for node in dump::list_json(&list, &scanner).as_array().unwrap() {
+                    dump::tree_text(node, 0, &mut buf);
+                }
+            }
+            Err(err) => { error = Some(err); }
+        }
+    } else {
+        let mut tokens = vec![];
+        // 扫描出错时仍输出出错前的标记，便于定位
+        error = dump::tokens(&mut scanner, &mut tokens).err();
+        if json {
+            let list: Vec<Json> = tokens.iter().map(|tok| dump::token_json(tok, &scanner)).collect();
+            buf.push_str(&Json::Array(list).to_string());
+            buf.push('\n');
+        } else {
+            for tok in tokens.iter() {
+                buf.push_str(&dump::token_text(tok, &scanner));
+                buf.push('\n');
+            }
+        }
+    }
+    if let Err(err) = output.write_all(buf.as_bytes()).and_then(|_| output.flush()) {
+        return Err(Failure::Diagnostic(format!("error: {}", err)));
+    }
+    return match error {
+        Some(err) => Err(Failure::Diagnostic(diagnostic(&path, &text, &err))),
+        None => Ok(()),
+    };
+}
+
+/// 监视目录中模板的变化，`--once` 时只检查一次并按结果设置退出码。
+fn watch(args: &[String], input: &mut dyn Read, output: &mut dyn Write, errors: &mut dyn Write) -> CommandResult {
+    let mut dir = None;
+    let mut out_dir = None;
+    let mut data = None;
+    let mut interval = 500;
+    let mut once = false;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--render" | "--data" | "-d" | "--interval" if i + 1 >= args.len() => {
+                return Err(Failure::Usage(format!("{} requires a value", args[i])));
+            }
+            "--render" => {
+                out_dir = Some(PathBuf::from(&args[i + 1]));
+                i += 1;
+            }
+            "--data" | "-d" => {
+                data = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--interval" => {
+                interval = match args[i + 1].parse::<u64>() {
+                    Ok(ms) => ms,
+                    Err(_) => { return Err(Failure::Usage(format!("invalid interval {:?}", args[i + 1]))); }
+                };
+                i += 1;
+            }
+            "--once" => { once = true; }
+            arg if dir.is_none() && !arg.starts_with('-') => { dir = Some(PathBuf::from(arg)); }
+            arg => { return Err(Failure::Usage(format!("unexpected argument {:?}", arg))); }
+        }
+        i += 1;
+    }
+    let dir = match dir {
+        Some(dir) => dir,
+        None => { return Err(Failure::Usage(format!("watch requires a directory"))); }
+    };
+    let data = match data {
+        Some(data) => load_data(&data, input)?,
+        None => Json::Object(Default::default()),
+    };
+
+    let mut watcher = Watcher::new(&dir);
+    // 与监视器解析引入的方式一致，引入的模板名称相对监视的根目录
+    let engine = Engine::new(FileLoader::new(&dir));
+    let result = if once {
+        watcher.poll().map(|events| report(&watcher, &engine, &events, &out_dir, &data, output, errors))
+    } else {
+        let _ = writeln!(errors, "watching {} ...", dir.display());
+        watcher.watch(Duration::from_millis(interval), |watcher, events| {
+            report(watcher, &engine, events, &out_dir, &data, output, errors);
+            return true;
+        }).map(|_| true)
+    };
+    return match result {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Failure::Reported),
+        Err(err) => Err(Failure::Diagnostic(format!("{}: error: {}", dir.display(), err))),
+    };
+}
+
+/// 输出一次轮询的结果，指定了输出目录时渲染变化的模板，全部成功时返回 true。
+fn report(watcher: &Watcher, engine: &Engine, events: &[Event], out_dir: &Option<PathBuf>, data: &Json,
+          output: &mut dyn Write, errors: &mut dyn Write) -> bool {
+    let mut ok = true;
+    for event in events {
+        let path = event.path();
+        let name = path.strip_prefix(watcher.root()).unwrap_or(path);
+        let target = out_dir.as_ref().map(|out| out.join(name));
+        match event {
+            &Event::Removed(_) => {
+                if let Some(ref target) = target {
+                    let _ = fs::remove_file(target);
+                }
+                let _ = writeln!(output, "removed {}", path.display());
+            }
+            &Event::Failed(_, ref diagnostics) => {
+                for msg in diagnostics {
+                    let _ = writeln!(errors, "{}", msg);
+                }
+                ok = false;
+            }
+            &Event::Changed(_) => {
+                let target = match target {
+                    Some(target) => target,
+                    None => {
+                        let _ = writeln!(output, "ok {}", path.display());
+                        continue;
+                    }
+                };
+                let name = match name.to_str() {
+                    Some(name) => name,
+                    None => {
+                        let _ = writeln!(errors, "{}: error: invalid template path", path.display());
+                        ok = false;
+                        continue;
+                    }
+                };
+                let written = render_template(engine, name, path, data).and_then(|buf| {
+                    let created = match target.parent() {
+                        Some(parent) => fs::create_dir_all(parent),
+                        None => Ok(()),
+                    };
+                    return created.and_then(|_| fs::write(&target, buf))
+                        .map_err(|err| format!("{}: error: {}", target.display(), err));
+                });
+                match written {
+                    Ok(()) => { let _ = writeln!(output, "rendered {}", target.display()); }
+                    Err(msg) => {
+                        let _ = writeln!(errors, "{}", msg);
+                        ok = false;
+                    }
+                }
+            }
+        }
+    }
+    let _ = output.flush();
+    return ok;
+}
@@ -0,0 +1,458 @@
+//! 无损的具体语法树。
+//!
+//! 扫描器会丢弃注释、去除文本两端的空白，标记的值也不含引号、`<`、`</` 等界定符号，无法还原源码。
+//! 本模块在扫描结果的基础上补全这些信息：
+//! - 标记之间的空白和换行作为前导或尾随的琐碎内容（trivia）附着在标记上；
+//! - 注释以 `TokenKind::Comment`、`TokenKind::DomComment` 标记保留；
+//! - 其余未被扫描器输出的符号（如：`<`、`="`、`-->`、`{{%}}`）作为 `TokenKind::Punct` 标记；
+//! - 每个标记记录其在源中的精确位置。
+//!
+//! 按源码顺序输出所有标记及其琐碎内容即可逐字节地还原源码，`SyntaxNode` 的 `Display` 实现即是如此。
+
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use token::{Token, TokenKind};
+use scanner::{BytesScanner, Tokenizer};
+use runtime::is_void_element;
+use {Error, Result};
+
+/// 语法树分组节点的种类
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyntaxKind {
+    /// 整个模板
+    Root,
+    /// DOM 元素，由开始标签、子节点及结束标签组成，独立标签只含开始标签
+    Element,
+    /// 开始标签，如：<div id="a">
+    StartTag,
+    /// 结束标签，如：</div>
+    EndTag,
+    /// 标签属性，如：id="a"
+    Attribute,
+    /// 代码段，从 {{ 到 }}
+    Statement,
+    /// 由 if/for/with 开始、以对应的 /if、/for、/with 结束的代码块
+    Block,
+}
+
+/// 琐碎内容的种类
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind {
+    /// 空格及制表符
+    Whitespace,
+    /// 换行，\n 或 \r\n
+    Newline,
+}
+
+/// 附着在标记上的琐碎内容。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub offset: usize,
+    pub text: String,
+}
+
+/// 语法树中的标记。
+///
+/// 尾随的琐碎内容为标记之后同一行的空白及行尾的换行，其它的琐碎内容作为下一个标记的前导部分。
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken {
+    pub kind: TokenKind,
+    /// 标记在源中的位置
+    pub offset: usize,
+    /// 标记在源中的原文
+    pub text: String,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+impl SyntaxToken {
+    /// 标记原文在源中的范围，不含琐碎内容。
+    pub fn span(&self) -> Range<usize> {
+        self.offset..self.offset + self.text.len()
+    }
+
+    /// 包含琐碎内容在内的范围。
+    pub fn full_span(&self) -> Range<usize> {
+        let start = self.leading.first().map(|t| t.offset).unwrap_or(self.offset);
+        let end = self.trailing.last().map(|t| t.offset + t.text.len()).unwrap_or(self.offset + self.text.len());
+        return start..end;
+    }
+}
+
+/// 语法树节点，叶子为标记。
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxNode {
+    Token(SyntaxToken),
+    Node(SyntaxKind, Vec<SyntaxNode>),
+}
+
+impl SyntaxNode {
+    /// 分组节点的种类，标记返回 None。
+    pub fn kind(&self) -> Option<SyntaxKind> {
+        match self {
+            &SyntaxNode::Node(kind, _) => Some(kind),
+            &SyntaxNode::Token(_) => None,
+        }
+    }
+
+    /// 子节点，标记没有子节点。
+    pub fn children(&self) -> &[SyntaxNode] {
+        match self {
+            &SyntaxNode::Node(_, ref children) => children,
+            &SyntaxNode::Token(_) => &[],
+        }
+    }
+
+    /// 按源码顺序列出所有标记。
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut buf = vec![];
+        self.collect_tokens(&mut buf);
+        return buf;
+    }
+
+    fn collect_tokens<'a>(&'a self, buf: &mut Vec<&'a SyntaxToken>) {
+        match self {
+            &SyntaxNode::Token(ref tok) => buf.push(tok),
+            &SyntaxNode::Node(_, ref children) => {
+                for child in children {
+                    child.collect_tokens(buf);
+                }
+            }
+        }
+    }
+
+    /// 判断节点在语法上是否完整，可以脱离上下文独立解析：
+    /// 元素须为独立标签或以结束标签结束，代码块须以对应的结束语句结束，
+    /// 游离的结束标签以及 else、elif、/if 等代码段不完整。
+    pub fn is_complete(&self) -> bool {
+        match self {
+            &SyntaxNode::Node(SyntaxKind::Element, ref children) => {
+                if let Some(&SyntaxNode::Node(SyntaxKind::EndTag, _)) = children.last() {
+                    return true;
+                }
+                let tokens = children[0].tokens();
+                let name = tokens.iter().find(|t| t.kind == TokenKind::DomTagStart).map(|t| t.text.as_str()).unwrap_or("");
+                let self_closing = tokens.last().map(|t| t.kind == TokenKind::DomTagEnd && t.text.starts_with('/')).unwrap_or(false);
+                return children.len() == 1 && (self_closing || is_void_element(name));
+            }
+            &SyntaxNode::Node(SyntaxKind::Block, ref children) => {
+                match children.last() {
+                    Some(&SyntaxNode::Node(SyntaxKind::Statement, ref list)) if children.len() > 1 => {
+                        return statement_keyword(&leaves(list)).0;
+                    }
+                    _ => false,
+                }
+            }
+            &SyntaxNode::Node(SyntaxKind::EndTag, _) => false,
+            &SyntaxNode::Node(SyntaxKind::Statement, ref list) => {
+                let (is_end, keyword) = statement_keyword(&leaves(list));
+                return !is_end && keyword != "else" && keyword != "elif";
+            }
+            &SyntaxNode::Node(_, ref children) => children.iter().all(|n| n.is_complete()),
+            &SyntaxNode::Token(_) => true,
+        }
+    }
+
+    /// 不含首尾琐碎内容的范围。
+    pub fn span(&self) -> Range<usize> {
+        let tokens = self.tokens();
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => first.span().start..last.span().end,
+            _ => 0..0,
+        }
+    }
+
+    /// 包含琐碎内容在内的范围。
+    pub fn full_span(&self) -> Range<usize> {
+        let tokens = self.tokens();
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => first.full_span().start..last.full_span().end,
+            _ => 0..0,
+        }
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for tok in self.tokens() {
+            for trivia in &tok.leading {
+                f.write_str(&trivia.text)?;
+            }
+            f.write_str(&tok.text)?;
+            for trivia in &tok.trailing {
+                f.write_str(&trivia.text)?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// 扫描源码并构建无损的语法树，根节点的最后一个标记为 `TokenKind::EOF`，承载末尾的琐碎内容。
+pub fn parse(source: &str) -> Result<SyntaxNode> {
+    let mut scanner = BytesScanner::new(source, Path::new("source"));
+    scanner.set_keep_comments(true);
+    let mut scanned = vec![];
+    loop {
+        match scanner.scan() {
+            Ok(tok) => scanned.push(tok),
+            Err(Error::EOF) => { break; }
+            Err(err) => { return Err(err); }
+        }
+    }
+    let pieces = split(source, &scanned);
+    let tokens = attach_trivia(source, pieces);
+    return Ok(build(tokens));
+}
+
+/// 源码的一个片段，标记或琐碎内容。
+enum Piece {
+    Token(TokenKind, usize, usize),
+    Trivia(TriviaKind, usize, usize),
+}
+
+/// 计算标记在源中的精确位置，并将标记之间的内容切分为琐碎内容和界定符号。
+fn split(source: &str, scanned: &Vec<Token>) -> Vec<Piece> {
+    let mut pieces = vec![];
+    let mut cursor = 0usize;
+    for tok in scanned {
+        let value = tok.value_str();
+        let mut start = tok.offset();
+        if start < cursor {
+            // 辅助标记（如：无值属性的结束标记）不占用源码
+            pieces.push(Piece::Token(tok.kind().clone(), cursor, cursor));
+            continue;
+        }
+        if tok.kind() == &TokenKind::Data {
+            // 文本的值去除了两端的空白
+            while start < source.len() && is_space(source.as_bytes()[start]) {
+                start += 1;
+            }
+        }
+        let end = if source[start..].starts_with(value) { start + value.len() } else { start };
+        split_gap(source, cursor, start, &mut pieces);
+        pieces.push(Piece::Token(tok.kind().clone(), start, end));
+        cursor = end;
+    }
+    split_gap(source, cursor, source.len(), &mut pieces);
+    pieces.push(Piece::Token(TokenKind::EOF, source.len(), source.len()));
+    return pieces;
+}
+
+fn is_space(ch: u8) -> bool {
+    ch == b' ' || ch == b'\t' || ch == b'\r' || ch == b'\n'
+}
+
+/// 切分标记之间的内容：空白、换行及界定符号，界定符号在 <、{{ 之前及 > 之后断开。
+fn split_gap(source: &str, start: usize, end: usize, pieces: &mut Vec<Piece>) {
+    let bytes = source.as_bytes();
+    let mut i = start;
+    while i < end {
+        let begin = i;
+        if bytes[i] == b'\n' {
+            pieces.push(Piece::Trivia(TriviaKind::Newline, i, i + 1));
+            i += 1;
+        } else if bytes[i] == b'\r' && i + 1 < end && bytes[i + 1] == b'\n' {
+            pieces.push(Piece::Trivia(TriviaKind::Newline, i, i + 2));
+            i += 2;
+        } else if is_space(bytes[i]) {
+            while i < end && (bytes[i] == b' ' || bytes[i] == b'\t' || (bytes[i] == b'\r' && !(i + 1 < end && bytes[i + 1] == b'\n'))) {
+                i += 1;
+            }
+            pieces.push(Piece::Trivia(TriviaKind::Whitespace, begin, i));
+        } else {
+            i += 1;
+            while i < end && !is_space(bytes[i]) && bytes[i] != b'<' && bytes[i - 1] != b'>'
+                && !(bytes[i] == b'{' && i + 1 < end && bytes[i + 1] == b'{') {
+                i += 1;
+            }
+            pieces.push(Piece::Token(TokenKind::Punct, begin, i));
+        }
+    }
+}
+
+/// 将琐碎内容附着到标记上。
+fn attach_trivia(source: &str, pieces: Vec<Piece>) -> Vec<SyntaxToken> {
+    let mut tokens: Vec<SyntaxToken> = vec![];
+    let mut leading = vec![];
+    // 上一个标记所在的行是否已结束
+    let mut line_ended = true;
+    for piece in pieces {
+        match piece {
+            Piece::Trivia(kind, start, end) => {
+                let trivia = Trivia { kind: kind, offset: start, text: source[start..end].to_string() };
+                if line_ended {
+                    leading.push(trivia);
+                    continue;
+                }
+                if kind == TriviaKind::Newline {
+                    line_ended = true;
+                }
+                tokens.last_mut().unwrap().trailing.push(trivia);
+            }
+            Piece::Token(kind, start, end) => {
+                tokens.push(SyntaxToken {
+                    kind: kind,
+                    offset: start,
+                    text: source[start..end].to_string(),
+                    leading: leading.drain(..).collect(),
+                    trailing: vec![],
+                });
+                line_ended = false;
+            }
+        }
+    }
+    return tokens;
+}
+
+/// 正在构建的分组节点。
+struct Frame {
+    kind: SyntaxKind,
+    /// 元素的标签名或代码块的关键字
+    name: String,
+    children: Vec<SyntaxNode>,
+}
+
+impl Frame {
+    fn new(kind: SyntaxKind, name: &str) -> Frame {
+        Frame { kind: kind, name: name.to_string(), children: vec![] }
+    }
+}
+
+/// 关闭栈顶的分组节点并加入其父节点。
+fn close(stack: &mut Vec<Frame>) {
+    let frame = stack.pop().unwrap();
+    stack.last_mut().unwrap().children.push(SyntaxNode::Node(frame.kind, frame.children));
+}
+
+/// 关闭栈中与给定种类和名称匹配的分组节点，其间未闭合的节点一并关闭。
+/// 找不到匹配的节点时返回 false。
+fn close_matching(stack: &mut Vec<Frame>, kind: SyntaxKind, name: &str) -> bool {
+    match stack.iter().rposition(|f| f.kind == kind && f.name == name) {
+        Some(index) if index > 0 => {
+            while stack.len() > index + 1 {
+                close(stack);
+            }
+            return true;
+        }
+        _ => false,
+    }
+}
+
+/// 取出子节点中的标记。
+fn leaves(list: &[SyntaxNode]) -> Vec<SyntaxToken> {
+    list.iter().filter_map(|n| match n {
+        &SyntaxNode::Token(ref tok) => Some(tok.clone()),
+        _ => None,
+    }).collect()
+}
+
+/// 代码段中第一个有意义的标记，用于识别代码块的开始和结束，如：if、/if。
+fn statement_keyword(statement: &[SyntaxToken]) -> (bool, String) {
+    let mut iter = statement.iter().skip(1);
+    match iter.next() {
+        Some(tok) if tok.kind == TokenKind::Symbol && tok.text == "/" => {
+            (true, iter.next().map(|t| t.text.clone()).unwrap_or_default())
+        }
+        Some(tok) if tok.kind == TokenKind::Identifier => (false, tok.text.clone()),
+        _ => (false, String::new()),
+    }
+}
+
+fn group(kind: SyntaxKind, tokens: Vec<SyntaxToken>) -> SyntaxNode {
+    SyntaxNode::Node(kind, tokens.into_iter().map(SyntaxNode::Token).collect())
+}
+
+/// 由标记序列构建语法树。
+fn build(tokens: Vec<SyntaxToken>) -> SyntaxNode {
+    let mut stack = vec![Frame::new(SyntaxKind::Root, "")];
+    let mut iter = tokens.into_iter().peekable();
+    while let Some(tok) = iter.next() {
+        let starts_tag = match iter.peek() {
+            Some(next) => tok.kind == TokenKind::Punct && tok.text.starts_with('<')
+                && (next.kind == TokenKind::DomTagStart || next.kind == TokenKind::DomCTag),
+            None => false,
+        };
+        if tok.kind == TokenKind::LDelimiter {
+            let mut statement = vec![tok];
+            while let Some(tok) = iter.next() {
+                let end = tok.kind == TokenKind::RDelimiter;
+                statement.push(tok);
+                if end {
+                    break;
+                }
+            }
+            let (is_end, keyword) = statement_keyword(&statement);
+            let is_block = keyword == "if" || keyword == "for" || keyword == "with";
+            let node = group(SyntaxKind::Statement, statement);
+            if is_block && is_end && close_matching(&mut stack, SyntaxKind::Block, &keyword) {
+                stack.last_mut().unwrap().children.push(node);
+                close(&mut stack);
+            } else if is_block && !is_end {
+                stack.push(Frame::new(SyntaxKind::Block, &keyword));
+                stack.last_mut().unwrap().children.push(node);
+            } else {
+                stack.last_mut().unwrap().children.push(node);
+            }
+        } else if starts_tag && iter.peek().map(|t| t.kind == TokenKind::DomCTag).unwrap_or(false) {
+            // 结束标签：</ name >
+            let name = iter.peek().unwrap().text.clone();
+            let mut tag = vec![tok, iter.next().unwrap()];
+            while let Some(true) = iter.peek().map(|t| t.kind == TokenKind::Punct) {
+                let tok = iter.next().unwrap();
+                let end = tok.text.starts_with('>');
+                tag.push(tok);
+                if end {
+                    break;
+                }
+            }
+            let node = group(SyntaxKind::EndTag, tag);
+            if close_matching(&mut stack, SyntaxKind::Element, &name) {
+                stack.last_mut().unwrap().children.push(node);
+                close(&mut stack);
+            } else {
+                stack.last_mut().unwrap().children.push(node);
+            }
+        } else if starts_tag {
+            // 开始标签：< name attrs >
+            let name = iter.peek().unwrap().text.clone();
+            let mut tag = vec![SyntaxNode::Token(tok), SyntaxNode::Token(iter.next().unwrap())];
+            let mut self_closing = false;
+            while let Some(tok) = iter.next() {
+                match tok.kind {
+                    TokenKind::DomAttrStart => {
+                        let mut attr = vec![tok];
+                        while let Some(tok) = iter.next() {
+                            let end = tok.kind == TokenKind::DomAttrEnd;
+                            attr.push(tok);
+                            if end {
+                                break;
+                            }
+                        }
+                        tag.push(group(SyntaxKind::Attribute, attr));
+                    }
+                    TokenKind::DomTagEnd => {
+                        self_closing = tok.text.starts_with('/');
+                        tag.push(SyntaxNode::Token(tok));
+                        break;
+                    }
+                    _ => tag.push(SyntaxNode::Token(tok)),
+                }
+            }
+            let mut element = Frame::new(SyntaxKind::Element, &name);
+            element.children.push(SyntaxNode::Node(SyntaxKind::StartTag, tag));
+            stack.push(element);
+            if self_closing || is_void_element(&name) {
+                close(&mut stack);
+            }
+        } else {
+            stack.last_mut().unwrap().children.push(SyntaxNode::Token(tok));
+        }
+    }
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+    let root = stack.pop().unwrap();
+    return SyntaxNode::Node(root.kind, root.children);
+}
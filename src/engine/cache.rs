@@ -0,0 +1,93 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// 按名称存储值的有界缓存，超出容量时淘汰最久未使用的值。
+#[derive(Debug)]
+pub struct LruCache<T> {
+    capacity: usize,
+    /// 每次访问递增，记录值最近一次被使用的时刻
+    clock: u64,
+    entries: HashMap<String, (T, u64)>,
+    /// 按最近使用的时刻排序的名称，第一项最久未使用
+    order: BTreeMap<u64, String>,
+}
+
+impl<T> LruCache<T> {
+    /// 创建缓存，容量为 0 时不缓存任何值。
+    pub fn new(capacity: usize) -> LruCache<T> {
+        LruCache { capacity: capacity, clock: 0, entries: HashMap::new(), order: BTreeMap::new() }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 修改容量，缩小时立即淘汰多出的值。
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 获取值并将其标记为最近使用。
+    pub fn get(&mut self, name: &str) -> Option<&T> {
+        self.clock += 1;
+        let clock = self.clock;
+        return match self.entries.get_mut(name) {
+            Some(entry) => {
+                if let Some(name) = self.order.remove(&entry.1) {
+                    self.order.insert(clock, name);
+                }
+                entry.1 = clock;
+                Some(&entry.0)
+            }
+            None => None,
+        };
+    }
+
+    /// 获取值，不影响淘汰顺序。
+    pub fn peek(&self, name: &str) -> Option<&T> {
+        self.entries.get(name).map(|entry| &entry.0)
+    }
+
+    /// 添加或替换值，超出容量时淘汰最久未使用的值。
+    pub fn insert(&mut self, name: &str, value: T) {
+        self.clock += 1;
+        if let Some((_, used)) = self.entries.insert(name.to_string(), (value, self.clock)) {
+            self.order.remove(&used);
+        }
+        self.order.insert(self.clock, name.to_string());
+        self.evict();
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<T> {
+        let (value, used) = self.entries.remove(name)?;
+        self.order.remove(&used);
+        return Some(value);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    /// 缓存中的名称，最近使用的在前。
+    pub fn names(&self) -> Vec<String> {
+        return self.order.values().rev().cloned().collect();
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            match self.order.pop_first() {
+                Some((_, oldest)) => { self.entries.remove(&oldest); }
+                None => { return; }
+            }
+        }
+    }
+}
@@ -0,0 +1,452 @@
+//! 模板引擎：持有解析和渲染的配置、模板加载器、注册的函数以及已编译模板的缓存，
+//! 是按名称加载和渲染模板的统一入口。
+//!
+//! 缓存中的模板记录了它自身及所有直接或间接引入的模板的版本，获取模板时若其中任何一个的版本
+//! 发生了变化（包括新增和删除），模板会被重新加载和编译。缓存有容量限制，超出时淘汰最久未使用的模板。
+//!
+//! 引擎和它返回的模板都是 `Send + Sync` 的：多个线程可以共享同一个引擎获取模板，
+//! 也可以共享同一个模板并各自以不同的上下文同时渲染。
+//!
+//! ```ignore
+//! let mut engine = Engine::new(FileLoader::new("templates"));
+//! engine.register_function("upper", |_, args| Ok(Value::String(format!("{}", args[0]).to_uppercase())));
+//! let html = engine.get_template("index.html")?.render(&context)?;
+//! ```
+
+mod cache;
+
+pub use self::cache::LruCache;
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::UNIX_EPOCH;
+use analysis;
+use ast::{NodeList, VisitResult};
+use optimizer::{optimize_with_escape, prerender};
+use parser::Parser;
+use runtime::{Context, Function, Includer, Interpreter, Invoker, Sink, Undefined, Value};
+use scanner::{BytesScanner, Source};
+use {Error, NoneResult, Result};
+
+/// 按名称读取模板源码，加载器可能被多个线程同时调用。
+pub trait Loader: Send + Sync {
+    /// 读取名为 name 的模板，找不到时返回 `Error::Message`。
+    fn load(&self, name: &str) -> Result<String>;
+
+    /// 获取模板的版本，内容变化时版本随之变化，用于判断缓存的模板是否过期。
+    ///
+    /// 默认为模板内容的散列值，能以更低代价判断变化的加载器（如按修改时间）应重写该方法。
+    fn version(&self, name: &str) -> Result<u64> {
+        let text = self.load(name)?;
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        return Ok(hasher.finish());
+    }
+}
+
+/// 从目录中加载模板，名称为相对该目录的路径，不允许绝对路径和 `..`。
+#[derive(Debug)]
+pub struct FileLoader {
+    root: PathBuf,
+}
+
+impl FileLoader {
+    pub fn new<P: AsRef<Path>>(root: P) -> FileLoader {
+        FileLoader { root: root.as_ref().to_path_buf() }
+    }
+
+    /// 模板名称对应的文件路径，名称不合法时返回错误。
+    pub fn path(&self, name: &str) -> Result<PathBuf> {
+        let path = Path::new(name);
+        if path.components().any(|c| match c { Component::Normal(_) | Component::CurDir => false, _ => true }) {
+            return Err(Error::Message(format!("invalid template name {:?}", name)));
+        }
+        return Ok(self.root.join(path));
+    }
+}
+
+impl Loader for FileLoader {
+    fn load(&self, name: &str) -> Result<String> {
+        let path = self.path(name)?;
+        return fs::read_to_string(path).map_err(|e| Error::Message(format!("cannot load template {:?}: {}", name, e)));
+    }
+
+    /// 以文件的修改时间和长度作为版本。
+    fn version(&self, name: &str) -> Result<u64> {
+        let path = self.path(name)?;
+        let metadata = fs::metadata(path).map_err(|e| Error::Message(format!("cannot load template {:?}: {}", name, e)))?;
+        let mut hasher = DefaultHasher::new();
+        metadata.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).hash(&mut hasher);
+        metadata.len().hash(&mut hasher);
+        return Ok(hasher.finish());
+    }
+}
+
+/// 从内存中加载模板，主要用于测试和内嵌的模板。
+#[derive(Debug, Default)]
+pub struct MemoryLoader {
+    /// 模板内容及添加时的版本
+    templates: HashMap<String, (String, u64)>,
+    /// 每次添加或替换模板时递增
+    counter: u64,
+}
+
+impl MemoryLoader {
+    pub fn new() -> MemoryLoader {
+        MemoryLoader { templates: HashMap::new(), counter: 0 }
+    }
+
+    /// 添加或替换一个模板。
+    pub fn insert(&mut self, name: &str, text: &str) {
+        self.counter += 1;
+        self.templates.insert(name.to_string(), (text.to_string(), self.counter));
+    }
+
+    fn get(&self, name: &str) -> Result<&(String, u64)> {
+        return match self.templates.get(name) {
+            Some(entry) => Ok(entry),
+            None => Err(Error::Message(format!("template {:?} not found", name))),
+        };
+    }
+}
+
+impl Loader for MemoryLoader {
+    fn load(&self, name: &str) -> Result<String> {
+        return self.get(name).map(|entry| entry.0.clone());
+    }
+
+    /// 以模板被添加或替换时的计数作为版本，无需散列内容。
+    fn version(&self, name: &str) -> Result<u64> {
+        return self.get(name).map(|entry| entry.1);
+    }
+}
+
+/// 已编译的模板：源码、语法树及编译时依赖的模板版本。
+#[derive(Debug)]
+struct Compiled {
+    name: String,
+    text: String,
+    list: NodeList,
+    /// 直接引入的模板名称
+    includes: Vec<String>,
+    /// 模板自身（第一项）及所有直接或间接引入的模板的版本，不存在的模板版本为 None
+    versions: Vec<(String, Option<u64>)>,
+}
+
+impl Compiled {
+    /// 将以偏移定位的错误转换为带有模板名称、行号和列号的错误。
+    fn locate(&self, error: Error) -> Error {
+        let (msg, offset) = match error {
+            Error::Scan(msg, offset) | Error::Parse(msg, offset) | Error::Visit(msg, offset) => (msg, offset),
+            error => { return error; }
+        };
+        let source = BytesScanner::new(&self.text, Path::new(&self.name));
+        return Error::RefMessage(msg, source.line(offset), source.column(offset), self.name.clone());
+    }
+}
+
+/// 渲染时的配置，由引擎和它返回的模板共享。
+#[derive(Debug, Clone)]
+struct Settings {
+    escape: bool,
+    undefined: Undefined,
+    functions: Vec<(String, Value)>,
+}
+
+/// 模板引擎。
+///
+/// 解析或渲染中的错误以 `Error::RefMessage` 返回，带有出错的模板名称和位置。
+pub struct Engine {
+    delimiters: (String, String),
+    xhtml: bool,
+    optimize: bool,
+    settings: Arc<Settings>,
+    loader: Box<dyn Loader>,
+    cache: Mutex<LruCache<Arc<Compiled>>>,
+    auto_reload: bool,
+}
+
+/// 默认缓存的模板数量。
+const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+impl Engine {
+    pub fn new<L: Loader + 'static>(loader: L) -> Engine {
+        Engine {
+            delimiters: ("{{".to_string(), "}}".to_string()),
+            xhtml: true,
+            optimize: true,
+            settings: Arc::new(Settings { escape: true, undefined: Undefined::Empty, functions: vec![] }),
+            loader: Box::new(loader),
+            cache: Mutex::new(LruCache::new(DEFAULT_CACHE_CAPACITY)),
+            auto_reload: true,
+        }
+    }
+
+    /// 设置语句的开始和结束定界符，默认为 `{{` 和 `}}`。
+    pub fn set_delimiters(&mut self, start: &str, end: &str) {
+        assert!(!start.is_empty() && !end.is_empty(), "empty delimiter");
+        self.delimiters = (start.to_string(), end.to_string());
+        self.clear_cache();
+    }
+
+    /// 设置是否解析 HTML 标签，默认解析。
+    pub fn set_parse_xhtml(&mut self, xhtml: bool) {
+        self.xhtml = xhtml;
+        self.clear_cache();
+    }
+
+    /// 设置是否在编译时优化语法树，默认优化。
+    ///
+    /// 优化折叠常量的运算和输出、移除不可达的分支，并将 HTML 标签预先渲染为字面量。
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+        self.clear_cache();
+    }
+
+    /// 设置是否对输出进行 HTML 转义，默认转义。已获取的模板不受影响。
+    pub fn set_escape(&mut self, escape: bool) {
+        Arc::make_mut(&mut self.settings).escape = escape;
+        // 优化时折叠的输出已按原来的设置转义
+        if self.optimize {
+            self.clear_cache();
+        }
+    }
+
+    /// 设置未定义的变量的处理方式，默认视为 null。已获取的模板不受影响。
+    pub fn set_undefined(&mut self, undefined: Undefined) {
+        Arc::make_mut(&mut self.settings).undefined = undefined;
+    }
+
+    /// 注册一个所有模板可用的宿主函数，渲染时上下文中的同名变量优先。
+    ///
+    /// 模板语言没有过滤器语法，过滤器也以函数的形式注册和调用，如：`{{upper(name)}}`。
+    pub fn register_function<F>(&mut self, name: &str, f: F)
+        where F: Fn(&mut dyn Invoker, Vec<Value>) -> Result<Value> + Send + Sync + 'static {
+        let settings = Arc::make_mut(&mut self.settings);
+        settings.functions.retain(|&(ref n, _)| n != name);
+        settings.functions.push((name.to_string(), Function::host(name, f)));
+    }
+
+    /// 设置最多缓存的模板数量，为 0 时不缓存。
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.cache().set_capacity(capacity);
+    }
+
+    /// 设置获取缓存的模板时是否检查模板及其依赖的版本，默认检查。
+    ///
+    /// 模板不会变化的部署环境中可以关闭，以省去每次获取时的版本查询。
+    pub fn set_auto_reload(&mut self, auto_reload: bool) {
+        self.auto_reload = auto_reload;
+    }
+
+    /// 缓存中的模板名称，最近使用的在前。
+    pub fn cached_templates(&self) -> Vec<String> {
+        self.cache().names()
+    }
+
+    /// 清除已编译模板的缓存，下次获取时重新加载。
+    pub fn clear_cache(&self) {
+        self.cache().clear();
+    }
+
+    /// 获取模板，首次获取时加载并编译，之后使用缓存。
+    ///
+    /// 模板直接或间接引入的模板在获取时一并编译，返回的模板不再依赖引擎，
+    /// 之后对模板源码或引擎配置的修改只影响再次获取的模板。
+    pub fn get_template(&self, name: &str) -> Result<Template> {
+        let compiled = self.compile(name)?;
+        let mut includes = HashMap::new();
+        let mut pending = compiled.includes.clone();
+        while let Some(include) = pending.pop() {
+            if include == name || includes.contains_key(&include) {
+                continue;
+            }
+            // 引入的模板无法加载或编译时，错误在渲染到引入处时才产生
+            let result = self.compile(&include);
+            if let Ok(ref dep) = result {
+                pending.extend(dep.includes.iter().cloned());
+            }
+            includes.insert(include, result);
+        }
+        return Ok(Template { compiled: compiled, includes: Arc::new(includes), settings: self.settings.clone() });
+    }
+
+    /// 锁定缓存，持有锁的线程出错退出后缓存仍然可用。
+    fn cache(&self) -> MutexGuard<'_, LruCache<Arc<Compiled>>> {
+        return self.cache.lock().unwrap_or_else(|e| e.into_inner());
+    }
+
+    /// 获取编译的模板，缓存中的模板过期时重新编译。
+    fn compile(&self, name: &str) -> Result<Arc<Compiled>> {
+        let cached = self.cache().get(name).cloned();
+        if let Some(compiled) = cached {
+            if !self.auto_reload || self.is_fresh(&compiled) {
+                return Ok(compiled);
+            }
+            self.cache().remove(name);
+        }
+        let mut compiled = self.parse(name)?;
+        compiled.versions = self.dependencies(&compiled);
+        let compiled = Arc::new(compiled);
+        self.cache().insert(name, compiled.clone());
+        return Ok(compiled);
+    }
+
+    /// 加载并解析模板，先于加载获取版本，以免加载后的修改被遗漏。
+    ///
+    /// 引入的模板在优化之前收集，不可达分支中的引入仍被视为依赖。
+    fn parse(&self, name: &str) -> Result<Compiled> {
+        let version = self.loader.version(name).ok();
+        let text = self.loader.load(name)?;
+        let list = {
+            let path = Path::new(name);
+            let mut scanner = BytesScanner::new(&text, path);
+            scanner.set_delimiters(&self.delimiters.0, &self.delimiters.1);
+            scanner.set_parse_xhtml(self.xhtml);
+            Parser::new(&mut scanner).parse_all()
+        };
+        let mut compiled = Compiled { name: name.to_string(), text: text, list: vec![], includes: vec![], versions: vec![(name.to_string(), version)] };
+        match list {
+            Ok(list) => {
+                compiled.includes = analysis::analyze(&list).includes;
+                compiled.list = if self.optimize { prerender(optimize_with_escape(list, self.settings.escape)) } else { list };
+            }
+            Err(err) => { return Err(compiled.locate(err)); }
+        }
+        return Ok(compiled);
+    }
+
+    /// 收集模板及其直接或间接引入的模板的当前版本。
+    ///
+    /// 版本未变的已缓存模板直接使用其引入关系，其它的重新解析并放入缓存，之后获取它们时不必再次解析；
+    /// 无法加载或解析的模板不再向下查找。
+    fn dependencies(&self, compiled: &Compiled) -> Vec<(String, Option<u64>)> {
+        let mut graph = HashMap::new();
+        graph.insert(compiled.name.clone(), (compiled.versions[0].1, compiled.includes.clone()));
+        let mut parsed = vec![];
+        let mut pending = compiled.includes.clone();
+        while let Some(name) = pending.pop() {
+            if graph.contains_key(&name) {
+                continue;
+            }
+            let version = self.loader.version(&name).ok();
+            let cached = self.cache().peek(&name).cloned();
+            let (version, includes) = match cached {
+                Some(ref dep) if version.is_some() && dep.versions[0].1 == version => (version, dep.includes.clone()),
+                _ => match self.parse(&name) {
+                    Ok(dep) => {
+                        let entry = (dep.versions[0].1, dep.includes.clone());
+                        parsed.push(dep);
+                        entry
+                    }
+                    Err(_) => (version, vec![]),
+                },
+            };
+            pending.extend(includes.iter().cloned());
+            graph.insert(name, (version, includes));
+        }
+        for mut dep in parsed {
+            dep.versions = closure(&graph, &dep.name);
+            let name = dep.name.clone();
+            self.cache().insert(&name, Arc::new(dep));
+        }
+        return closure(&graph, &compiled.name);
+    }
+
+    /// 判断模板及其依赖的版本是否都未变化。
+    fn is_fresh(&self, compiled: &Compiled) -> bool {
+        compiled.versions.iter().all(|&(ref name, version)| self.loader.version(name).ok() == version)
+    }
+}
+
+/// 从模板的引入关系中收集模板自身（第一项）及所有直接或间接引入的模板的版本。
+fn closure(graph: &HashMap<String, (Option<u64>, Vec<String>)>, name: &str) -> Vec<(String, Option<u64>)> {
+    let mut versions: Vec<(String, Option<u64>)> = vec![];
+    let mut pending = vec![name.to_string()];
+    while let Some(name) = pending.pop() {
+        if versions.iter().any(|&(ref n, _)| n == &name) {
+            continue;
+        }
+        if let Some(&(version, ref includes)) = graph.get(&name) {
+            pending.extend(includes.iter().rev().cloned());
+            versions.push((name, version));
+        }
+    }
+    return versions;
+}
+
+/// 由引擎编译的模板，包含它直接或间接引入的模板。
+///
+/// 模板可以被克隆或在线程间共享，每次渲染使用各自的上下文。
+#[derive(Debug, Clone)]
+pub struct Template {
+    compiled: Arc<Compiled>,
+    includes: Arc<HashMap<String, Result<Arc<Compiled>>>>,
+    settings: Arc<Settings>,
+}
+
+impl Template {
+    pub fn name(&self) -> &str {
+        &self.compiled.name
+    }
+
+    /// 获取模板的语法树，引擎开启优化时为优化后的语法树。
+    pub fn nodes(&self) -> &NodeList {
+        &self.compiled.list
+    }
+
+    /// 使用给定的上下文渲染模板为字符串，上下文不会被修改。
+    pub fn render(&self, context: &Context) -> Result<String> {
+        let mut buf = vec![];
+        self.render_to(context, &mut buf)?;
+        return String::from_utf8(buf).map_err(|e| Error::Message(format!("{}", e)));
+    }
+
+    /// 使用给定的上下文渲染模板到输出。
+    pub fn render_to(&self, context: &Context, output: &mut dyn Write) -> NoneResult {
+        self.stream(context, Sink::new(output))
+    }
+
+    /// 使用给定的上下文渲染模板到设置了缓冲大小和刷新点的输出，内容在渲染过程中逐步写出。
+    ///
+    /// 写出失败时返回的错误定位到正在渲染的节点：不缓冲时即写出失败的内容所属的节点；
+    /// 缓冲时为使缓冲的内容达到容量或到达刷新点的节点，失败的内容中可能包含之前的节点缓冲的部分。
+    ///
+    /// 每次渲染使用一个新的局部上下文，模板中声明的变量和注册的函数都位于其中，渲染结束后丢弃；
+    /// 局部上下文中找不到的变量到给定的上下文中查找，上下文中已有的同名变量不被注册的函数遮蔽。
+    pub fn stream(&self, context: &Context, output: Sink) -> NoneResult {
+        let settings = &self.settings;
+        let mut locals = Context::new();
+        for &(ref name, ref value) in settings.functions.iter() {
+            if context.get(name).is_none() {
+                locals.set(name, value.clone());
+            }
+        }
+        let mut interpreter = Interpreter::with_sink(&mut locals, output);
+        interpreter.set_parent(context);
+        interpreter.set_undefined(settings.undefined);
+        interpreter.set_escape(settings.escape);
+        interpreter.set_includer(self);
+        return interpreter.render(&self.compiled.list).map_err(|e| self.compiled.locate(e));
+    }
+}
+
+impl Includer for Template {
+    fn include(&self, name: &str, interpreter: &mut Interpreter) -> VisitResult {
+        let compiled = if name == self.compiled.name {
+            self.compiled.clone()
+        } else {
+            match self.includes.get(name) {
+                Some(&Ok(ref compiled)) => compiled.clone(),
+                Some(&Err(ref err)) => { return Err(err.clone()); }
+                None => { return Err(Error::Message(format!("template {:?} not found", name))); }
+            }
+        };
+        return interpreter.render(&compiled.list).map_err(|e| compiled.locate(e));
+    }
+}
@@ -0,0 +1,547 @@
+//! 模板源码的格式化。
+//!
+//! 经 `BytesScanner` 和 `Parser` 解析后按统一的风格重新输出：
+//! - `{{ }}` 内的表达式按运算符优先级重新生成，运算符两侧各留一个空格，仅保留必要的括号；
+//! - 嵌套的 DOM 标签逐级缩进，内容能在行宽内放下时与标签输出在同一行；
+//! - 属性可按源码顺序或名称排序，`@if`、`@for` 等扩展指令保持为属性形式并排在最前；
+//! - 模板注释、DOM 注释及 `{{%}}...{{%}}` 字面输出段原样保留。
+//!
+//! 扫描器会去除文本两端的空白，因此格式化只改变空白的排布，不改变渲染结果。
+
+use std::path::Path;
+use ast::{Node, NodeList, DomAttr, Operator, Constant};
+use token::{Token, TokenKind};
+use scanner::BytesScanner;
+use parser::Parser;
+use runtime::is_void_element;
+use Result;
+
+/// DOM 属性的排列方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttributeOrder {
+    /// 保持源码中的顺序
+    Source,
+    /// 按属性名称排序
+    Alphabetical,
+}
+
+/// 格式化选项
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// 每一级缩进所用的字符串
+    pub indent: String,
+    /// 行宽，标签及其内容能在该宽度内放下时输出在同一行
+    pub line_width: usize,
+    /// 属性的排列方式
+    pub attribute_order: AttributeOrder,
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions {
+            indent: "    ".to_string(),
+            line_width: 100,
+            attribute_order: AttributeOrder::Source,
+        }
+    }
+}
+
+/// 格式化模板源码，源码无法解析时返回解析错误。
+pub fn format(source: &str, options: &FormatOptions) -> Result<String> {
+    let mut scanner = BytesScanner::new(source, Path::new("source"));
+    scanner.set_keep_comments(true);
+    let list = Parser::new(&mut scanner).parse_all()?;
+    let mut formatter = Formatter { options: options, output: String::new(), depth: 0 };
+    formatter.block(&list);
+    return Ok(formatter.output);
+}
+
+/// 运算符的源码形式及优先级，与解析器的优先级表一致。
+fn operator(op: &Operator) -> (&'static str, usize) {
+    match op {
+        &Operator::NullCond => ("??", 1),
+        &Operator::Or => ("||", 2),
+        &Operator::And => ("&&", 3),
+        &Operator::Eq => ("==", 4),
+        &Operator::NotEq => ("!=", 4),
+        &Operator::Lt => ("<", 5),
+        &Operator::Lte => ("<=", 5),
+        &Operator::Gt => (">", 5),
+        &Operator::Gte => (">=", 5),
+        &Operator::In => ("in", 5),
+        &Operator::NotIn => ("not in", 5),
+        &Operator::Add => ("+", 7),
+        &Operator::Sub => ("-", 7),
+        &Operator::Mul => ("*", 8),
+        &Operator::Div => ("/", 8),
+        &Operator::Mod => ("%", 8),
+        &Operator::Neg => ("-", 9),
+        &Operator::Pos => ("+", 9),
+        &Operator::Not => ("!", 9),
+        &Operator::Pow => ("**", 10),
+        &Operator::TestCond => ("?", 0),
+    }
+}
+
+/// 表达式节点的优先级，数值越大结合越紧密。
+fn precedence(node: &Node) -> usize {
+    match node {
+        &Node::Ternary(..) | &Node::Lambda(..) => 0,
+        &Node::Binary(_, _, ref op) | &Node::Unary(_, ref op) => operator(op).1,
+        &Node::Range(..) => 6,
+        &Node::Property(..) | &Node::Method(..) | &Node::OptionalProperty(..) |
+        &Node::OptionalMethod(..) | &Node::Slice(..) => 11,
+        _ => 12,
+    }
+}
+
+/// 为字符串常量加上引号，原文中含有未转义的单引号时使用双引号。
+fn quote(raw: &str) -> String {
+    let mut escaped = false;
+    let mut has_apostrophe = false;
+    for ch in raw.chars() {
+        if escaped {
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else if ch == '\'' {
+            has_apostrophe = true;
+        }
+    }
+    if has_apostrophe {
+        return format!("\"{}\"", raw);
+    }
+    return format!("'{}'", raw);
+}
+
+fn is_empty(node: &Node) -> bool {
+    match node {
+        &Node::Empty => true,
+        _ => false,
+    }
+}
+
+/// 判断是否为控制块，标签内容中的控制块总是分行输出。
+fn is_block(node: &Node) -> bool {
+    match node {
+        &Node::If(..) | &Node::For(..) | &Node::With(..) => true,
+        &Node::Statement(ref list) | &Node::List(ref list) => list.iter().any(is_block),
+        &Node::DomTag(_, _, ref children) => children.iter().any(is_block),
+        _ => false,
+    }
+}
+
+/// 以 `@if`、`@for` 扩展指令产生的节点，其表达式的位置在标签之内。
+fn directive_tag<'n>(body: &'n NodeList, offset: Option<usize>) -> Option<&'n Node> {
+    if body.len() != 1 {
+        return None;
+    }
+    match (&body[0], offset) {
+        (&Node::DomTag(ref name, _, _), Some(offset)) if offset > name.offset() => Some(&body[0]),
+        _ => None,
+    }
+}
+
+struct Formatter<'a> {
+    options: &'a FormatOptions,
+    output: String,
+    depth: usize,
+}
+
+impl<'a> Formatter<'a> {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.output.push_str(&self.options.indent);
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    fn nested(&mut self, list: &NodeList) {
+        self.depth += 1;
+        self.block(list);
+        self.depth -= 1;
+    }
+
+    fn block(&mut self, list: &NodeList) {
+        for node in list {
+            self.node(node);
+        }
+    }
+
+    fn node(&mut self, node: &Node) {
+        match node {
+            &Node::Empty => {}
+            &Node::Root(ref list) | &Node::List(ref list) | &Node::Statement(ref list) => self.block(list),
+            &Node::DomTag(ref name, ref attrs, ref children) => self.dom_tag(name, attrs, children, None),
+            &Node::If(ref condition, ref body, ref branches, _) => {
+                if let Some(tag) = directive_tag(body, condition.offset()) {
+                    let directive = self.directive("@if", &self.expr(condition));
+                    self.directive_tag(tag, directive);
+                    self.directive_branches(branches);
+                    return;
+                }
+                let head = format!("{{{{if {}}}}}", self.expr(condition));
+                self.line(&head);
+                self.nested(body);
+                self.branches(branches);
+                self.line("{{/if}}");
+            }
+            &Node::For(ref key, ref value, ref iter, ref body, ref for_else) => {
+                let head = self.for_head(key, value, iter);
+                if let Some(tag) = directive_tag(body, Some(key.offset())) {
+                    let directive = self.directive("@for", &head);
+                    self.directive_tag(tag, directive);
+                    if let &Node::Else(ref list) = &**for_else {
+                        self.directive_else(list);
+                    }
+                    return;
+                }
+                self.line(&format!("{{{{for {}}}}}", head));
+                self.nested(body);
+                self.else_block(for_else);
+                self.line("{{/for}}");
+            }
+            &Node::With(ref expr, ref alias, ref body, ref with_else) => {
+                let head = format!("{{{{with {} as {}}}}}", self.expr(expr), alias.value_str());
+                self.line(&head);
+                self.nested(body);
+                self.else_block(with_else);
+                self.line("{{/with}}");
+            }
+            node => {
+                let text = self.inline(node).unwrap_or_default();
+                self.line(&text);
+            }
+        }
+    }
+
+    fn branches(&mut self, branches: &NodeList) {
+        for branch in branches {
+            match branch {
+                &Node::If(ref condition, ref body, ref rest, _) => {
+                    let head = format!("{{{{elif {}}}}}", self.expr(condition));
+                    self.line(&head);
+                    self.nested(body);
+                    self.branches(rest);
+                }
+                node => self.else_block(node),
+            }
+        }
+    }
+
+    fn else_block(&mut self, node: &Node) {
+        if let &Node::Else(ref list) = node {
+            self.line("{{else}}");
+            self.nested(list);
+        }
+    }
+
+    /// 输出以 `@elif`、`@else` 扩展指令形式书写的分支。
+    fn directive_branches(&mut self, branches: &NodeList) {
+        for branch in branches {
+            match branch {
+                &Node::If(ref condition, ref body, ref rest, _) => {
+                    match body.first() {
+                        Some(tag) if body.len() == 1 => {
+                            let directive = self.directive("@elif", &self.expr(condition));
+                            self.directive_tag(tag, directive);
+                        }
+                        _ => self.block(body),
+                    }
+                    self.directive_branches(rest);
+                }
+                &Node::Else(ref list) => self.directive_else(list),
+                _ => {}
+            }
+        }
+    }
+
+    fn directive_else(&mut self, list: &NodeList) {
+        match list.first() {
+            Some(tag) if list.len() == 1 => self.directive_tag(tag, "@else".to_string()),
+            _ => self.block(list),
+        }
+    }
+
+    fn directive_tag(&mut self, tag: &Node, directive: String) {
+        if let &Node::DomTag(ref name, ref attrs, ref children) = tag {
+            self.dom_tag(name, attrs, children, Some(directive));
+        }
+    }
+
+    /// 生成扩展指令属性，值中含有双引号时使用单引号。
+    fn directive(&self, name: &str, value: &str) -> String {
+        if value.contains('"') {
+            return format!("{}='{}'", name, value);
+        }
+        return format!("{}=\"{}\"", name, value);
+    }
+
+    fn for_head(&self, key: &Token, value: &Token, iter: &Node) -> String {
+        if value.kind() == &TokenKind::Ignore {
+            return format!("{} : {}", key.value_str(), self.expr(iter));
+        }
+        return format!("{}, {} : {}", key.value_str(), value.value_str(), self.expr(iter));
+    }
+
+    /// 生成标签的开始部分，不含结尾的 `>` 或 `/>`。
+    fn open_tag(&self, name: &Token, attrs: &Vec<DomAttr>, directive: Option<String>) -> String {
+        let mut list: Vec<&DomAttr> = attrs.iter().collect();
+        if self.options.attribute_order == AttributeOrder::Alphabetical {
+            list.sort_by(|a, b| a.name.value_str().cmp(b.name.value_str()));
+        }
+        let mut open = format!("<{}", name.value_str());
+        if let Some(directive) = directive {
+            open.push(' ');
+            open.push_str(&directive);
+        }
+        for attr in list {
+            open.push(' ');
+            open.push_str(attr.name.value_str());
+            if attr.value.is_empty() {
+                continue;
+            }
+            let value = self.inline_list(&attr.value, "").unwrap_or_default();
+            if value.contains('"') {
+                open.push_str(&format!("='{}'", value));
+            } else {
+                open.push_str(&format!("=\"{}\"", value));
+            }
+        }
+        return open;
+    }
+
+    fn dom_tag(&mut self, name: &Token, attrs: &Vec<DomAttr>, children: &NodeList, directive: Option<String>) {
+        let open = self.open_tag(name, attrs, directive);
+        if children.is_empty() {
+            if is_void_element(name.value_str()) {
+                self.line(&format!("{}/>", open));
+            } else {
+                self.line(&format!("{}></{}>", open, name.value_str()));
+            }
+            return;
+        }
+        let close = format!("</{}>", name.value_str());
+        if let (false, Some(content)) = (children.iter().any(is_block), self.inline_list(children, " ")) {
+            let width = self.depth * self.options.indent.len() + open.len() + content.len() + close.len() + 1;
+            if !content.contains('\n') && width <= self.options.line_width {
+                self.line(&format!("{}>{}{}", open, content, close));
+                return;
+            }
+        }
+        self.line(&format!("{}>", open));
+        self.nested(children);
+        self.line(&close);
+    }
+
+    /// 将节点集合输出为一行，含有不能内联的节点时返回 None。
+    fn inline_list(&self, list: &NodeList, separator: &str) -> Option<String> {
+        let mut parts = vec![];
+        for node in list {
+            match node {
+                &Node::Empty => {}
+                node => parts.push(self.inline(node)?),
+            }
+        }
+        return Some(parts.join(separator));
+    }
+
+    fn inline(&self, node: &Node) -> Option<String> {
+        match node {
+            &Node::Literal(ref tok) if tok.kind() == &TokenKind::Literal => {
+                Some(format!("{{{{%}}}}{}{{{{%}}}}", tok.value_str()))
+            }
+            &Node::Literal(ref tok) => Some(tok.value_str().to_string()),
+            &Node::Comment(ref tok) if tok.kind() == &TokenKind::DomComment => Some(format!("<!--{}-->", tok.value_str())),
+            &Node::Comment(ref tok) => Some(tok.value_str().to_string()),
+            &Node::Statement(ref list) | &Node::List(ref list) => self.inline_list(list, ""),
+            &Node::Print(ref body, escape) => Some(self.print(body, escape)),
+            &Node::Include(ref tok, _) => Some(format!("{{{{include {}}}}}", quote(tok.value_str()))),
+            &Node::DomTag(ref name, ref attrs, ref children) => {
+                let open = self.open_tag(name, attrs, None);
+                if children.is_empty() && is_void_element(name.value_str()) {
+                    return Some(format!("{}/>", open));
+                }
+                let content = self.inline_list(children, " ")?;
+                Some(format!("{}>{}</{}>", open, content, name.value_str()))
+            }
+            &Node::If(ref condition, ref body, ref branches, _) => {
+                if directive_tag(body, condition.offset()).is_some() {
+                    return None;
+                }
+                let mut text = format!("{{{{if {}}}}}{}", self.expr(condition), self.inline_list(body, "")?);
+                text.push_str(&self.inline_branches(branches)?);
+                text.push_str("{{/if}}");
+                Some(text)
+            }
+            &Node::For(ref key, ref value, ref iter, ref body, ref for_else) => {
+                if directive_tag(body, Some(key.offset())).is_some() {
+                    return None;
+                }
+                let head = self.for_head(key, value, iter);
+                let mut text = format!("{{{{for {}}}}}{}", head, self.inline_list(body, "")?);
+                text.push_str(&self.inline_else(for_else)?);
+                text.push_str("{{/for}}");
+                Some(text)
+            }
+            &Node::With(ref expr, ref alias, ref body, ref with_else) => {
+                let mut text = format!("{{{{with {} as {}}}}}{}", self.expr(expr), alias.value_str(), self.inline_list(body, "")?);
+                text.push_str(&self.inline_else(with_else)?);
+                text.push_str("{{/with}}");
+                Some(text)
+            }
+            node => Some(self.print(node, true)),
+        }
+    }
+
+    fn inline_branches(&self, branches: &NodeList) -> Option<String> {
+        let mut text = String::new();
+        for branch in branches {
+            match branch {
+                &Node::If(ref condition, ref body, ref rest, _) => {
+                    text.push_str(&format!("{{{{elif {}}}}}{}", self.expr(condition), self.inline_list(body, "")?));
+                    text.push_str(&self.inline_branches(rest)?);
+                }
+                node => text.push_str(&self.inline_else(node)?),
+            }
+        }
+        return Some(text);
+    }
+
+    fn inline_else(&self, node: &Node) -> Option<String> {
+        match node {
+            &Node::Else(ref list) => Some(format!("{{{{else}}}}{}", self.inline_list(list, "")?)),
+            _ => Some(String::new()),
+        }
+    }
+
+    /// 生成输出语句。
+    ///
+    /// 以字符串、数字或模板字符串开始的代码段不转义输出，因此需要转义时加上括号，
+    /// 不转义的其它表达式则使用 `{{!! }}`。
+    fn print(&self, body: &Node, escape: bool) -> String {
+        let expr = self.expr(body);
+        let unescaped = match expr.chars().next() {
+            Some(ch) => ch == '\'' || ch == '"' || ch == '`' || ch.is_ascii_digit(),
+            None => false,
+        };
+        if escape && unescaped {
+            return format!("{{{{ ({}) }}}}", expr);
+        }
+        if !escape && !unescaped {
+            return format!("{{{{!! {} }}}}", expr);
+        }
+        return format!("{{{{ {} }}}}", expr);
+    }
+
+    /// 生成表达式，优先级低于 min 时加上括号。
+    fn operand(&self, node: &Node, min: usize) -> String {
+        let text = self.expr(node);
+        if precedence(node) < min {
+            return format!("({})", text);
+        }
+        return text;
+    }
+
+    fn exprs(&self, list: &NodeList) -> String {
+        list.iter().map(|node| self.expr(node)).collect::<Vec<String>>().join(", ")
+    }
+
+    fn expr(&self, node: &Node) -> String {
+        match node {
+            &Node::Empty => String::new(),
+            &Node::Ternary(ref expr, ref left, ref right) => {
+                format!("{} ? {} : {}", self.operand(expr, 1), self.expr(left), self.expr(right))
+            }
+            &Node::Binary(ref left, ref right, Operator::Pow) => {
+                format!("{} ** {}", self.operand(left, 11), self.operand(right, 9))
+            }
+            &Node::Binary(ref left, ref right, ref op) => {
+                let (symbol, prec) = operator(op);
+                format!("{} {} {}", self.operand(left, prec), symbol, self.operand(right, prec + 1))
+            }
+            &Node::Unary(ref body, ref op) => {
+                let body = self.operand(body, 9);
+                // 避免与后续的一元运算符连成 --、!! 等符号
+                if body.starts_with('-') || body.starts_with('+') || body.starts_with('!') {
+                    return format!("{}({})", operator(op).0, body);
+                }
+                format!("{}{}", operator(op).0, body)
+            }
+            &Node::Property(ref obj, ref params, ref op) | &Node::OptionalProperty(ref obj, ref params, ref op) => {
+                let obj = self.operand(obj, 11);
+                match (op.value_str(), params.first()) {
+                    (".", Some(&Node::Const(Constant::String(ref name, _)))) |
+                    ("?.", Some(&Node::Const(Constant::String(ref name, _)))) => {
+                        format!("{}{}{}", obj, op.value_str(), name.value_str())
+                    }
+                    _ => format!("{}{}{}]", obj, op.value_str(), self.exprs(params)),
+                }
+            }
+            &Node::Method(ref obj, ref params, _) => format!("{}({})", self.operand(obj, 11), self.exprs(params)),
+            &Node::OptionalMethod(ref obj, ref params, _) => format!("{}?.({})", self.operand(obj, 11), self.exprs(params)),
+            &Node::Range(ref start, ref end, ref step, inclusive) => {
+                let mut text = format!("{}{}{}", self.operand(start, 6), if inclusive { "..=" } else { ".." }, self.operand(end, 7));
+                if !is_empty(step) {
+                    text.push_str(&format!(" step {}", self.operand(step, 7)));
+                }
+                text
+            }
+            &Node::Slice(ref obj, ref start, ref end, ref step, ref op) => {
+                let mut text = format!("{}{}{}:{}", self.operand(obj, 11), op.value_str(), self.expr(start), self.expr(end));
+                if !is_empty(step) {
+                    text.push(':');
+                    text.push_str(&self.expr(step));
+                }
+                text.push(']');
+                text
+            }
+            &Node::Const(ref constant) => {
+                match constant {
+                    &Constant::None => "null".to_string(),
+                    &Constant::True => "true".to_string(),
+                    &Constant::False => "false".to_string(),
+                    &Constant::Break(_) => "break".to_string(),
+                    &Constant::Continue(_) => "continue".to_string(),
+                    &Constant::String(ref tok, _) => quote(tok.value_str()),
+                    &Constant::Integer(ref tok, _) | &Constant::Float(ref tok, _) => tok.value_str().to_string(),
+                }
+            }
+            &Node::Template(ref parts) => {
+                let mut text = String::from("`");
+                for part in parts {
+                    match part {
+                        &Node::Const(Constant::String(ref tok, _)) => text.push_str(tok.value_str()),
+                        node => text.push_str(&format!("${{{}}}", self.expr(node))),
+                    }
+                }
+                text.push('`');
+                text
+            }
+            &Node::Identifier(ref tok) => tok.value_str().to_string(),
+            &Node::Array(ref items) => format!("[{}]", self.exprs(items)),
+            &Node::Map(ref entries) => {
+                if entries.is_empty() {
+                    return "{}".to_string();
+                }
+                // 两侧留空格，避免嵌套的 } 与结束边界符 }} 混淆
+                format!("{{ {} }}", self.exprs(entries))
+            }
+            &Node::MapEntry(ref key, ref value) => {
+                if key.kind() == &TokenKind::String {
+                    return format!("{}: {}", quote(key.value_str()), self.expr(value));
+                }
+                format!("{}: {}", key.value_str(), self.expr(value))
+            }
+            &Node::Lambda(ref params, ref body) => {
+                let names: Vec<&str> = params.iter().map(|p| p.value_str()).collect();
+                if names.len() == 1 {
+                    return format!("{} => {}", names[0], self.expr(body));
+                }
+                format!("({}) => {}", names.join(", "), self.expr(body))
+            }
+            node => self.inline(node).unwrap_or_default(),
+        }
+    }
+}
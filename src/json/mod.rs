@@ -0,0 +1,348 @@
+//! 简单的 JSON 值及其解析、序列化，供语言服务器、命令行工具等交换数据使用。
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::char;
+use {Error, Result};
+
+/// JSON 值，对象的键按字典序排列。
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    /// 解析 JSON 文本，错误的偏移指向出错的字节。
+    pub fn parse(text: &str) -> Result<Json> {
+        let mut reader = Reader { source: text.as_bytes(), offset: 0 };
+        let value = reader.value()?;
+        reader.whitespace();
+        if reader.offset < reader.source.len() {
+            return Err(Error::Parse(format!("unexpected trailing characters"), reader.offset));
+        }
+        return Ok(value);
+    }
+
+    /// 由键值对列表创建对象。
+    pub fn object(entries: Vec<(&str, Json)>) -> Json {
+        Json::Object(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    /// 获取对象的字段，不是对象或字段不存在时返回 None。
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            &Json::Object(ref entries) => entries.get(key),
+            _ => None,
+        }
+    }
+
+    /// 按路径依次获取嵌套对象的字段。
+    pub fn find(&self, path: &[&str]) -> Option<&Json> {
+        let mut value = self;
+        for key in path {
+            match value.get(key) {
+                Some(v) => { value = v; }
+                None => { return None; }
+            }
+        }
+        return Some(value);
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            &Json::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            &Json::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// 获取整数值，带小数部分的数字返回 None。
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            &Json::Number(n) if n.fract() == 0.0 => Some(n as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            &Json::Array(ref items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        match self {
+            &Json::Null => true,
+            _ => false,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Json {
+    fn from(s: &'a str) -> Json { Json::String(s.to_string()) }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json { Json::String(s) }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json { Json::Bool(b) }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Json { Json::Number(n as f64) }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json { Json::Number(n as f64) }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Json { Json::Number(n) }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Json { Json::Array(items) }
+}
+
+/// 输出字符串的 JSON 表示（带引号）。
+pub fn quote(s: &str, f: &mut dyn fmt::Write) -> fmt::Result {
+    f.write_char('"')?;
+    for ch in s.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    return f.write_char('"');
+}
+
+/// 紧凑格式输出，整数值不带小数部分，非有限的数字输出为 null。
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Json::Null => f.write_str("null"),
+            &Json::Bool(b) => write!(f, "{}", b),
+            &Json::Number(n) => {
+                if !n.is_finite() {
+                    return f.write_str("null");
+                } else if n.fract() == 0.0 && n.abs() < 1e15 {
+                    return write!(f, "{}", n as i64);
+                }
+                return write!(f, "{}", n);
+            }
+            &Json::String(ref s) => quote(s, f),
+            &Json::Array(ref items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                return f.write_str("]");
+            }
+            &Json::Object(ref entries) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    quote(key, f)?;
+                    write!(f, ":{}", value)?;
+                }
+                return f.write_str("}");
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    source: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.source.get(self.offset).cloned()
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.offset += 1;
+        }
+    }
+
+    fn error<T>(&self, msg: &str) -> Result<T> {
+        return Err(Error::Parse(msg.to_string(), self.offset));
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json> {
+        if self.source[self.offset..].starts_with(word.as_bytes()) {
+            self.offset += word.len();
+            return Ok(value);
+        }
+        return self.error("unexpected character");
+    }
+
+    fn value(&mut self) -> Result<Json> {
+        self.whitespace();
+        match self.peek() {
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => {
+                self.offset += 1;
+                let mut items = vec![];
+                self.whitespace();
+                if self.peek() == Some(b']') {
+                    self.offset += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => { self.offset += 1; }
+                        Some(b']') => {
+                            self.offset += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => { return self.error("expected ',' or ']'"); }
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.offset += 1;
+                let mut entries = BTreeMap::new();
+                self.whitespace();
+                if self.peek() == Some(b'}') {
+                    self.offset += 1;
+                    return Ok(Json::Object(entries));
+                }
+                loop {
+                    self.whitespace();
+                    if self.peek() != Some(b'"') {
+                        return self.error("expected object key");
+                    }
+                    let key = self.string()?;
+                    self.whitespace();
+                    if self.peek() != Some(b':') {
+                        return self.error("expected ':'");
+                    }
+                    self.offset += 1;
+                    let value = self.value()?;
+                    entries.insert(key, value);
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => { self.offset += 1; }
+                        Some(b'}') => {
+                            self.offset += 1;
+                            return Ok(Json::Object(entries));
+                        }
+                        _ => { return self.error("expected ',' or '}'"); }
+                    }
+                }
+            }
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(_) => self.error("unexpected character"),
+            None => self.error("unexpected end of input"),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json> {
+        let start = self.offset;
+        while let Some(b'-') | Some(b'+') | Some(b'.') | Some(b'e') | Some(b'E') | Some(b'0'..=b'9') = self.peek() {
+            self.offset += 1;
+        }
+        let text = String::from_utf8_lossy(&self.source[start..self.offset]).into_owned();
+        return match text.parse::<f64>() {
+            Ok(n) => Ok(Json::Number(n)),
+            Err(_) => Err(Error::Parse(format!("invalid number {:?}", text), start)),
+        };
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = match self.source.get(self.offset..self.offset + 4) {
+            Some(digits) => String::from_utf8_lossy(digits).into_owned(),
+            None => { return self.error("invalid unicode escape"); }
+        };
+        return match u32::from_str_radix(&digits, 16) {
+            Ok(code) => {
+                self.offset += 4;
+                Ok(code)
+            }
+            Err(_) => self.error("invalid unicode escape"),
+        };
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.offset += 1;
+        let mut buf: Vec<u8> = vec![];
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.offset += 1;
+                    break;
+                }
+                Some(b'\\') => {
+                    self.offset += 1;
+                    let escaped = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            self.offset += 1;
+                            let mut code = self.hex4()?;
+                            // 代理对
+                            if code >= 0xD800 && code < 0xDC00 && self.source[self.offset..].starts_with(b"\\u") {
+                                self.offset += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            let ch = char::from_u32(code).unwrap_or('\u{FFFD}');
+                            let mut tmp = [0u8; 4];
+                            buf.extend_from_slice(ch.encode_utf8(&mut tmp).as_bytes());
+                            continue;
+                        }
+                        _ => { return self.error("invalid escape"); }
+                    };
+                    self.offset += 1;
+                    let mut tmp = [0u8; 4];
+                    buf.extend_from_slice(escaped.encode_utf8(&mut tmp).as_bytes());
+                }
+                Some(b) => {
+                    buf.push(b);
+                    self.offset += 1;
+                }
+                None => { return self.error("unterminated string"); }
+            }
+        }
+        // 源是有效的 UTF-8，按字节复制的片段仍是有效的 UTF-8
+        return Ok(String::from_utf8_lossy(&buf).into_owned());
+    }
+}
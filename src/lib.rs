@@ -1,3 +1,5 @@
+extern crate unicode_xid;
+
 #[macro_use]
 mod macros;
 pub mod util;
@@ -6,15 +8,26 @@ pub mod ast;
 pub mod token;
 pub mod scanner;
 pub mod parser;
+pub mod runtime;
+pub mod analysis;
+pub mod optimizer;
+pub mod formatter;
+pub mod cst;
+pub mod json;
+pub mod lsp;
+pub mod cli;
+pub mod watch;
+pub mod engine;
 
 use std::result;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Error {
     None,
     EOF,
     Ok,
     Message(String),
+    /// 带有位置的消息：消息、行号、列号和模板名称
     RefMessage(String, usize, usize, String),
     Scan(String, usize),
     Parse(String, usize),
@@ -41,6 +54,9 @@ impl Error {
             &Error::Visit(ref msg, ref offset) => {
                 panic!("Visiting failed at: {}({}:{}): {}", source.filename().to_str().unwrap(), source.line(*offset), source.column(*offset), msg)
             }
+            &Error::RefMessage(ref msg, line, column, ref name) => {
+                panic!("Failed at: {}({}:{}): {}", name, line, column, msg)
+            }
             _ => {
                 panic!("{:?}", self)
             }
@@ -0,0 +1,468 @@
+//! 模板的语言服务器，通过标准输入输出以 LSP（Language Server Protocol）与编辑器通信。
+//!
+//! 支持的功能：
+//! - 打开、修改文档时发布解析错误（增量同步，只重新解析被修改的部分）；
+//! - 文档大纲：DOM 元素、`if`/`for`/`with` 代码块以及 `include` 语句；
+//! - 按 DOM 元素和代码块折叠；
+//! - 在 `{{include 'name'}}` 上跳转到被引入的模板。
+//!
+//! 位置的行号和列号通过 `Source::line`、`Source::column` 计算，并按协议换算为从 0 开始的行号和 UTF-16 列号。
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use ast::{Node, NodeList};
+use cst::{self, SyntaxKind, SyntaxNode};
+use json::Json;
+use parser::{Document, TextEdit};
+use scanner::{BytesScanner, Source};
+use token::{Token, TokenKind};
+use Error;
+
+/// 文档符号的种类，取值见协议的 `SymbolKind`。
+const SYMBOL_MODULE: i64 = 2;
+const SYMBOL_NAMESPACE: i64 = 3;
+const SYMBOL_FIELD: i64 = 8;
+
+/// 协议的错误码：消息不是合法的 JSON。
+const PARSE_ERROR: i64 = -32700;
+/// 协议的错误码：方法不存在。
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// 语言服务器的状态：打开的文档及协议的生命周期。
+#[derive(Debug)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    /// 工作区根目录，引入的模板在文档所在目录找不到时在此查找
+    root: Option<PathBuf>,
+    shutdown: bool,
+    exited: bool,
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server { documents: HashMap::new(), root: None, shutdown: false, exited: false }
+    }
+
+    /// 是否已收到 `exit` 通知。
+    pub fn is_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// 退出码：收到 `shutdown` 请求后退出为 0，否则为 1。
+    pub fn exit_code(&self) -> i32 {
+        if self.shutdown { 0 } else { 1 }
+    }
+
+    /// 处理一条消息，返回需要发送给客户端的响应和通知。
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(|m| m.as_str()).unwrap_or("");
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            // 通知没有 id，也不需要响应
+            None => { return self.notify(method, &params); }
+        };
+        let result = match method {
+            "initialize" => {
+                self.root = params.get("rootUri").and_then(|u| u.as_str()).map(uri_to_path);
+                Ok(capabilities())
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/documentSymbol" => Ok(self.document_symbols(&params)),
+            "textDocument/foldingRange" => Ok(self.folding_ranges(&params)),
+            "textDocument/definition" => Ok(self.definition(&params)),
+            _ => Err((METHOD_NOT_FOUND, format!("method not found: {}", method))),
+        };
+        let response = match result {
+            Ok(result) => Json::object(vec![("jsonrpc", "2.0".into()), ("id", id), ("result", result)]),
+            Err((code, msg)) => error_response(id, code, msg),
+        };
+        return vec![response];
+    }
+
+    fn notify(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params.find(&["textDocument", "uri"]).and_then(|u| u.as_str()).unwrap_or("").to_string();
+        match method {
+            "exit" => {
+                self.exited = true;
+            }
+            "textDocument/didOpen" => {
+                let text = params.find(&["textDocument", "text"]).and_then(|t| t.as_str()).unwrap_or("");
+                // 空文档总能解析成功，再以一次编辑载入内容，解析失败时文档也会被保留
+                let mut document = Document::parse("").unwrap();
+                let result = document.edit(&TextEdit::new(0..0, text));
+                self.documents.insert(uri.clone(), document);
+                return vec![self.publish(&uri, result.err())];
+            }
+            "textDocument/didChange" => {
+                let mut error = None;
+                if let Some(document) = self.documents.get_mut(&uri) {
+                    let changes = params.get("contentChanges").and_then(|c| c.as_array()).cloned().unwrap_or(vec![]);
+                    for change in changes {
+                        let text = change.get("text").and_then(|t| t.as_str()).unwrap_or("");
+                        let range = match change.get("range") {
+                            Some(range) => offset_at(document.source(), &range.get("start").cloned().unwrap_or(Json::Null))
+                                ..offset_at(document.source(), &range.get("end").cloned().unwrap_or(Json::Null)),
+                            None => 0..document.source().len(),
+                        };
+                        // 只有最后一次编辑的结果反映文档的当前状态
+                        error = document.edit(&TextEdit::new(range, text)).err();
+                    }
+                } else {
+                    return vec![];
+                }
+                return vec![self.publish(&uri, error)];
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![self.publish(&uri, None)];
+            }
+            _ => {}
+        }
+        return vec![];
+    }
+
+    /// 发布文档的诊断信息，没有错误时清除之前发布的诊断。
+    fn publish(&self, uri: &str, error: Option<Error>) -> Json {
+        let mut diagnostics = vec![];
+        if let (Some(err), Some(document)) = (error, self.documents.get(uri)) {
+            let text = document.source();
+            let (msg, offset) = match err {
+                Error::Scan(msg, offset) | Error::Parse(msg, offset) | Error::Visit(msg, offset) => (msg, offset),
+                err => (format!("{:?}", err), 0),
+            };
+            let scanner = BytesScanner::new(text, Path::new(uri));
+            let pos = position(text, &scanner, offset);
+            diagnostics.push(Json::object(vec![
+                ("range", Json::object(vec![("start", pos.clone()), ("end", pos)])),
+                ("severity", 1i64.into()),
+                ("source", "otpl".into()),
+                ("message", msg.into()),
+            ]));
+        }
+        return Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            ("params", Json::object(vec![("uri", uri.into()), ("diagnostics", diagnostics.into())])),
+        ]);
+    }
+
+    fn document<'a>(&'a self, params: &'a Json) -> Option<(&'a str, &'a Document)> {
+        let uri = match params.find(&["textDocument", "uri"]).and_then(|u| u.as_str()) {
+            Some(uri) => uri,
+            None => { return None; }
+        };
+        return self.documents.get(uri).map(|document| (uri, document));
+    }
+
+    fn document_symbols(&self, params: &Json) -> Json {
+        let (uri, document) = match self.document(params) {
+            Some(found) => found,
+            None => { return Json::Null; }
+        };
+        let text = document.source();
+        return match cst::parse(text) {
+            Ok(tree) => {
+                let scanner = BytesScanner::new(text, Path::new(uri));
+                Json::Array(symbols(text, &scanner, &tree))
+            }
+            Err(_) => Json::Array(vec![]),
+        };
+    }
+
+    fn folding_ranges(&self, params: &Json) -> Json {
+        let (uri, document) = match self.document(params) {
+            Some(found) => found,
+            None => { return Json::Null; }
+        };
+        let text = document.source();
+        let mut ranges = vec![];
+        if let Ok(tree) = cst::parse(text) {
+            let scanner = BytesScanner::new(text, Path::new(uri));
+            folding(&scanner, &tree, &mut ranges);
+        }
+        return Json::Array(ranges);
+    }
+
+    fn definition(&self, params: &Json) -> Json {
+        let (uri, document) = match self.document(params) {
+            Some(found) => found,
+            None => { return Json::Null; }
+        };
+        let offset = offset_at(document.source(), &params.get("position").cloned().unwrap_or(Json::Null));
+        let mut found = vec![];
+        includes(document.nodes(), &mut found);
+        for (tok, name) in found {
+            // 名称标记的偏移不含引号，光标在引号上时也视为选中
+            if offset + 1 < tok.offset() || offset > tok.offset() + tok.value().len() + 1 {
+                continue;
+            }
+            let mut dirs = vec![];
+            if let Some(dir) = uri_to_path(uri).parent() {
+                dirs.push(dir.to_path_buf());
+            }
+            if let Some(ref root) = self.root {
+                dirs.push(root.clone());
+            }
+            for dir in dirs {
+                let path = dir.join(&name);
+                if path.is_file() {
+                    let start = Json::object(vec![("line", 0i64.into()), ("character", 0i64.into())]);
+                    return Json::object(vec![
+                        ("uri", path_to_uri(&path).into()),
+                        ("range", Json::object(vec![("start", start.clone()), ("end", start)])),
+                    ]);
+                }
+            }
+        }
+        return Json::Null;
+    }
+}
+
+fn capabilities() -> Json {
+    let sync = Json::object(vec![("openClose", true.into()), ("change", 2i64.into())]);
+    return Json::object(vec![
+        ("capabilities", Json::object(vec![
+            ("textDocumentSync", sync),
+            ("documentSymbolProvider", true.into()),
+            ("foldingRangeProvider", true.into()),
+            ("definitionProvider", true.into()),
+        ])),
+        ("serverInfo", Json::object(vec![("name", "otpl-lsp".into()), ("version", env!("CARGO_PKG_VERSION").into())])),
+    ]);
+}
+
+/// 错误响应，无法确定请求的 id 时 id 为 null。
+fn error_response(id: Json, code: i64, msg: String) -> Json {
+    return Json::object(vec![
+        ("jsonrpc", "2.0".into()), ("id", id),
+        ("error", Json::object(vec![("code", code.into()), ("message", msg.into())])),
+    ]);
+}
+
+/// 读取一条以 `Content-Length` 头分帧的消息，输入结束时返回 None。
+///
+/// 消息缺少长度、不是 UTF-8 或不是合法的 JSON 时返回 `io::ErrorKind::InvalidData` 错误，之后可以继续读取下一条消息。
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim();
+        if name.eq_ignore_ascii_case("Content-Length") {
+            length = parts.next().and_then(|v| v.trim().parse::<usize>().ok());
+        }
+    }
+    let length = match length {
+        Some(length) => length,
+        None => { return Err(io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")); }
+    };
+    let mut body = vec![0u8; length];
+    input.read_exact(&mut body)?;
+    let text = match String::from_utf8(body) {
+        Ok(text) => text,
+        Err(_) => { return Err(io::Error::new(io::ErrorKind::InvalidData, "message is not valid utf-8")); }
+    };
+    return match Json::parse(&text) {
+        Ok(message) => Ok(Some(message)),
+        Err(err) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid message: {:?}", err))),
+    };
+}
+
+/// 写入一条消息。
+pub fn write_message<W: Write>(output: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    return output.flush();
+}
+
+/// 运行语言服务器直到收到 `exit` 通知或输入结束，返回进程的退出码。
+///
+/// 格式错误的消息以解析错误响应，服务器继续处理之后的消息；只有读写失败时返回错误。
+pub fn run<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> io::Result<i32> {
+    let mut server = Server::new();
+    while !server.is_exited() {
+        let message = match read_message(input) {
+            Ok(Some(message)) => message,
+            Ok(None) => { break; }
+            Err(ref err) if err.kind() == io::ErrorKind::InvalidData => {
+                write_message(output, &error_response(Json::Null, PARSE_ERROR, format!("{}", err)))?;
+                continue;
+            }
+            Err(err) => { return Err(err); }
+        };
+        for reply in server.handle(&message) {
+            write_message(output, &reply)?;
+        }
+    }
+    return Ok(server.exit_code());
+}
+
+/// 将偏移换算为协议的位置：从 0 开始的行号和按 UTF-16 编码单元计的列号。
+fn position(text: &str, source: &dyn Source, offset: usize) -> Json {
+    let mut offset = if offset > text.len() { text.len() } else { offset };
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let (line, column) = (source.line(offset), source.column(offset));
+    if line == 0 || column == 0 {
+        return Json::object(vec![("line", 0i64.into()), ("character", 0i64.into())]);
+    }
+    let character = text[offset + 1 - column..offset].encode_utf16().count();
+    return Json::object(vec![("line", (line - 1).into()), ("character", character.into())]);
+}
+
+/// 将协议的位置换算为偏移，超出行尾或文档结尾的位置被截断。
+fn offset_at(text: &str, position: &Json) -> usize {
+    let line = position.get("line").and_then(|l| l.as_i64()).unwrap_or(0) as usize;
+    let character = position.get("character").and_then(|c| c.as_i64()).unwrap_or(0) as usize;
+    let mut start = 0;
+    for _ in 0..line {
+        match text[start..].find('\n') {
+            Some(index) => { start += index + 1; }
+            None => { return text.len(); }
+        }
+    }
+    let mut units = 0;
+    for (index, ch) in text[start..].char_indices() {
+        if ch == '\n' || units >= character {
+            return start + index;
+        }
+        units += ch.len_utf16();
+    }
+    return text.len();
+}
+
+fn range(text: &str, source: &dyn Source, start: usize, end: usize) -> Json {
+    return Json::object(vec![("start", position(text, source, start)), ("end", position(text, source, end))]);
+}
+
+/// 代码段去掉界定符后的文本，如：`if a > 1`。
+fn statement_text(text: &str, statement: &SyntaxNode) -> String {
+    let span = statement.span();
+    return text[span].trim_start_matches("{{").trim_end_matches("}}").trim().to_string();
+}
+
+/// 由无损语法树生成层次化的文档符号。
+fn symbols(text: &str, source: &dyn Source, node: &SyntaxNode) -> Vec<Json> {
+    let mut list = vec![];
+    for child in node.children() {
+        let (name, kind, selection) = match child.kind() {
+            Some(SyntaxKind::Element) => {
+                let tokens = child.children()[0].tokens();
+                match tokens.iter().find(|t| t.kind == TokenKind::DomTagStart) {
+                    Some(tok) => (tok.text.clone(), SYMBOL_FIELD, tok.span()),
+                    None => { continue; }
+                }
+            }
+            Some(SyntaxKind::Block) => {
+                let head = &child.children()[0];
+                (statement_text(text, head), SYMBOL_NAMESPACE, head.span())
+            }
+            Some(SyntaxKind::Statement) => {
+                let name = statement_text(text, child);
+                if !name.starts_with("include") {
+                    continue;
+                }
+                (name, SYMBOL_MODULE, child.span())
+            }
+            _ => { continue; }
+        };
+        let span = child.span();
+        list.push(Json::object(vec![
+            ("name", name.into()),
+            ("kind", kind.into()),
+            ("range", range(text, source, span.start, span.end)),
+            ("selectionRange", range(text, source, selection.start, selection.end)),
+            ("children", symbols(text, source, child).into()),
+        ]));
+    }
+    return list;
+}
+
+/// 跨越多行的 DOM 元素和代码块可以折叠，结束标签或结束语句所在的行保持可见。
+fn folding(source: &dyn Source, node: &SyntaxNode, ranges: &mut Vec<Json>) {
+    for child in node.children() {
+        match child.kind() {
+            Some(SyntaxKind::Element) | Some(SyntaxKind::Block) => {
+                let span = child.span();
+                let start = source.line(span.start);
+                let end = source.line(span.end);
+                if start > 0 && end > start + 1 {
+                    ranges.push(Json::object(vec![("startLine", (start - 1).into()), ("endLine", (end - 2).into())]));
+                }
+                folding(source, child, ranges);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 收集语法树中的 `include` 语句。
+fn includes(list: &NodeList, found: &mut Vec<(Token, String)>) {
+    for node in list {
+        match node {
+            &Node::Include(ref tok, ref name) => found.push((tok.clone(), name.clone())),
+            &Node::DomTag(_, ref attrs, ref children) => {
+                for attr in attrs {
+                    includes(&attr.value, found);
+                }
+                includes(children, found);
+            }
+            &Node::Root(ref list) | &Node::List(ref list) | &Node::Statement(ref list) | &Node::Else(ref list) => includes(list, found),
+            &Node::If(_, ref body, ref branches, _) => {
+                includes(body, found);
+                includes(branches, found);
+            }
+            &Node::For(_, _, _, ref body, ref other) | &Node::With(_, _, ref body, ref other) => {
+                includes(body, found);
+                includes(&vec![(**other).clone()], found);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 将 `file://` URI 转换为路径，解码其中的百分号转义。
+pub fn uri_to_path(uri: &str) -> PathBuf {
+    let path = if uri.starts_with("file://") { &uri[7..] } else { uri };
+    let bytes = path.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = String::from_utf8_lossy(&bytes[i + 1..i + 3]).into_owned();
+            if let Ok(b) = u8::from_str_radix(&hex, 16) {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    return PathBuf::from(String::from_utf8_lossy(&decoded).into_owned());
+}
+
+/// 将路径转换为 `file://` URI，对非 URI 安全的字符进行百分号转义。
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &b in path.to_string_lossy().as_bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => uri.push(b as char),
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    return uri;
+}
@@ -33,4 +33,11 @@ macro_rules! debug {
     //     debug_head!();
     //     $x
     // };
-}
\ No newline at end of file
+}
+/// 输出扫描、解析过程的跟踪信息。
+/// 仅在测试时输出，且写入标准错误，以免干扰占用标准输出的工具（如：语言服务器、命令行渲染）。
+macro_rules! trace {
+    ($($arg:tt)*) => (if cfg!(test) {
+        eprintln!($($arg)*);
+    });
+}
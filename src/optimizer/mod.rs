@@ -0,0 +1,254 @@
+//! 语法树的优化。
+//!
+//! 在编译或解释执行之前对语法树做等价变换：
+//! - 折叠常量上的二元、一元和三目运算，以及常量的输出；
+//! - 移除不可达的 `if` 分支；
+//! - 合并相邻的字面量；
+//! - 展开嵌套的代码段。
+//!
+//! 常量运算借助解释器求值，因此折叠的结果与运行时完全一致，运行时会出错的表达式（如除以 0）保持原样。
+//!
+//! `prerender` 是独立的一趟，将 DOM 标签预先渲染为字面量。
+
+mod prerender;
+
+pub use self::prerender::prerender;
+
+use ast::{Node, NodeList, Operator, Constant, DomAttr};
+use token::{Token, TokenKind};
+use runtime::{Context, Interpreter, Value, escape_html};
+
+/// 优化一个语法树节点集合。
+pub fn optimize(list: NodeList) -> NodeList {
+    return optimize_with_escape(list, true);
+}
+
+/// 优化一个语法树节点集合，escape 为 false 时折叠的输出不做 HTML 转义，与关闭了转义的解释器一致。
+pub fn optimize_with_escape(list: NodeList, escape: bool) -> NodeList {
+    let mut buf = vec![];
+    for node in list {
+        optimize_into(node, escape, &mut buf);
+    }
+    return merge_literals(buf);
+}
+
+/// 判断节点是否为可参与运算的常量，`break`/`continue` 不是值。
+fn is_value(node: &Node) -> bool {
+    match node {
+        &Node::Const(Constant::Break(_)) | &Node::Const(Constant::Continue(_)) => false,
+        &Node::Const(_) => true,
+        _ => false,
+    }
+}
+
+/// 计算常量表达式的值，出错时返回 None。
+fn eval(node: &Node) -> Option<Value> {
+    let mut context = Context::new();
+    let mut output = vec![];
+    return Interpreter::new(&mut context, &mut output).eval(node).ok();
+}
+
+/// 将值转换为常量节点，无法表示为常量的值返回 None。
+fn to_const(value: Value, offset: usize) -> Option<Node> {
+    let constant = match value {
+        Value::Null => Constant::None,
+        Value::Bool(true) => Constant::True,
+        Value::Bool(false) => Constant::False,
+        Value::Int(i) => Constant::Integer(Token(TokenKind::Int, offset, i.to_string()), i),
+        Value::Float(f) => Constant::Float(Token(TokenKind::Float, offset, f.to_string()), f),
+        Value::String(s) => Constant::String(Token(TokenKind::String, offset, s.clone()), s),
+        _ => { return None; }
+    };
+    return Some(Node::Const(constant));
+}
+
+/// 尝试将表达式折叠为常量。
+fn fold(node: Node) -> Node {
+    let offset = node.offset().unwrap_or(0);
+    match eval(&node).and_then(|value| to_const(value, offset)) {
+        Some(constant) => constant,
+        None => node,
+    }
+}
+
+fn optimize_box(node: Box<Node>) -> Box<Node> {
+    Box::new(optimize_expr(*node))
+}
+
+fn optimize_exprs(list: NodeList) -> NodeList {
+    list.into_iter().map(optimize_expr).collect()
+}
+
+/// 优化一个表达式。
+fn optimize_expr(node: Node) -> Node {
+    match node {
+        Node::Binary(left, right, operator) => {
+            let left = optimize_expr(*left);
+            let right = optimize_expr(*right);
+            if is_value(&left) {
+                // 左侧为常量时的短路运算
+                let truth = eval(&left).map(|v| (v.is_true(), v.is_null()));
+                match (&operator, truth) {
+                    (&Operator::And, Some((false, _))) => { return Node::Const(Constant::False); }
+                    (&Operator::Or, Some((true, _))) => { return Node::Const(Constant::True); }
+                    (&Operator::NullCond, Some((_, true))) => { return right; }
+                    (&Operator::NullCond, Some((_, false))) => { return left; }
+                    _ => {}
+                }
+                if is_value(&right) {
+                    return fold(Node::Binary(Box::new(left), Box::new(right), operator));
+                }
+            }
+            return Node::Binary(Box::new(left), Box::new(right), operator);
+        }
+        Node::Unary(body, operator) => {
+            let body = optimize_expr(*body);
+            if is_value(&body) {
+                return fold(Node::Unary(Box::new(body), operator));
+            }
+            return Node::Unary(Box::new(body), operator);
+        }
+        Node::Ternary(expr, left, right) => {
+            let expr = optimize_expr(*expr);
+            let left = optimize_expr(*left);
+            let right = optimize_expr(*right);
+            if is_value(&expr) {
+                if let Some(value) = eval(&expr) {
+                    return if value.is_true() { left } else { right };
+                }
+            }
+            return Node::Ternary(Box::new(expr), Box::new(left), Box::new(right));
+        }
+        Node::Template(parts) => {
+            let parts = optimize_exprs(parts);
+            if parts.iter().all(is_value) {
+                return fold(Node::Template(parts));
+            }
+            return Node::Template(parts);
+        }
+        Node::Property(obj, params, operator) => Node::Property(optimize_box(obj), optimize_exprs(params), operator),
+        Node::Method(obj, params, operator) => Node::Method(optimize_box(obj), optimize_exprs(params), operator),
+        Node::OptionalProperty(obj, params, operator) => Node::OptionalProperty(optimize_box(obj), optimize_exprs(params), operator),
+        Node::OptionalMethod(obj, params, operator) => Node::OptionalMethod(optimize_box(obj), optimize_exprs(params), operator),
+        Node::Range(start, end, step, inclusive) => Node::Range(optimize_box(start), optimize_box(end), optimize_box(step), inclusive),
+        Node::Slice(obj, start, end, step, operator) => {
+            Node::Slice(optimize_box(obj), optimize_box(start), optimize_box(end), optimize_box(step), operator)
+        }
+        Node::Array(items) => Node::Array(optimize_exprs(items)),
+        Node::Map(entries) => Node::Map(optimize_exprs(entries)),
+        Node::MapEntry(key, value) => Node::MapEntry(key, optimize_box(value)),
+        Node::Lambda(params, body) => Node::Lambda(params, optimize_box(body)),
+        node => node,
+    }
+}
+
+/// 优化一个语句节点，结果追加到 buf 中，被移除的节点不产生任何输出。
+fn optimize_into(node: Node, escape: bool, buf: &mut NodeList) {
+    match node {
+        Node::Empty | Node::Comment(_) => {}
+        Node::Statement(list) | Node::List(list) => {
+            // 代码段的执行等价于依次执行其中的节点
+            for node in list {
+                optimize_into(node, escape, buf);
+            }
+        }
+        Node::Print(body, escape_print) => {
+            let body = optimize_expr(*body);
+            if is_value(&body) {
+                if let Some(value) = eval(&body) {
+                    let offset = body.offset().unwrap_or(0);
+                    let text = format!("{}", value);
+                    let text = if escape_print && escape { escape_html(&text) } else { text };
+                    if !text.is_empty() {
+                        buf.push(Node::Literal(Token(TokenKind::Data, offset, text)));
+                    }
+                    return;
+                }
+            }
+            buf.push(Node::Print(Box::new(body), escape_print));
+        }
+        Node::If(condition, body, branches, is_else_if) => {
+            optimize_if(*condition, body, branches, is_else_if, escape, buf);
+        }
+        Node::For(key, value, iter, body, for_else) => {
+            let for_else = optimize_else(*for_else, escape);
+            buf.push(Node::For(key, value, optimize_box(iter), optimize_with_escape(body, escape), Box::new(for_else)));
+        }
+        Node::With(expr, alias, body, with_else) => {
+            let with_else = optimize_else(*with_else, escape);
+            buf.push(Node::With(optimize_box(expr), alias, optimize_with_escape(body, escape), Box::new(with_else)));
+        }
+        Node::DomTag(name, attrs, children) => {
+            let attrs = attrs.into_iter().map(|attr| DomAttr { name: attr.name, value: optimize_with_escape(attr.value, escape) }).collect();
+            buf.push(Node::DomTag(name, attrs, optimize_with_escape(children, escape)));
+        }
+        Node::Root(list) => buf.push(Node::Root(optimize_with_escape(list, escape))),
+        Node::Else(list) => buf.push(Node::Else(optimize_with_escape(list, escape))),
+        node => buf.push(optimize_expr(node)),
+    }
+}
+
+fn optimize_else(node: Node, escape: bool) -> Node {
+    match node {
+        Node::Else(list) => Node::Else(optimize_with_escape(list, escape)),
+        node => node,
+    }
+}
+
+/// 优化 if 语句，移除条件为常量假的分支，条件为常量真时直接展开其主体。
+fn optimize_if(condition: Node, body: NodeList, branches: NodeList, is_else_if: bool, escape: bool, buf: &mut NodeList) {
+    let mut arms: Vec<(Node, NodeList)> = vec![(optimize_expr(condition), body)];
+    let mut otherwise: Option<NodeList> = None;
+    for branch in branches {
+        match branch {
+            Node::If(condition, body, _, _) => arms.push((optimize_expr(*condition), body)),
+            Node::Else(body) => { otherwise = Some(body); }
+            _ => {}
+        }
+    }
+    let mut kept: Vec<(Node, NodeList)> = vec![];
+    for (condition, body) in arms {
+        let truth = if is_value(&condition) { eval(&condition).map(|v| v.is_true()) } else { None };
+        match truth {
+            Some(false) => {}
+            Some(true) => {
+                // 之后的分支不可达
+                otherwise = Some(body);
+                break;
+            }
+            None => kept.push((condition, body)),
+        }
+    }
+    if kept.is_empty() {
+        if let Some(body) = otherwise {
+            for node in optimize_with_escape(body, escape) {
+                buf.push(node);
+            }
+        }
+        return;
+    }
+    let mut kept = kept.into_iter();
+    let (condition, body) = kept.next().unwrap();
+    let mut branches: NodeList = kept.map(|(c, b)| Node::If(Box::new(c), optimize_with_escape(b, escape), vec![], true)).collect();
+    if let Some(body) = otherwise {
+        branches.push(Node::Else(optimize_with_escape(body, escape)));
+    }
+    buf.push(Node::If(Box::new(condition), optimize_with_escape(body, escape), branches, is_else_if));
+}
+
+/// 合并相邻的字面量。
+fn merge_literals(list: NodeList) -> NodeList {
+    let mut buf: NodeList = vec![];
+    for node in list {
+        if let Node::Literal(tok) = node {
+            if let Some(&mut Node::Literal(ref mut prev)) = buf.last_mut() {
+                prev.2.push_str(tok.value_str());
+                continue;
+            }
+            buf.push(Node::Literal(tok));
+            continue;
+        }
+        buf.push(node);
+    }
+    return buf;
+}
@@ -0,0 +1,78 @@
+use ast::{Node, NodeList};
+use token::{Token, TokenKind};
+use runtime::is_void_element;
+use super::merge_literals;
+
+/// 在编译期预先渲染 DOM 标签。
+///
+/// 标签的开始、结束部分以及静态属性被输出为字面量，动态的属性值和子节点作为空洞原样保留，
+/// 合并后完全静态的子树只剩下一个字面量。输出与解释器渲染 `Node::DomTag` 的结果一致。
+pub fn prerender(list: NodeList) -> NodeList {
+    let mut buf = vec![];
+    for node in list {
+        prerender_into(node, &mut buf);
+    }
+    return merge_literals(buf);
+}
+
+fn push_text(buf: &mut NodeList, offset: usize, text: &str) {
+    buf.push(Node::Literal(Token(TokenKind::Data, offset, text.to_string())));
+}
+
+fn prerender_box(node: Box<Node>) -> Box<Node> {
+    match *node {
+        Node::Else(list) => Box::new(Node::Else(prerender(list))),
+        node => Box::new(node),
+    }
+}
+
+fn prerender_into(node: Node, buf: &mut NodeList) {
+    match node {
+        Node::DomTag(name, attrs, children) => {
+            let offset = name.offset();
+            push_text(buf, offset, &format!("<{}", name.value_str()));
+            for attr in attrs {
+                if attr.name.value()[0] == '@' as u8 {
+                    // 未被解析器处理的扩展指令不输出
+                    continue;
+                }
+                push_text(buf, attr.name.offset(), &format!(" {}", attr.name.value_str()));
+                if attr.value.is_empty() {
+                    continue;
+                }
+                push_text(buf, attr.name.offset(), "=\"");
+                for node in attr.value {
+                    prerender_into(node, buf);
+                }
+                push_text(buf, attr.name.offset(), "\"");
+            }
+            if children.is_empty() && is_void_element(name.value_str()) {
+                push_text(buf, offset, "/>");
+                return;
+            }
+            push_text(buf, offset, ">");
+            for node in children {
+                prerender_into(node, buf);
+            }
+            push_text(buf, offset, &format!("</{}>", name.value_str()));
+        }
+        Node::Statement(list) => buf.push(Node::Statement(prerender(list))),
+        Node::Root(list) => buf.push(Node::Root(prerender(list))),
+        Node::Else(list) => buf.push(Node::Else(prerender(list))),
+        Node::If(condition, body, branches, is_else_if) => {
+            let branches = branches.into_iter().map(|branch| match branch {
+                Node::If(condition, body, branches, is_else_if) => Node::If(condition, prerender(body), branches, is_else_if),
+                Node::Else(body) => Node::Else(prerender(body)),
+                node => node,
+            }).collect();
+            buf.push(Node::If(condition, prerender(body), branches, is_else_if));
+        }
+        Node::For(key, value, iter, body, for_else) => {
+            buf.push(Node::For(key, value, iter, prerender(body), prerender_box(for_else)));
+        }
+        Node::With(expr, alias, body, with_else) => {
+            buf.push(Node::With(expr, alias, prerender(body), prerender_box(with_else)));
+        }
+        node => buf.push(node),
+    }
+}
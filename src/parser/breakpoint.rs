@@ -12,16 +12,23 @@ pub struct BreakPoint {
     pub kind: TokenKind,
     /// 用于测试的值得集合
     pub values: Vec<Vec<u8>>,
+    /// 是否以开始定界符开头，开始定界符按种类匹配，以支持自定义定界符
+    pub delimiter: bool,
 }
 
 impl BreakPoint {
     pub fn new(keep: bool, kind: TokenKind, values: Vec<Vec<u8>>) -> BreakPoint {
-        BreakPoint { keep: keep, kind: kind, values: values }
+        BreakPoint { keep: keep, kind: kind, values: values, delimiter: false }
+    }
+
+    /// 创建以开始定界符开头的断点，之后的token按值测试，如：`{{/if`。
+    pub fn statement(keep: bool, values: Vec<Vec<u8>>) -> BreakPoint {
+        BreakPoint { keep: keep, kind: TokenKind::Ignore, values: values, delimiter: true }
     }
 
     pub fn build(breaks: Vec<BreakPoint>) -> Box<(FnMut(&mut Parser) -> NoneResult)> {
         return Box::new(move |parser: &mut Parser| -> NoneResult {
-            println!("BreakPoint ");
+            trace!("BreakPoint ");
             let mut found;
             let mut buf: Vec<Token> = vec![];
             for point in &breaks {
@@ -30,23 +37,23 @@ impl BreakPoint {
                 }
                 found = true;
 
-                for value in &point.values {
+                let start = if point.delimiter { 1 } else { 0 };
+                for i in 0..start + point.values.len() {
                     match parser.take().and_then(|tok| -> NoneResult{
-                        //println!("BreakPoint:{:?}", parser.tokenizer.source().content_str(&tok));
-                        if &point.kind == tok.kind() && value.compare(tok.value()) {
-                            //println!("bbbbbbbbbb{:?}", 2);
-                        } else if point.kind == TokenKind::Ignore && value.compare(tok.value()) {
-                            //println!("bbbbbbbbbb{:?}", 2);
+                        let matched = if i < start {
+                            tok.kind() == &TokenKind::LDelimiter
                         } else {
-                            buf.push(tok);
-                            return Err(Error::None);
-                        }
+                            let value = &point.values[i - start];
+                            (&point.kind == tok.kind() || point.kind == TokenKind::Ignore) && value.compare(tok.value())
+                        };
                         buf.push(tok);
-                        return Error::ok();
+                        if matched {
+                            return Error::ok();
+                        }
+                        return Err(Error::None);
                     }) {
                         Ok(_) => {}
                         Err(Error::None) => {
-                            //println!("bbbbbbbbbb{:?}", 0);
                             found = false;
                             break;
                         }
@@ -59,11 +66,11 @@ impl BreakPoint {
                         parser.back(buf.pop().unwrap());
                     }
                 }
-                println!("BreakPoint out:{:?}  {:?}", found,point);
+                trace!("BreakPoint out:{:?}  {:?}", found,point);
                 if found { return Error::ok(); }
             }
 
             return Err(Error::None);
         });
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,524 @@
+use std::ops::Range;
+use std::path::Path;
+use ast::{Node, NodeList, Constant};
+use token::{Token, TokenKind};
+use scanner::BytesScanner;
+use cst::{self, SyntaxKind, SyntaxNode};
+use super::Parser;
+use {Error, Result};
+
+/// 文本编辑：将 range 范围内的源码替换为 text。
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, text: &str) -> TextEdit {
+        TextEdit { range: range, text: text.to_string() }
+    }
+}
+
+/// 可独立解析的一段源码及其语法树节点。
+#[derive(Debug, Clone)]
+struct Chunk {
+    span: Range<usize>,
+    nodes: NodeList,
+    /// 以 @elif、@else 扩展指令开始，须与前一段一起解析
+    continues: bool,
+    /// 段为单个元素或代码块时，其内容也可以切分为段单独重新解析
+    inner: Option<Inner>,
+}
+
+/// 元素或代码块的内容，不含开始、结束标签或语句。
+#[derive(Debug, Clone)]
+struct Inner {
+    span: Range<usize>,
+    /// 内容中的段，首次编辑内容时才切分
+    chunks: Option<Vec<Chunk>>,
+}
+
+/// 支持增量解析的文档，供编辑器集成使用。
+///
+/// 文档按顶层的 DOM 元素、代码块和文本切分为若干段，各段可以脱离上下文独立解析，
+/// 元素和代码块的内容再以同样的方式切分。
+/// 编辑时只重新解析包含编辑范围的最内层内容中与编辑范围相交的段，其余段的语法树被复用，仅调整其中标记的偏移；
+/// 重新解析的范围内出现不完整的结构（如：删除了结束标签）时逐步向两侧扩大，超出内容时改为重新解析外层的段，
+/// 必要时解析整个文档。
+#[derive(Debug, Clone)]
+pub struct Document {
+    source: String,
+    chunks: Vec<Chunk>,
+    nodes: NodeList,
+    /// 上次编辑后解析失败，语法树已过期，下次编辑时重新解析整个文档
+    stale: bool,
+}
+
+impl Document {
+    /// 解析整个文档。
+    pub fn parse(source: &str) -> Result<Document> {
+        let chunks = parse_chunks(source, 0..source.len())?;
+        let mut document = Document { source: source.to_string(), chunks: chunks, nodes: vec![], stale: false };
+        document.collect_nodes();
+        return Ok(document);
+    }
+
+    /// 当前的源码。
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// 最近一次解析成功的语法树，与 `Parser::parse_all` 解析整个源码的结果一致。
+    pub fn nodes(&self) -> &NodeList {
+        &self.nodes
+    }
+
+    /// 应用一个编辑并增量解析，返回被重新解析的范围（新源码中的位置）。
+    ///
+    /// 解析失败时源码仍被更新而语法树保持为上一次的结果，以便后续的编辑可以继续应用。
+    pub fn edit(&mut self, edit: &TextEdit) -> Result<Range<usize>> {
+        let (start, end) = (edit.range.start, edit.range.end);
+        if start > end || end > self.source.len() || !self.source.is_char_boundary(start) || !self.source.is_char_boundary(end) {
+            return Err(Error::Parse(format!("invalid edit range {}..{}", start, end), start));
+        }
+        if !self.stale {
+            // 切分包含编辑范围的内容须使用编辑前的源码
+            split_inner(&self.source, &mut self.chunks, start, end);
+        }
+        let mut source = String::with_capacity(self.source.len() + edit.text.len());
+        source.push_str(&self.source[..start]);
+        source.push_str(&edit.text);
+        source.push_str(&self.source[end..]);
+        self.source = source;
+        let delta = edit.text.len() as isize - (end - start) as isize;
+
+        if self.stale {
+            return self.reparse_all();
+        }
+        return match reparse(&self.source, &mut self.chunks, start, end, delta) {
+            Ok(Some(region)) => {
+                self.collect_nodes();
+                Ok(region)
+            }
+            Ok(None) => self.reparse_all(),
+            Err(err) => {
+                self.stale = true;
+                Err(err)
+            }
+        };
+    }
+
+    fn reparse_all(&mut self) -> Result<Range<usize>> {
+        match parse_chunks(&self.source, 0..self.source.len()) {
+            Ok(chunks) => {
+                self.chunks = chunks;
+                self.stale = false;
+                self.collect_nodes();
+                return Ok(0..self.source.len());
+            }
+            Err(err) => {
+                self.stale = true;
+                return Err(err);
+            }
+        }
+    }
+
+    fn collect_nodes(&mut self) {
+        self.nodes = self.chunks.iter().flat_map(|c| c.nodes.iter().cloned()).collect();
+    }
+}
+
+/// 与编辑范围相交或相邻的第一个和最后一个段。
+fn find_chunks(chunks: &[Chunk], start: usize, end: usize) -> (usize, usize) {
+    let first = chunks.iter().position(|c| c.span.end >= start).unwrap_or(chunks.len() - 1);
+    let last = chunks.iter().rposition(|c| c.span.start <= end).unwrap_or(0);
+    return (first, if last < first { first } else { last });
+}
+
+/// 编辑范围位于某个段的内容中时，切分尚未切分的内容，逐层向内直到最内层的内容。
+fn split_inner(source: &str, chunks: &mut Vec<Chunk>, start: usize, end: usize) {
+    if chunks.is_empty() {
+        return;
+    }
+    let (first, last) = find_chunks(chunks, start, end);
+    if first != last {
+        return;
+    }
+    let chunk = &mut chunks[first];
+    let split = match chunk.inner {
+        Some(ref inner) if inner.span.start < start && end < inner.span.end => {
+            if inner.chunks.is_some() {
+                true
+            } else {
+                match parse_chunks(source, inner.span.clone()) {
+                    // 单独解析内容的结果须与解析整个段时的一致
+                    Ok(inner_chunks) => {
+                        let count: usize = inner_chunks.iter().map(|c| c.nodes.len()).sum();
+                        if inner_list(&mut chunk.nodes).map(|list| list.len()) == Some(count) {
+                            chunk.inner.as_mut().unwrap().chunks = Some(inner_chunks);
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                    Err(_) => false,
+                }
+            }
+        }
+        _ => { return; }
+    };
+    if !split {
+        chunk.inner = None;
+        return;
+    }
+    split_inner(source, chunk.inner.as_mut().unwrap().chunks.as_mut().unwrap(), start, end);
+}
+
+/// 重新解析 chunks 中与编辑范围相交的段，编辑范围位于已切分的内容中时只重新解析内容中的段。
+///
+/// 返回重新解析的范围；须扩大到 chunks 之外时返回 None，由外层重新解析。
+fn reparse(source: &str, chunks: &mut Vec<Chunk>, start: usize, end: usize, delta: isize) -> Result<Option<Range<usize>>> {
+    if chunks.is_empty() {
+        return Ok(None);
+    }
+    let (mut first, mut last) = find_chunks(chunks, start, end);
+    if first == last {
+        let chunk = &mut chunks[first];
+        let mut found = None;
+        if let Some(ref mut inner) = chunk.inner {
+            if inner.span.start < start && end < inner.span.end {
+                if let Some(ref mut inner_chunks) = inner.chunks {
+                    found = reparse(source, inner_chunks, start, end, delta)?;
+                    if found.is_some() {
+                        inner.span.end = (inner.span.end as isize + delta) as usize;
+                        *inner_list(&mut chunk.nodes).unwrap() = inner_chunks.iter().flat_map(|c| c.nodes.iter().cloned()).collect();
+                    }
+                }
+            }
+        }
+        if let Some(region) = found {
+            chunk.span.end = (chunk.span.end as isize + delta) as usize;
+            for chunk in chunks[first + 1..].iter_mut() {
+                shift_chunk(chunk, delta);
+            }
+            return Ok(Some(region));
+        }
+    }
+    loop {
+        let region = chunks[first].span.start..(chunks[last].span.end as isize + delta) as usize;
+        let mut expand = match cst::parse(&source[region.clone()]) {
+            Ok(tree) => !tree.children().iter().all(is_complete) || !ends_cleanly(&tree)
+                || tree.children().first().map(continues).unwrap_or(false) && first > 0,
+            Err(_) => true,
+        };
+        if !expand && last + 1 < chunks.len() && chunks[last + 1].continues {
+            expand = true;
+        }
+        if !expand {
+            let before = if first > 0 { chunks[first - 1].span.start } else { region.start };
+            let after = if last + 1 < chunks.len() { (chunks[last + 1].span.end as isize + delta) as usize } else { region.end };
+            expand = !is_boundary(source, before..after, &region);
+        }
+        if !expand {
+            let parsed = parse_chunks(source, region.clone())?;
+            let mut after: Vec<Chunk> = chunks.drain(last + 1..).collect();
+            chunks.truncate(first);
+            chunks.extend(parsed);
+            for chunk in after.iter_mut() {
+                shift_chunk(chunk, delta);
+            }
+            chunks.extend(after);
+            return Ok(Some(region));
+        }
+        if first == 0 && last + 1 == chunks.len() {
+            return Ok(None);
+        }
+        first = if first > 0 { first - 1 } else { first };
+        last = if last + 1 < chunks.len() { last + 1 } else { last };
+    }
+}
+
+/// 判断在包含相邻段的 outer 中扫描时，region 的两端是否仍是标记的边界。
+///
+/// 重新解析的范围可能与相邻的段合为一个标记（如：删除标签后相邻的文本），须一起重新解析。
+fn is_boundary(source: &str, outer: Range<usize>, region: &Range<usize>) -> bool {
+    let tree = match cst::parse(&source[outer.clone()]) {
+        Ok(tree) => tree,
+        Err(_) => { return false; }
+    };
+    let starts: Vec<usize> = tree.tokens().iter().map(|t| t.full_span().start + outer.start).collect();
+    let is_start = |offset: usize| offset == outer.start || offset == outer.end || starts.contains(&offset);
+    return is_start(region.start) && is_start(region.end);
+}
+
+/// 判断元素是否以 @elif、@else 扩展指令开始。
+fn continues(node: &SyntaxNode) -> bool {
+    if node.kind() != Some(SyntaxKind::Element) {
+        return false;
+    }
+    return node.children()[0].children().iter().any(|attr| {
+        attr.kind() == Some(SyntaxKind::Attribute) && attr.tokens().first().map(|t| t.text.starts_with("@el")).unwrap_or(false)
+    });
+}
+
+/// 判断节点及其中的元素、代码块是否都完整。
+///
+/// 不完整的元素在单独解析时由源码的结尾闭合，在上下文中（如：代码块内）则会导致解析失败，因此须逐层检查。
+fn is_complete(node: &SyntaxNode) -> bool {
+    if !node.is_complete() {
+        return false;
+    }
+    let children = node.children();
+    match node.kind() {
+        Some(SyntaxKind::Element) if children.len() > 1 => {
+            // 名称不匹配的结束标签也会被收入元素中，如：<b{{>x</b>
+            if tag_name(&children[0], TokenKind::DomTagStart) != tag_name(&children[children.len() - 1], TokenKind::DomCTag) {
+                return false;
+            }
+            return children[1..children.len() - 1].iter().all(is_complete);
+        }
+        Some(SyntaxKind::Block) if children.len() > 1 => {
+            return children[1..children.len() - 1].iter().all(|n| is_branch(n) || is_complete(n));
+        }
+        _ => true,
+    }
+}
+
+/// 开始或结束标签中的标签名。
+fn tag_name(tag: &SyntaxNode, kind: TokenKind) -> Option<&str> {
+    return tag.tokens().into_iter().find(|t| t.kind == kind).map(|t| t.text.as_str());
+}
+
+/// 判断节点是否为 else、elif 语句。
+fn is_branch(node: &SyntaxNode) -> bool {
+    if node.kind() != Some(SyntaxKind::Statement) {
+        return false;
+    }
+    return node.tokens().get(1).map(|t| t.text == "else" || t.text == "elif").unwrap_or(false);
+}
+
+/// 判断源码片段是否以完整的标记结束。
+///
+/// 片段末尾不完整的标记（如：缺少 }} 的语句、缺少 > 的标签）在上下文中会与之后的内容一起扫描，
+/// 单独解析的结果与解析整个文档的不同。
+fn ends_cleanly(tree: &SyntaxNode) -> bool {
+    let tokens = tree.tokens();
+    return match tokens.iter().rev().find(|t| t.kind != TokenKind::EOF) {
+        Some(tok) => {
+            match tok.kind {
+                TokenKind::RDelimiter | TokenKind::DomTagEnd | TokenKind::Data | TokenKind::Comment | TokenKind::DomComment => true,
+                TokenKind::Punct => tok.text.ends_with('>') || tok.text.ends_with("}}"),
+                _ => false,
+            }
+        }
+        None => true,
+    };
+}
+
+/// 段为单个元素或没有分支的代码块时，其内容对应的节点列表。
+fn inner_list(nodes: &mut NodeList) -> Option<&mut NodeList> {
+    if nodes.len() != 1 {
+        return None;
+    }
+    match &mut nodes[0] {
+        &mut Node::DomTag(_, _, ref mut children) => Some(children),
+        &mut Node::Statement(ref mut list) if list.len() == 1 => {
+            match &mut list[0] {
+                &mut Node::If(_, ref mut body, ref branches, _) if branches.is_empty() => Some(body),
+                &mut Node::For(_, _, _, ref mut body, ref for_else) if is_empty(for_else) => Some(body),
+                &mut Node::With(_, _, ref mut body, ref with_else) if is_empty(with_else) => Some(body),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_empty(node: &Node) -> bool {
+    match node {
+        &Node::Empty => true,
+        _ => false,
+    }
+}
+
+/// 元素或代码块的内容在源中的范围，base 为语法树在源中的位置。
+///
+/// 节点须完整，且内容中不含 else、elif 等将代码块分为多个分支的语句。
+fn inner_span(node: &SyntaxNode, base: usize) -> Option<Range<usize>> {
+    match node.kind() {
+        Some(SyntaxKind::Element) | Some(SyntaxKind::Block) => {}
+        _ => { return None; }
+    }
+    let children = node.children();
+    if children.len() < 2 || !is_complete(node) || children[1..children.len() - 1].iter().any(is_branch) {
+        return None;
+    }
+    return Some(children[0].full_span().end + base..children[children.len() - 1].full_span().start + base);
+}
+
+/// 将源码的给定范围切分为可独立解析的段并解析。
+///
+/// 连续的文本、注释等标记合为一段，以 @elif、@else 开始的元素与前一段合并。
+fn parse_chunks(source: &str, region: Range<usize>) -> Result<Vec<Chunk>> {
+    let tree = match cst::parse(&source[region.clone()]) {
+        Ok(tree) => tree,
+        Err(err) => { return Err(relocate(err, region.start)); }
+    };
+    // 每段的范围、是否以扩展指令开始及段只含一个子节点时的内容范围
+    let mut spans: Vec<(Range<usize>, bool, Option<Range<usize>>)> = vec![];
+    let mut last_is_token = false;
+    for child in tree.children() {
+        let span = child.full_span();
+        let span = span.start + region.start..span.end + region.start;
+        let is_token = child.kind().is_none();
+        let merge = !spans.is_empty() && ((is_token && last_is_token) || continues(child));
+        if merge {
+            let last = spans.len() - 1;
+            spans[last].0.end = span.end;
+            spans[last].2 = None;
+        } else {
+            spans.push((span, continues(child), inner_span(child, region.start)));
+        }
+        last_is_token = is_token;
+    }
+    let mut chunks = vec![];
+    for (span, continued, inner) in spans {
+        let mut scanner = BytesScanner::new(&source[span.clone()], Path::new("source"));
+        let mut nodes = match Parser::new(&mut scanner).parse_all() {
+            Ok(nodes) => nodes,
+            Err(err) => { return Err(relocate(err, span.start)); }
+        };
+        for node in nodes.iter_mut() {
+            shift(node, span.start as isize);
+        }
+        let inner = match inner {
+            Some(inner) if inner_list(&mut nodes).is_some() => Some(Inner { span: inner, chunks: None }),
+            _ => None,
+        };
+        chunks.push(Chunk { span: span, nodes: nodes, continues: continued, inner: inner });
+    }
+    return Ok(chunks);
+}
+
+/// 调整段及其内容中的段的位置。
+fn shift_chunk(chunk: &mut Chunk, delta: isize) {
+    chunk.span = (chunk.span.start as isize + delta) as usize..(chunk.span.end as isize + delta) as usize;
+    shift_list(&mut chunk.nodes, delta);
+    if let Some(ref mut inner) = chunk.inner {
+        inner.span = (inner.span.start as isize + delta) as usize..(inner.span.end as isize + delta) as usize;
+        if let Some(ref mut chunks) = inner.chunks {
+            for chunk in chunks.iter_mut() {
+                shift_chunk(chunk, delta);
+            }
+        }
+    }
+}
+
+/// 将段内的错误位置转换为文档中的位置。
+fn relocate(err: Error, base: usize) -> Error {
+    match err {
+        Error::Scan(msg, offset) => Error::Scan(msg, offset + base),
+        Error::Parse(msg, offset) => Error::Parse(msg, offset + base),
+        err => err,
+    }
+}
+
+fn shift_token(tok: &mut Token, delta: isize) {
+    // 空标记（如：未指定的 for 值变量）没有位置
+    if tok.kind() != &TokenKind::Ignore {
+        tok.1 = (tok.1 as isize + delta) as usize;
+    }
+}
+
+fn shift_list(list: &mut NodeList, delta: isize) {
+    for node in list.iter_mut() {
+        shift(node, delta);
+    }
+}
+
+/// 调整节点中所有标记的偏移。
+fn shift(node: &mut Node, delta: isize) {
+    match node {
+        &mut Node::Literal(ref mut tok) | &mut Node::Identifier(ref mut tok) |
+        &mut Node::Include(ref mut tok, _) | &mut Node::Comment(ref mut tok) => shift_token(tok, delta),
+        &mut Node::DomTag(ref mut name, ref mut attrs, ref mut children) => {
+            shift_token(name, delta);
+            for attr in attrs.iter_mut() {
+                shift_token(&mut attr.name, delta);
+                shift_list(&mut attr.value, delta);
+            }
+            shift_list(children, delta);
+        }
+        &mut Node::Root(ref mut list) | &mut Node::List(ref mut list) | &mut Node::Statement(ref mut list) |
+        &mut Node::Else(ref mut list) | &mut Node::Template(ref mut list) | &mut Node::Array(ref mut list) |
+        &mut Node::Map(ref mut list) => shift_list(list, delta),
+        &mut Node::Ternary(ref mut expr, ref mut left, ref mut right) => {
+            shift(expr, delta);
+            shift(left, delta);
+            shift(right, delta);
+        }
+        &mut Node::Binary(ref mut left, ref mut right, _) => {
+            shift(left, delta);
+            shift(right, delta);
+        }
+        &mut Node::Unary(ref mut body, _) | &mut Node::Print(ref mut body, _) => shift(body, delta),
+        &mut Node::Property(ref mut obj, ref mut params, ref mut operator) |
+        &mut Node::Method(ref mut obj, ref mut params, ref mut operator) |
+        &mut Node::OptionalProperty(ref mut obj, ref mut params, ref mut operator) |
+        &mut Node::OptionalMethod(ref mut obj, ref mut params, ref mut operator) => {
+            shift(obj, delta);
+            shift_list(params, delta);
+            shift_token(operator, delta);
+        }
+        &mut Node::Range(ref mut start, ref mut end, ref mut step, _) => {
+            shift(start, delta);
+            shift(end, delta);
+            shift(step, delta);
+        }
+        &mut Node::Slice(ref mut obj, ref mut start, ref mut end, ref mut step, ref mut operator) => {
+            shift(obj, delta);
+            shift(start, delta);
+            shift(end, delta);
+            shift(step, delta);
+            shift_token(operator, delta);
+        }
+        &mut Node::If(ref mut condition, ref mut body, ref mut branches, _) => {
+            shift(condition, delta);
+            shift_list(body, delta);
+            shift_list(branches, delta);
+        }
+        &mut Node::For(ref mut key, ref mut value, ref mut iter, ref mut body, ref mut for_else) => {
+            shift_token(key, delta);
+            shift_token(value, delta);
+            shift(iter, delta);
+            shift_list(body, delta);
+            shift(for_else, delta);
+        }
+        &mut Node::With(ref mut expr, ref mut alias, ref mut body, ref mut with_else) => {
+            shift(expr, delta);
+            shift_token(alias, delta);
+            shift_list(body, delta);
+            shift(with_else, delta);
+        }
+        &mut Node::Const(ref mut constant) => {
+            match constant {
+                &mut Constant::String(ref mut tok, _) | &mut Constant::Integer(ref mut tok, _) |
+                &mut Constant::Float(ref mut tok, _) | &mut Constant::Break(ref mut tok) |
+                &mut Constant::Continue(ref mut tok) => shift_token(tok, delta),
+                _ => {}
+            }
+        }
+        &mut Node::MapEntry(ref mut key, ref mut value) => {
+            shift_token(key, delta);
+            shift(value, delta);
+        }
+        &mut Node::Lambda(ref mut params, ref mut body) => {
+            for param in params.iter_mut() {
+                shift_token(param, delta);
+            }
+            shift(body, delta);
+        }
+        &mut Node::Empty => {}
+    }
+}
@@ -1,10 +1,12 @@
 mod breakpoint;
+mod incremental;
 
 pub use self::breakpoint::BreakPoint;
+pub use self::incremental::{Document, TextEdit};
 
 use ast;
 use ast::{Node, NodeList};
-use token::{Token, TokenKind, ascii};
+use token::{Token, TokenKind, ascii, number, string};
 use scanner::Tokenizer;
 use util::VecSliceCompare;
 use scanner::BytesScanner;
@@ -29,45 +31,83 @@ fn optimize_literal(value: &[u8]) -> (usize, usize) {
         }
         end = i;
     }
-    println!("abc:{} {} {}", start, end, value.len());
+    trace!("abc:{} {} {}", start, end, value.len());
     return (start, end);
 }
 
-fn get_operator(operator: Token) -> ast::Operator {
-    if vec!['+' as u8, ].compare(operator.value()) {
-        return ast::Operator::Add;
-    } else if vec!['-' as u8, ].compare(operator.value()) {
-        return ast::Operator::Sub;
-    } else if vec!['*' as u8, ].compare(operator.value()) {
-        return ast::Operator::Mul;
-    } else if vec!['/' as u8, ].compare(operator.value()) {
-        return ast::Operator::Div;
-    } else if vec!['%' as u8, ].compare(operator.value()) {
-        return ast::Operator::Mod;
-    } else if vec!['<' as u8, ].compare(operator.value()) {
-        return ast::Operator::Lt;
-    } else if vec!['>' as u8, ].compare(operator.value()) {
-        return ast::Operator::Gt;
-    } else if vec!['=' as u8, '=' as u8, ].compare(operator.value()) {
-        return ast::Operator::Eq;
-    } else if vec!['!' as u8, '=' as u8, ].compare(operator.value()) {
-        return ast::Operator::NotEq;
-    } else if vec!['<' as u8, '=' as u8, ].compare(operator.value()) {
-        return ast::Operator::Lte;
-    } else if vec!['>' as u8, '=' as u8, ].compare(operator.value()) {
-        return ast::Operator::Gte;
-    } else if vec!['&' as u8, '&' as u8, ].compare(operator.value()) {
-        return ast::Operator::And;
-    } else if vec!['|' as u8, '|' as u8, ].compare(operator.value()) {
-        return ast::Operator::Or;
-    } else if vec!['?' as u8, '?' as u8, ].compare(operator.value()) {
-        return ast::Operator::NullCond;
-    } else if vec!['&' as u8, ].compare(operator.value()) {
-        return ast::Operator::TestCond;
+fn get_unary_operator(operator: Token) -> ast::Operator {
+    if vec!['-' as u8, ].compare(operator.value()) {
+        return ast::Operator::Neg;
+    } else if vec!['+' as u8, ].compare(operator.value()) {
+        return ast::Operator::Pos;
     } else if vec!['!' as u8, ].compare(operator.value()) {
         return ast::Operator::Not;
     }
-    panic!("undefined operator: {:?}", operator);
+    panic!("undefined unary operator: {:?}", operator);
+}
+
+/// 二元运算的种类
+enum BinaryOperator {
+    Binary(ast::Operator),
+    /// 区间 a..b 或 a..=b(is-inclusive)，可带 step
+    Range(bool),
+}
+
+const PREC_NULL_COND: usize = 1;
+const PREC_OR: usize = 2;
+const PREC_AND: usize = 3;
+const PREC_EQUALITY: usize = 4;
+const PREC_RELATIONAL: usize = 5;
+const PREC_RANGE: usize = 6;
+const PREC_ADDITIVE: usize = 7;
+const PREC_MULTIPLICATIVE: usize = 8;
+
+/// 获取符号形式的二元运算符及其优先级，数值越大优先级越高。
+///
+/// | 优先级 | 运算符 | 结合性 |
+/// |--------|--------|--------|
+/// | 0 | `? :` 三目运算 | 右 |
+/// | 1 | `??` | 左 |
+/// | 2 | `\|\|` | 左 |
+/// | 3 | `&&` | 左 |
+/// | 4 | `==` `!=` | 左 |
+/// | 5 | `<` `<=` `>` `>=` `in` `not in` | 左 |
+/// | 6 | `..` `..=`，可带 `step` | 左 |
+/// | 7 | `+` `-` | 左 |
+/// | 8 | `*` `/` `%` | 左 |
+/// | 9 | 一元 `-` `+` `!` | 右 |
+/// | 10 | `**` | 右 |
+/// | 11 | 成员访问 `.` `[]` `()` | 左 |
+///
+/// 三目、一元、幂及成员访问不在本表中，分别由 `parse_ternary`、`parse_unary`、
+/// `parse_power` 及 `parse_member_access` 处理；`in`、`not in` 为标识符形式，
+/// 由 `take_binary_operator` 处理。
+fn binary_precedence(value: &[u8]) -> Option<(BinaryOperator, usize)> {
+    let found = match value {
+        b"??" => (BinaryOperator::Binary(ast::Operator::NullCond), PREC_NULL_COND),
+        b"||" => (BinaryOperator::Binary(ast::Operator::Or), PREC_OR),
+        b"&&" => (BinaryOperator::Binary(ast::Operator::And), PREC_AND),
+        b"==" => (BinaryOperator::Binary(ast::Operator::Eq), PREC_EQUALITY),
+        b"!=" => (BinaryOperator::Binary(ast::Operator::NotEq), PREC_EQUALITY),
+        b"<" => (BinaryOperator::Binary(ast::Operator::Lt), PREC_RELATIONAL),
+        b"<=" => (BinaryOperator::Binary(ast::Operator::Lte), PREC_RELATIONAL),
+        b">" => (BinaryOperator::Binary(ast::Operator::Gt), PREC_RELATIONAL),
+        b">=" => (BinaryOperator::Binary(ast::Operator::Gte), PREC_RELATIONAL),
+        b".." => (BinaryOperator::Range(false), PREC_RANGE),
+        b"..=" => (BinaryOperator::Range(true), PREC_RANGE),
+        b"+" => (BinaryOperator::Binary(ast::Operator::Add), PREC_ADDITIVE),
+        b"-" => (BinaryOperator::Binary(ast::Operator::Sub), PREC_ADDITIVE),
+        b"*" => (BinaryOperator::Binary(ast::Operator::Mul), PREC_MULTIPLICATIVE),
+        b"/" => (BinaryOperator::Binary(ast::Operator::Div), PREC_MULTIPLICATIVE),
+        b"%" => (BinaryOperator::Binary(ast::Operator::Mod), PREC_MULTIPLICATIVE),
+        _ => { return None; }
+    };
+    return Some(found);
+}
+
+/// 是否为字符串或模板字符串的文本，其值是内容而非符号。
+fn is_text(kind: &TokenKind) -> bool {
+    return kind == &TokenKind::String || kind == &TokenKind::Template || kind == &TokenKind::TemplatePart;
 }
 
 fn err(dev_prefix: &str, msg: String, offs: usize) -> Error {
@@ -100,11 +140,14 @@ impl<'a> Parser<'a> {
 
 
     fn skip_value(&mut self, symbols: Vec<Vec<u8>>) -> Result<Token> {
-        println!("skip_value");
+        trace!("skip_value");
         return self.take().and_then(|tok| -> Result<Token>{
-            for symbol in &symbols {
-                //println!("\n{:?}  {:?}", tok.kind(), tok.value_str());
-                if symbol.compare(tok.value()) { return Ok(tok); }
+            // 字符串及模板字符串文本的内容不作为符号匹配
+            if !is_text(tok.kind()) {
+                for symbol in &symbols {
+                    //println!("\n{:?}  {:?}", tok.kind(), tok.value_str());
+                    if symbol.compare(tok.value()) { return Ok(tok); }
+                }
             }
             self.back(tok);
             return Err(Error::None);
@@ -112,7 +155,7 @@ impl<'a> Parser<'a> {
     }
 
     fn skip_symbol(&mut self, symbols: Vec<TokenKind>) -> Result<Token> {
-        println!("skip_symbol");
+        trace!("skip_symbol");
         return self.take().and_then(|tok| -> Result<Token>{
             for symbol in &symbols {
                 if symbol == tok.kind() {
@@ -125,7 +168,7 @@ impl<'a> Parser<'a> {
     }
 
     fn skip_type(&mut self, kind: TokenKind) -> Option<Token> {
-        println!("skip_type");
+        trace!("skip_type");
         if let Ok(tok) = self.take() {
             if tok.kind() == &kind {
                 return Some(tok);
@@ -144,7 +187,7 @@ impl<'a> Parser<'a> {
     }
 
     fn check_breakpoint(&mut self) -> NoneResult {
-        println!("check_breakpoint");
+        trace!("check_breakpoint");
         if self.break_checkers.is_empty() { return Err(Error::None); }
         let mut checker = self.break_checkers.pop().unwrap();
         let result = checker.as_mut()(self);
@@ -154,7 +197,7 @@ impl<'a> Parser<'a> {
 
     /// 期望一个类型。如果未找到则产生一个错误。
     fn expect_type(&mut self, kind: TokenKind) -> Result<Token> {
-        println!("expect_type");
+        trace!("expect_type");
         return self.take().and_then(|tok| -> Result<Token>{
             if tok.kind() == &kind {
                 return Ok(tok);
@@ -173,9 +216,9 @@ impl<'a> Parser<'a> {
     }
 
     fn expect_value(&mut self, value: Vec<u8>) -> Result<Token> {
-        println!("expect_value");
+        trace!("expect_value");
         return self.take().and_then(|tok| -> Result<Token>{
-            if value.compare(tok.value()) {
+            if !is_text(tok.kind()) && value.compare(tok.value()) {
                 return Ok(tok);
             }
             self.back(tok.clone());
@@ -194,47 +237,51 @@ impl<'a> Parser<'a> {
 
     /// 解析DOM标签属性
     fn parse_dom_attr(&mut self) -> Result<ast::DomAttr> {
-        println!("parse_dom_attr");
+        trace!("parse_dom_attr");
         match self.take() {
             Ok(tok) => {
-                println!("parse_dom_attr cccccccccccccccccccccccc");
+                trace!("parse_dom_attr cccccccccccccccccccccccc");
                 if &TokenKind::DomAttrStart != tok.kind() {
                     self.back(tok);
-                    println!("parse_dom_attr ssssssssssssssss");
+                    trace!("parse_dom_attr ssssssssssssssss");
                     return Err(Error::None);
                 }
-                println!("parse_dom_attr xxxxxxxxxxxxxxxxxxxxxxx");
+                trace!("parse_dom_attr xxxxxxxxxxxxxxxxxxxxxxx");
                 let mut node = ast::DomAttr::new(tok.clone());
+                // 属性值中的代码块使用与外层相同的定界符
+                let (start, end) = self.tokenizer.delimiters();
+                let (start, end) = (String::from_utf8_lossy(start).into_owned(), String::from_utf8_lossy(end).into_owned());
                 return self.expect_type(TokenKind::DomAttrValue).and_then(|attr_val| -> NoneResult{
-                    let val = attr_val.value();
-                    let name = tok.value();
+                    let val = attr_val.value_str();
+                    let name = tok.value_str();
                     let pos = attr_val.1;
                     let mut value: Result<NodeList>;
-                    println!("parse_dom_attr bbbbbbbbbbbbbbbbbbbbb");
-                    if name[0] == '@' as u8 {
+                    trace!("parse_dom_attr bbbbbbbbbbbbbbbbbbbbb");
+                    if name.as_bytes()[0] == '@' as u8 {
                         let mut name = &name[1..name.len()];
-                        if vec!['i' as u8, 'f' as u8, ].compare(name) {} else if vec!['f' as u8, 'o' as u8, 'r' as u8, ].compare(name) {} else if vec!['e' as u8, 'l' as u8, 'i' as u8, 'f' as u8, ].compare(name) {
+                        if vec!['i' as u8, 'f' as u8, ].compare(name.as_bytes()) {} else if vec!['f' as u8, 'o' as u8, 'r' as u8, ].compare(name.as_bytes()) {} else if vec!['e' as u8, 'l' as u8, 'i' as u8, 'f' as u8, ].compare(name.as_bytes()) {
                             name = &name[2..name.len()];
-                        } else if vec!['e' as u8, 'l' as u8, 's' as u8, 'e' as u8, ].compare(name) {
+                        } else if vec!['e' as u8, 'l' as u8, 's' as u8, 'e' as u8, ].compare(name.as_bytes()) {
                             // else 不解析值
-                            println!("else不解析值");
+                            trace!("else不解析值");
                             return Error::ok();
                         } else {
                             //return Err(err("parse_dom_attr", format!("Unsupported extends command: {:?}", unsafe { from_utf8_unchecked(name) }), tok.offset()));
                             let mut inner = BytesScanner::new(val, "inner-attr".as_ref());
+                            inner.set_delimiters(&start, &end);
                             let mut buf = vec![];
                             loop {
-                                println!("parse_dom_attr in loop 111");
+                                trace!("parse_dom_attr in loop 111");
                                 match inner.scan() {
                                     Ok(mut tok) => {
-                                        println!("22FACK {:?}", tok.value_str());
+                                        trace!("22FACK {:?}", tok.value_str());
                                         tok.1 += pos - 1;
                                         buf.push(tok);
                                     }
                                     Err(Error::EOF) => { break; }
                                     Err(err) => { return Err(err); }
                                 }
-                                println!("parse_dom_attr out loop 111");
+                                trace!("parse_dom_attr out loop 111");
                             }
                             while !buf.is_empty() {
                                 inner.back_token(buf.pop().unwrap());
@@ -251,45 +298,45 @@ impl<'a> Parser<'a> {
                         }
                         let start = name.len() + 3;
                         let mut s = String::from("{{");
-                        s += unsafe { from_utf8_unchecked(name) };
+                        s += name;
                         s += " ";
-                        s += unsafe { from_utf8_unchecked(val) };
+                        s += val;
                         s += "}}";
                         s += "{{/";
-                        s += unsafe { from_utf8_unchecked(name) };
+                        s += name;
                         s += "}}";
-                        println!("K=>>>>>>>>>> {:?}", s);
-                        let mut inner = BytesScanner::new(s.as_bytes(), "inner-ext".as_ref());
+                        trace!("K=>>>>>>>>>> {:?}", s);
+                        let mut inner = BytesScanner::new(&s, "inner-ext".as_ref());
                         // 重新定位
                         let mut buf = vec![];
                         loop {
-                            println!("parse_dom_attr in loop 222222");
+                            trace!("parse_dom_attr in loop 222222");
                             match inner.scan() {
                                 Ok(mut tok) => {
-                                    println!("3333FACK {:?}", tok.value_str());
+                                    trace!("3333FACK {:?}", tok.value_str());
                                     tok.1 += pos - start;
                                     buf.push(tok);
                                 }
                                 Err(Error::EOF) => { break; }
                                 Err(err) => { return Err(err); }
                             }
-                            println!("parse_dom_attr out loop 222222");
+                            trace!("parse_dom_attr out loop 222222");
                         }
                         while !buf.is_empty() {
                             inner.back_token(buf.pop().unwrap());
                         }
                         value = Parser::new(&mut inner).parse_all();
                     } else {
-                                                let s = unsafe { from_utf8_unchecked(&val) };
-                                                println!("1=>>>>>>>>>> {:?}", s);
+                                                trace!("1=>>>>>>>>>> {:?}", val);
                         //                        println!("999999999999999999999:{:?}", attr_val.value_str());
                         let mut inner = BytesScanner::new(val, "inner-attr".as_ref());
+                        inner.set_delimiters(&start, &end);
                         let mut buf = vec![];
-                        println!("parse_dom_attr in loop");
+                        trace!("parse_dom_attr in loop");
                         loop {
                             match inner.scan() {
                                 Ok(mut tok) => {
-                                    println!("FACK {:?}", tok.value_str());
+                                    trace!("FACK {:?}", tok.value_str());
                                     tok.1 += pos - 1;
                                     buf.push(tok);
                                 }
@@ -297,7 +344,7 @@ impl<'a> Parser<'a> {
                                 Err(err) => { return Err(err); }
                             }
                         }
-                        println!("parse_dom_attr out loop");
+                        trace!("parse_dom_attr out loop");
                         while !buf.is_empty() {
                             inner.back_token(buf.pop().unwrap());
                         }
@@ -321,11 +368,11 @@ impl<'a> Parser<'a> {
     }
     /// 解析DOM标签
     fn parse_dom_tag(&mut self, tag: Token) -> Result<ast::Node> {
-        println!("parse_dom_tag");
+        trace!("parse_dom_tag");
         let mut attrs = vec![];
         let mut children = vec![];
         loop {
-            println!("parse_dom_attr in loop-");
+            trace!("parse_dom_attr in loop-");
             match self.parse_dom_attr() {
                 Ok(attr) => {
                     //println!("0=>>>>>>>>>>>>{:?}", attr);
@@ -334,26 +381,26 @@ impl<'a> Parser<'a> {
                 Err(Error::None) => { break; }
                 Err(err) => { return Err(err); }
             }
-            println!("parse_dom_attr out loop-");
+            trace!("parse_dom_attr out loop-");
         }
-        println!("parse_dom_attr out loop-zzzz");
+        trace!("parse_dom_attr out loop-zzzz");
         match self.expect_type(TokenKind::DomTagEnd) {
             Ok(tok) => {
                 // 如果是独立标签 /
-                if tok.2[0] == ascii::SLA {
+                if tok.value()[0] == ascii::SLA {
                     return Ok(Node::DomTag(tag, attrs, children));
                 }
             }
             Err(Error::None) => { return Err(Error::None); } //TODO:重新定义错误：标签未结束
             Err(err) => { return Err(err); }
         }
-        let name = tag.2.clone();
-        println!("parse_dom_attr out loop-dddddddd");
+        let name = tag.value().to_vec();
+        trace!("parse_dom_attr out loop-dddddddd");
         //todo: 考虑，没有按标准(如：html标准dom)来的情况
         self.set_breakpoint(BreakPoint::build(vec![
             BreakPoint::new(false, TokenKind::DomCTag, vec![name]),
         ]));
-        println!("parse_dom_attr out loop-qqqqqqqqqq");
+        trace!("parse_dom_attr out loop-qqqqqqqqqq");
         match self.parse_until(&mut children) {
             Ok(_) => {
                 //println!("vvvvvvvvvvvvvvv");
@@ -366,7 +413,7 @@ impl<'a> Parser<'a> {
             }
             Err(err) => { return Err(err); }
         }
-        println!("parse_dom_attr out loop-lllllll");
+        trace!("parse_dom_attr out loop-lllllll");
         self.pop_breakpoint();
 
         //        if tag.children.len() > 0 {
@@ -379,7 +426,7 @@ impl<'a> Parser<'a> {
     }
     /// 解析表达式的独立主体部分
     fn parse_primary(&mut self) -> Result<ast::Node> {
-        println!("parse_primary");
+        trace!("parse_primary");
         return self.take().and_then(|tok| -> Result<ast::Node>{
             match tok.kind() {
                 &TokenKind::Identifier => {
@@ -403,24 +450,35 @@ impl<'a> Parser<'a> {
                     if vec!['c' as u8, 'o' as u8, 'n' as u8, 't' as u8, 'i' as u8, 'n' as u8, 'u' as u8, 'e' as u8].compare(tok.value()) {
                         return Ok(Node::Const(ast::Constant::Continue(tok)));
                     }
-                    println!("Identifier:bbbbbbbbbbbbbbbbb");
+                    trace!("Identifier:bbbbbbbbbbbbbbbbb");
+                    // 单参数箭头函数，如：x => x.price
+                    match self.skip_value(vec![vec!['=' as u8, '>' as u8]]) {
+                        Ok(_) => { return self.parse_lambda_body(vec![tok]); }
+                        Err(Error::None) => {}
+                        Err(err) => { return Err(err); }
+                    }
                     return Ok(Node::Identifier(tok));
                 }
                 &TokenKind::Int => {
-                    return match self.skip_value(vec![vec!['.' as u8]]).and_then(|_| -> Result<Token> { self.expect_type(TokenKind::Int) }) {
-                        Ok(precision) => { return Ok(Node::Const(ast::Constant::Float(tok, precision))); }
-                        Err(Error::None) => {
-                            println!("Identifier:int");
-                            return Ok(Node::Const(ast::Constant::Integer(tok)));
-                        }
-                        Err(err) => { return Err(err); }
+                    return match number::parse_int(tok.value_str()) {
+                        Ok(value) => Ok(Node::Const(ast::Constant::Integer(tok, value))),
+                        Err(msg) => Err(err("parse_primary", msg, tok.offset())),
+                    };
+                }
+                &TokenKind::Float => {
+                    return match number::parse_float(tok.value_str()) {
+                        Ok(value) => Ok(Node::Const(ast::Constant::Float(tok, value))),
+                        Err(msg) => Err(err("parse_primary", msg, tok.offset())),
                     };
                 }
                 &TokenKind::Symbol => {
                     return self.parse_statement_symbol(tok);
                 }
                 &TokenKind::String => {
-                    return Ok(Node::Const(ast::Constant::String(tok)));
+                    return self.parse_string(tok);
+                }
+                &TokenKind::Template | &TokenKind::TemplatePart => {
+                    return self.parse_template(tok);
                 }
                 _ => {
                     return Err(err("parse_primary", format!("unexpected token: {:?}", tok.value_str()), tok.offset()));
@@ -436,27 +494,104 @@ impl<'a> Parser<'a> {
             }
         });
     }
+    /// 解析字符串常量并处理转义符
+    fn parse_string(&mut self, tok: Token) -> Result<ast::Node> {
+        return match string::unescape(tok.value_str()) {
+            Ok(value) => Ok(Node::Const(ast::Constant::String(tok, value))),
+            Err((msg, offs)) => Err(err("parse_string", msg, tok.offset() + offs)),
+        };
+    }
+    /// 解析模板字符串，如：`hello ${name}`，tok 为第一段文本
+    fn parse_template(&mut self, tok: Token) -> Result<ast::Node> {
+        let mut parts = vec![];
+        let mut tok = tok;
+        loop {
+            if !tok.value().is_empty() {
+                match string::unescape(tok.value_str()) {
+                    Ok(value) => {
+                        let chunk = Token(TokenKind::String, tok.offset(), tok.value_str().to_string());
+                        parts.push(Node::Const(ast::Constant::String(chunk, value)));
+                    }
+                    Err((msg, offs)) => { return Err(err("parse_template", msg, tok.offset() + offs)); }
+                }
+            }
+            if tok.kind() == &TokenKind::Template {
+                break;
+            }
+            // 文本之后为 ${...} 内嵌的表达式，扫描器在匹配的 } 之后继续输出下一段文本
+            let node = self.parse_expression();
+            if node.is_err() { return node; }
+            parts.push(node.unwrap());
+            tok = match self.take() {
+                Ok(next) => {
+                    if next.kind() != &TokenKind::Template && next.kind() != &TokenKind::TemplatePart {
+                        return Err(err("parse_template", format!("expected character {}, found {:?}", ascii::RBK as char, next.value_str()), next.offset()));
+                    }
+                    next
+                }
+                Err(Error::EOF) => {
+                    return Err(err("parse_template", format!("expected character {}, but EOF", ascii::RBK as char), tok.offset()));
+                }
+                Err(err) => { return Err(err); }
+            };
+        }
+        return Ok(Node::Template(parts));
+    }
     /// 解析成员访问
     fn parse_member_access(&mut self) -> Result<ast::Node> {
-        println!("parse_member_access");
+        trace!("parse_member_access");
         let node = self.parse_primary();
         if node.is_err() { return node; }
         let mut node = node.unwrap();
-        let symbols = vec![vec!['.' as u8], vec!['[' as u8], vec!['(' as u8]];
+        let symbols = vec![vec!['.' as u8], vec!['[' as u8], vec!['(' as u8], vec!['?' as u8, '.' as u8], vec!['?' as u8, '[' as u8]];
         loop {
             match self.skip_value(symbols.clone()) {
                 Ok(operator) => {
-                    if symbols[0].compare(operator.value()) {
+                    if symbols[3].compare(operator.value()) {
+                        // 可选链 a?.b 或可选调用 f?.(x)
+                        match self.skip_value(vec![vec!['(' as u8]]) {
+                            Ok(_) => {
+                                match self.parse_group(vec![')' as u8]) {
+                                    Ok(list) => {
+                                        node = Node::OptionalMethod(Box::new(node), list, operator);
+                                    }
+                                    Err(err) => { return Err(err); }
+                                }
+                                continue;
+                            }
+                            Err(Error::None) => {}
+                            Err(err) => { return Err(err); }
+                        }
+                        match self.expect_type(TokenKind::Identifier) {
+                            Ok(tok) => {
+                                let name = tok.value_str().to_string();
+                                node = Node::OptionalProperty(Box::new(node), vec![Node::Const(ast::Constant::String(tok, name))], operator);
+                            }
+                            Err(err) => { return Err(err); }
+                        }
+                    } else if symbols[4].compare(operator.value()) {
+                        // 可选索引 a?[0]，可选切片由运算符 ?[ 区分
+                        match self.parse_index(node, operator) {
+                            Ok(Node::Property(obj, list, operator)) => {
+                                node = Node::OptionalProperty(obj, list, operator);
+                            }
+                            Ok(index) => {
+                                node = index;
+                            }
+                            Err(err) => { return Err(err); }
+                        }
+                    } else if symbols[0].compare(operator.value()) {
                         match self.expect_type(TokenKind::Identifier) {
                             Ok(tok) => {
-                                node = Node::Property(Box::new(node), vec![Node::Const(ast::Constant::String(tok))], operator);
+                                let name = tok.value_str().to_string();
+                                node = Node::Property(Box::new(node), vec![Node::Const(ast::Constant::String(tok, name))], operator);
                             }
                             Err(err) => { return Err(err); }
                         }
                     } else if symbols[1].compare(operator.value()) {
-                        match self.parse_group(vec![']' as u8]) {
-                            Ok(list) => {
-                                node = Node::Property(Box::new(node), list, operator);
+                        match self.parse_index(node, operator) {
+                            Ok(index) => {
+                                node = index;
                             }
                             Err(err) => { return Err(err); }
                         }
@@ -475,109 +610,179 @@ impl<'a> Parser<'a> {
         }
         return Ok(node);
     }
-    /// 解析一元运算
+    /// 解析切片中可省略的部分，遇到 : 或 ] 时返回 Empty。
+    fn parse_slice_part(&mut self) -> Result<ast::Node> {
+        match self.skip_value(vec![vec![':' as u8], vec![']' as u8]]) {
+            Ok(tok) => {
+                self.back(tok);
+                return Ok(Node::Empty);
+            }
+            Err(Error::None) => {}
+            Err(err) => { return Err(err); }
+        }
+        return self.parse_expression();
+    }
+    /// 解析索引访问 obj[index] 或切片 obj[start:end:step]
+    fn parse_index(&mut self, node: ast::Node, operator: Token) -> Result<ast::Node> {
+        let start = self.parse_slice_part();
+        if start.is_err() { return start; }
+        let start = start.unwrap();
+        match self.skip_value(vec![vec![':' as u8]]) {
+            Ok(_) => {}
+            Err(Error::None) => {
+                if let Node::Empty = start {
+                    return Err(err("parse_index", format!("expected index expression"), operator.offset()));
+                }
+                // 普通索引访问
+                let mut list = vec![start];
+                match self.skip_value(vec![vec![',' as u8]]) {
+                    Ok(_) => {
+                        match self.parse_group(vec![']' as u8]) {
+                            Ok(mut rest) => { list.append(&mut rest); }
+                            Err(err) => { return Err(err); }
+                        }
+                    }
+                    Err(Error::None) => {
+                        match self.expect_value(vec![']' as u8]) {
+                            Ok(_) => {}
+                            Err(err) => { return Err(err); }
+                        }
+                    }
+                    Err(err) => { return Err(err); }
+                }
+                return Ok(Node::Property(Box::new(node), list, operator));
+            }
+            Err(err) => { return Err(err); }
+        }
+        let end = self.parse_slice_part();
+        if end.is_err() { return end; }
+        let mut step = Node::Empty;
+        match self.skip_value(vec![vec![':' as u8]]) {
+            Ok(_) => {
+                match self.parse_slice_part() {
+                    Ok(node) => { step = node; }
+                    err => { return err; }
+                }
+            }
+            Err(Error::None) => {}
+            Err(err) => { return Err(err); }
+        }
+        match self.expect_value(vec![']' as u8]) {
+            Ok(_) => {}
+            Err(err) => { return Err(err); }
+        }
+        return Ok(Node::Slice(Box::new(node), Box::new(start), Box::new(end.unwrap()), Box::new(step), operator));
+    }
+    /// 解析一元运算，一元运算符为右结合，如：-x、!!x
     fn parse_unary(&mut self) -> Result<ast::Node> {
-        println!("parse_unary");
+        trace!("parse_unary");
         match self.skip_value(vec![vec!['-' as u8], vec!['+' as u8], vec!['!' as u8]]) {
             Ok(operator) => {
-                //TODO: - = neg, + = pos
-                let node = self.parse_member_access();
+                let node = self.parse_unary();
                 if node.is_err() { return node; }
-                return Ok(Node::Unary(Box::new(node.unwrap()), get_operator(operator)));
+                return Ok(Node::Unary(Box::new(node.unwrap()), get_unary_operator(operator)));
             }
             Err(Error::None) => {}
             Err(err) => {
-                println!("parse_unary:err:{:?}", err);
+                trace!("parse_unary:err:{:?}", err);
                 return Err(err);
             }
         }
-        return self.parse_member_access();
+        return self.parse_power();
     }
-    /// 解析乘除运算
-    fn parse_binary_mdm(&mut self) -> Result<ast::Node> {
-        println!("parse_binary_mdm");
-        let node = self.parse_unary();
+    /// 解析幂运算，右结合且优先于其左侧的一元运算：-2 ** 2 == -4，2 ** -1 == 0.5
+    fn parse_power(&mut self) -> Result<ast::Node> {
+        let node = self.parse_member_access();
         if node.is_err() { return node; }
-        let mut node = node.unwrap();
-        loop {
-            match self.skip_value(vec![vec!['*' as u8], vec!['/' as u8], vec!['%' as u8]]) {
-                Ok(operator) => {
-                    let right = self.parse_unary();
-                    if right.is_err() { return right; }
-                    node = Node::Binary(Box::new(node), Box::new(right.unwrap()), get_operator(operator));
-                }
-                Err(Error::None) => { break; }
-                Err(err) => { return Err(err); }
+        match self.skip_value(vec![vec!['*' as u8, '*' as u8]]) {
+            Ok(_) => {
+                let right = self.parse_unary();
+                if right.is_err() { return right; }
+                return Ok(Node::Binary(Box::new(node.unwrap()), Box::new(right.unwrap()), ast::Operator::Pow));
             }
+            Err(Error::None) => {}
+            Err(err) => { return Err(err); }
         }
-        return Ok(node);
+        return node;
     }
-    /// 解析加减运算
-    fn parse_binary_as(&mut self) -> Result<ast::Node> {
-        println!("parse_binary_as");
-        let node = self.parse_binary_mdm();
-        if node.is_err() { return node; }
-        let mut node = node.unwrap();
-        loop {
-            match self.skip_value(vec![vec!['+' as u8], vec!['-' as u8]]) {
-                Ok(operator) => {
-                    let right = self.parse_binary_mdm();
-                    if right.is_err() { return right; }
-                    node = Node::Binary(Box::new(node), Box::new(right.unwrap()), get_operator(operator));
+    /// 读取一个二元运算符，返回所消费的标记、运算符及其优先级。
+    /// 如果不是二元运算符，则退回标记并返回 Error::None。
+    fn take_binary_operator(&mut self) -> Result<(Vec<Token>, BinaryOperator, usize)> {
+        let tok = match self.take() {
+            Ok(tok) => tok,
+            Err(err) => { return Err(err); }
+        };
+        let found = match tok.kind() {
+            &TokenKind::Symbol => binary_precedence(tok.value()),
+            &TokenKind::Identifier if tok.value() == b"in" => {
+                Some((BinaryOperator::Binary(ast::Operator::In), PREC_RELATIONAL))
+            }
+            &TokenKind::Identifier if tok.value() == b"not" => {
+                match self.skip_value(vec![vec!['i' as u8, 'n' as u8, ]]) {
+                    Ok(next) => {
+                        return Ok((vec![tok, next], BinaryOperator::Binary(ast::Operator::NotIn), PREC_RELATIONAL));
+                    }
+                    Err(Error::None) => None,
+                    Err(err) => { return Err(err); }
                 }
-                Err(Error::None) => { break; }
-                Err(err) => { return Err(err); }
+            }
+            _ => None,
+        };
+        match found {
+            Some((operator, prec)) => { return Ok((vec![tok], operator, prec)); }
+            None => {
+                self.back(tok);
+                return Err(Error::None);
             }
         }
-        return Ok(node);
     }
-    /// 解析比较运算
-    fn parse_compare(&mut self) -> Result<ast::Node> {
-        println!("parse_compare");
-        let node = self.parse_binary_as();
+    /// 以优先级爬升法解析二元运算，只处理优先级不低于 min_prec 的运算符。
+    /// 优先级见 [`binary_precedence`]。
+    fn parse_binary(&mut self, min_prec: usize) -> Result<ast::Node> {
+        let node = self.parse_unary();
         if node.is_err() { return node; }
         let mut node = node.unwrap();
         loop {
-            match self.skip_value(vec![vec!['=' as u8, '=' as u8]
-                                       , vec!['!' as u8, '=' as u8]
-                                       , vec!['<' as u8, '=' as u8]
-                                       , vec!['>' as u8, '=' as u8]
-                                       , vec!['<' as u8]
-                                       , vec!['>' as u8]]) {
-                Ok(operator) => {
-                    let right = self.parse_binary_as();
-                    if right.is_err() { return right; }
-                    node = Node::Binary(Box::new(node), Box::new(right.unwrap()), get_operator(operator));
-                }
+            let (mut toks, operator, prec) = match self.take_binary_operator() {
+                Ok(found) => found,
                 Err(Error::None) => { break; }
                 Err(err) => { return Err(err); }
+            };
+            if prec < min_prec {
+                while !toks.is_empty() {
+                    self.back(toks.pop().unwrap());
+                }
+                break;
             }
-        }
-        return Ok(node);
-    }
-    /// 解析逻辑运算
-    fn parse_logic(&mut self) -> Result<ast::Node> {
-        println!("parse_logic");
-        let node = self.parse_compare();
-        if node.is_err() { return node; }
-        let mut node = node.unwrap();
-        loop {
-            match self.skip_value(vec![vec!['?' as u8, '?' as u8], vec!['|' as u8, '|' as u8], vec!['&' as u8, '&' as u8], vec!['&' as u8]]) {
-                Ok(operator) => {
-                    let right = self.parse_compare();
-                    if right.is_err() { return right; }
-                    node = Node::Binary(Box::new(node), Box::new(right.unwrap()), get_operator(operator));
+            // 所有二元运算均为左结合，右侧只接受更高优先级的运算
+            let right = self.parse_binary(prec + 1);
+            if right.is_err() { return right; }
+            match operator {
+                BinaryOperator::Binary(operator) => {
+                    node = Node::Binary(Box::new(node), Box::new(right.unwrap()), operator);
+                }
+                BinaryOperator::Range(inclusive) => {
+                    let mut step = Node::Empty;
+                    match self.skip_value(vec![vec!['s' as u8, 't' as u8, 'e' as u8, 'p' as u8, ]]) {
+                        Ok(_) => {
+                            match self.parse_binary(prec + 1) {
+                                Ok(node) => { step = node; }
+                                err => { return err; }
+                            }
+                        }
+                        Err(Error::None) => {}
+                        Err(err) => { return Err(err); }
+                    }
+                    node = Node::Range(Box::new(node), Box::new(right.unwrap()), Box::new(step), inclusive);
                 }
-                Err(Error::None) => { break; }
-                Err(err) => { return Err(err); }
             }
         }
         return Ok(node);
     }
     /// 解析三目运算
     fn parse_ternary(&mut self) -> Result<ast::Node> {
-        println!("parse_ternary");
-        let node = self.parse_logic();
+        trace!("parse_ternary");
+        let node = self.parse_binary(PREC_NULL_COND);
         if node.is_err() { return node; }
         let mut node = node.unwrap();
         loop {
@@ -603,9 +808,61 @@ impl<'a> Parser<'a> {
     fn parse_expression(&mut self) -> Result<ast::Node> {
         self.parse_ternary()
     }
+    /// 尝试解析箭头函数的参数列表 `(a, b) =>`，左括号已被读取。
+    ///
+    /// 如果不是箭头函数则退回所有已读取的标记并返回 `Error::None`。
+    fn parse_lambda_params(&mut self) -> Result<Vec<Token>> {
+        let mut taken: Vec<Token> = vec![];
+        let mut params = vec![];
+        let mut matched = false;
+        loop {
+            let tok = match self.take() {
+                Ok(tok) => tok,
+                Err(Error::EOF) => { break; }
+                Err(err) => { return Err(err); }
+            };
+            taken.push(tok.clone());
+            // 期望参数名：开头或逗号之后
+            let expect_param = params.is_empty() && taken.len() == 1 || taken.len() > 1 && vec![',' as u8].compare(taken[taken.len() - 2].value());
+            if tok.kind() == &TokenKind::Identifier && expect_param {
+                params.push(tok);
+                continue;
+            }
+            if vec![')' as u8].compare(tok.value()) && (taken.len() == 1 || !expect_param) {
+                matched = true;
+                break;
+            }
+            if vec![',' as u8].compare(tok.value()) && !expect_param {
+                continue;
+            }
+            break;
+        }
+        if matched {
+            match self.skip_value(vec![vec!['=' as u8, '>' as u8]]) {
+                Ok(_) => { return Ok(params); }
+                Err(Error::None) => {}
+                Err(err) => { return Err(err); }
+            }
+        }
+        while !taken.is_empty() {
+            self.back(taken.pop().unwrap());
+        }
+        return Err(Error::None);
+    }
+    /// 解析箭头函数的函数体，`=>` 已被读取
+    fn parse_lambda_body(&mut self, params: Vec<Token>) -> Result<ast::Node> {
+        for (i, param) in params.iter().enumerate() {
+            if params[..i].iter().any(|p| p.value() == param.value()) {
+                return Err(err("parse_lambda", format!("duplicate parameter {}", param.value_str()), param.offset()));
+            }
+        }
+        let body = self.parse_expression();
+        if body.is_err() { return body; }
+        return Ok(Node::Lambda(params, Box::new(body.unwrap())));
+    }
     /// 解析一个组
     fn parse_group(&mut self, end: Vec<u8>) -> Result<NodeList> {
-        println!("parse_group");
+        trace!("parse_group");
         let mut list = vec![];
         match self.skip_value(vec![end.clone()]) {
             Ok(_) => { return Ok(list); }
@@ -637,7 +894,7 @@ impl<'a> Parser<'a> {
     }
     /// 解析一个map结构
     fn parse_map(&mut self) -> Result<NodeList> {
-        println!("parse_map");
+        trace!("parse_map");
         let mut list = vec![];
         match self.skip_value(vec![vec!['}' as u8]]) {
             Ok(_) => { return Ok(list); }
@@ -670,7 +927,7 @@ impl<'a> Parser<'a> {
                 }
                 Err(err) => { return Err(err); }
             }
-            println!("...........................................");
+            trace!("...........................................");
             match self.skip_value(vec![vec![',' as u8], vec!['}' as u8]]) {
                 Ok(tok) => {
                     if vec!['}' as u8].compare(tok.value()) {
@@ -691,7 +948,7 @@ impl<'a> Parser<'a> {
             Err(err) => { return Err(err); }
         }
         self.set_breakpoint(BreakPoint::build(vec![
-            BreakPoint::new(true, TokenKind::Ignore, vec![vec!['{' as u8, '{' as u8, ], vec!['/' as u8], key]),
+            BreakPoint::statement(true, vec![vec!['/' as u8], key]),
         ]));
         let mut body = vec![];
         match self.parse_until(&mut body) {
@@ -705,22 +962,22 @@ impl<'a> Parser<'a> {
         return Ok(Node::Else(body));
     }
     fn parse_if(&mut self, is_else_if: bool) -> Result<ast::Node> {
-        println!("parse_if");
+        trace!("parse_if");
         let condition = self.parse_expression();
         if condition.is_err() { return condition; }
         //跳过边界
         match self.expect_type(TokenKind::RDelimiter) {
             Ok(_) => {}
             Err(err) => {
-                println!("zzzzzzzzz");
+                trace!("zzzzzzzzz");
                 return Err(err);
             }
         }
-        println!("xxxxxxxxxxxxxxxxxxxxx");
+        trace!("xxxxxxxxxxxxxxxxxxxxx");
         self.set_breakpoint(BreakPoint::build(vec![
-            BreakPoint::new(true, TokenKind::Ignore, vec![vec!['{' as u8, '{' as u8, ], vec!['e' as u8, 'l' as u8, 'i' as u8, 'f' as u8, ]]),
-            BreakPoint::new(true, TokenKind::Ignore, vec![vec!['{' as u8, '{' as u8, ], vec!['e' as u8, 'l' as u8, 's' as u8, 'e' as u8, ]]),
-            BreakPoint::new(true, TokenKind::Ignore, vec![vec!['{' as u8, '{' as u8, ], vec!['/' as u8], vec!['i' as u8, 'f' as u8, ]]),
+            BreakPoint::statement(true, vec![vec!['e' as u8, 'l' as u8, 'i' as u8, 'f' as u8, ]]),
+            BreakPoint::statement(true, vec![vec!['e' as u8, 'l' as u8, 's' as u8, 'e' as u8, ]]),
+            BreakPoint::statement(true, vec![vec!['/' as u8], vec!['i' as u8, 'f' as u8, ]]),
         ]));
         let mut body = vec![];
         match self.parse_until(&mut body) {
@@ -811,8 +1068,8 @@ impl<'a> Parser<'a> {
         }
 
         self.set_breakpoint(BreakPoint::build(vec![
-            BreakPoint::new(true, TokenKind::Ignore, vec![vec!['{' as u8, '{' as u8, ], vec!['e' as u8, 'l' as u8, 's' as u8, 'e' as u8, ]]),
-            BreakPoint::new(true, TokenKind::Ignore, vec![vec!['{' as u8, '{' as u8, ], vec!['/' as u8], vec!['f' as u8, 'o' as u8, 'r' as u8, ]]),
+            BreakPoint::statement(true, vec![vec!['e' as u8, 'l' as u8, 's' as u8, 'e' as u8, ]]),
+            BreakPoint::statement(true, vec![vec!['/' as u8], vec!['f' as u8, 'o' as u8, 'r' as u8, ]]),
         ]));
         let mut body = vec![];
         match self.parse_until(&mut body) {
@@ -857,8 +1114,105 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// 解析 {{include 'name'}}，模板名称必须是字符串常量以便静态分析依赖
+    fn parse_include(&mut self, keyword: Token) -> Result<ast::Node> {
+        match self.take() {
+            Ok(tok) => {
+                if tok.kind() != &TokenKind::String {
+                    return Err(err("parse_include", format!("expected template name string, found {:?}", tok.value_str()), tok.offset()));
+                }
+                return match string::unescape(tok.value_str()) {
+                    Ok(name) => Ok(Node::Include(tok, name)),
+                    Err((msg, offs)) => Err(err("parse_include", msg, tok.offset() + offs)),
+                };
+            }
+            Err(Error::EOF) => {
+                return Err(err("parse_include", format!("expected template name"), keyword.offset()));
+            }
+            Err(err) => { return Err(err); }
+        }
+    }
+
+    fn parse_with(&mut self, keyword: Token) -> Result<ast::Node> {
+        let mut expr: Node;
+        match self.parse_expression() {
+            Ok(node) => {
+                expr = node;
+            }
+            Err(err) => {
+                return Err(err);
+            }
+        }
+        match self.expect_value(vec!['a' as u8, 's' as u8, ]) {
+            Ok(_) => {}
+            Err(err) => {
+                return Err(err);
+            }
+        }
+        let mut alias: Token;
+        match self.expect_type(TokenKind::Identifier) {
+            Ok(tok) => {
+                alias = tok;
+            }
+            Err(err) => {
+                return Err(err);
+            }
+        }
+        //跳过边界
+        match self.expect_type(TokenKind::RDelimiter) {
+            Ok(_) => {}
+            Err(err) => { return Err(err); }
+        }
+
+        self.set_breakpoint(BreakPoint::build(vec![
+            BreakPoint::statement(true, vec![vec!['e' as u8, 'l' as u8, 's' as u8, 'e' as u8, ]]),
+            BreakPoint::statement(true, vec![vec!['/' as u8], vec!['w' as u8, 'i' as u8, 't' as u8, 'h' as u8, ]]),
+        ]));
+        let mut body = vec![];
+        match self.parse_until(&mut body) {
+            Ok(_) => {}
+            Err(Error::None) => {
+                return Err(err("parse_with", format!("with 命令未结束：必须至少包含 else 或 /with其中之一"), keyword.offset()));
+            }
+            Err(err) => { return Err(err); }
+        }
+        self.pop_breakpoint();
+        let mut with_else = Node::Empty;
+        match self.skip_type(TokenKind::LDelimiter).and_then(|tok| -> Option<Token>{
+            return self.skip_type(TokenKind::Identifier).or_else(|| -> Option<Token>{
+                self.back(tok);
+                return None;
+            });
+        }).ok_or(Error::None).and_then(|tok| -> Result<ast::Node> {
+            //else
+            if vec!['e' as u8, 'l' as u8, 's' as u8, 'e' as u8, ]
+                .compare(tok.value()) {
+                return self.parse_else(vec!['w' as u8, 'i' as u8, 't' as u8, 'h' as u8, ]);
+            }
+
+            self.back(tok);
+            return Err(Error::None);
+        }) {
+            Ok(node) => {
+                with_else = node;
+            }
+            Err(Error::None) => {}
+            err => { return err; }
+        }
+
+        match self.expect_type(TokenKind::LDelimiter)
+            .and_then(|_| -> Result<Token>{ self.expect_value(vec!['/' as u8]) })
+            .and_then(|_| -> Result<Token>{ self.expect_value(vec!['w' as u8, 'i' as u8, 't' as u8, 'h' as u8, ]) }) {
+            Ok(_) => { return Ok(Node::With(Box::new(expr), alias, body, Box::new(with_else))); }
+            Err(Error::None) => {
+                return Err(err("parse_with", format!("with 命令未结束：必须以/with结束"), keyword.offset()));
+            }
+            Err(err) => { return Err(err); }
+        }
+    }
+
     fn parse_print(&mut self, escape: bool) -> Result<ast::Node> {
-        println!("parse_print");
+        trace!("parse_print");
         let mut body: Node;
         match self.parse_expression() {
             Ok(node) => {
@@ -896,18 +1250,34 @@ impl<'a> Parser<'a> {
                 }
             };
         }
+        if vec!['(' as u8].compare(tok.value()) {
+            // 箭头函数，如：(a, b) => a + b
+            match self.parse_lambda_params() {
+                Ok(params) => { return self.parse_lambda_body(params); }
+                Err(Error::None) => {}
+                Err(err) => { return Err(err); }
+            }
+            // 括号分组
+            let node = self.parse_expression();
+            if node.is_err() { return node; }
+            match self.expect_value(vec![')' as u8]) {
+                Ok(_) => {}
+                Err(err) => { return Err(err); }
+            }
+            return node;
+        }
         return Err(err("parse_statement", format!("unexpected symbol {}", tok.value_str()), tok.offset()));
     }
     /// 解析代码段
     fn parse_statement(&mut self) -> Result<ast::Node> {
-        println!("parse_statement");
+        trace!("parse_statement");
         let mut list = vec![];
         loop {
             match self.take().and_then(|tok| -> Result<ast::Node>{
                 return match tok.kind() {
                     &TokenKind::RDelimiter => { return Err(Error::None); }
                     &TokenKind::Identifier => {
-                        println!("yyyyyyyyyyyyyyyyyyyy");
+                        trace!("yyyyyyyyyyyyyyyyyyyy");
                         //if
                         if vec!['i' as u8, 'f' as u8, ].compare(tok.value()) {
                             return self.parse_if(false);
@@ -915,11 +1285,22 @@ impl<'a> Parser<'a> {
                         if vec!['f' as u8, 'o' as u8, 'r' as u8, ].compare(tok.value()) {
                             return self.parse_for();
                         }
+                        if vec!['w' as u8, 'i' as u8, 't' as u8, 'h' as u8, ].compare(tok.value()) {
+                            return self.parse_with(tok);
+                        }
+                        if vec!['i' as u8, 'n' as u8, 'c' as u8, 'l' as u8, 'u' as u8, 'd' as u8, 'e' as u8, ].compare(tok.value()) {
+                            return self.parse_include(tok);
+                        }
                         self.back(tok);
                         return self.parse_print(true);
                     }
                     &TokenKind::Symbol => {
-                        return self.parse_statement_symbol(tok);
+                        if vec!['!' as u8, '!' as u8, ].compare(tok.value()) {
+                            return self.parse_print(false);
+                        }
+                        // 以符号开始的表达式，如：-x、(a + b)、[1, 2]
+                        self.back(tok);
+                        return self.parse_print(true);
                     }
                     _ => {
                         self.back(tok);
@@ -938,12 +1319,12 @@ impl<'a> Parser<'a> {
     }
 
     fn parse(&mut self) -> Result<ast::Node> {
-        println!("parse");
+        trace!("parse");
         return self.take().and_then(|tok| -> Result<ast::Node>{
             match tok.kind() {
                 &TokenKind::DomTagStart => {
                     let ret= self.parse_dom_tag(tok);
-                    println!("parse parse_dom_tag end");
+                    trace!("parse parse_dom_tag end");
                     return ret;
                 }
                 &TokenKind::LDelimiter => {
@@ -957,8 +1338,15 @@ impl<'a> Parser<'a> {
                     //                    let tok = Token(TokenKind::Data, tok.1 + start, tok.1 + end);
                     return Ok(Node::Literal(tok));
                 }
+                &TokenKind::Literal => {
+                    // {{%}}...{{%}} 中的内容原样输出
+                    return Ok(Node::Literal(tok));
+                }
+                &TokenKind::Comment | &TokenKind::DomComment => {
+                    return Ok(Node::Comment(tok));
+                }
                 _ => {
-                    println!("TODO: no parsing token: {:?}", tok);
+                    trace!("TODO: no parsing token: {:?}", tok);
                     return Ok(Node::Empty);
                 }
             }
@@ -966,10 +1354,10 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_until(&mut self, buf: &mut NodeList) -> NoneResult {
-        println!("parse_until");
+        trace!("parse_until");
         self.tokenizer.mark();
         loop {
-            println!("parse_until loop in");
+            trace!("parse_until loop in");
             match self.check_breakpoint() {
                 //println!("zzzzzzzzzzzzz");
                 Ok(_) => {
@@ -980,14 +1368,14 @@ impl<'a> Parser<'a> {
                 Err(Error::None) => {}
                 err => { return err; }
             }
-            println!("parse_until loop parse");
+            trace!("parse_until loop parse");
             match self.parse() {
                 Ok(Node::Empty) => {}
                 Ok(node) => { buf.push(node) }
                 Err(Error::None) | Err(Error::EOF) => { break; }
                 Err(err) => { return Err(err); }
             }
-            println!("parse_until loop out");
+            trace!("parse_until loop out");
         }
         // TODO: 还原点
         self.tokenizer.reset();
@@ -997,7 +1385,7 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse_all(&mut self) -> Result<NodeList> {
-        println!("parse_all");
+        trace!("parse_all");
         let mut list = vec![];
         loop {
             match self.parse() {
@@ -1016,7 +1404,7 @@ impl<'a> Parser<'a> {
     fn extend_if(&mut self, tag: Token, mut attrs: Vec<ast::DomAttr>, children: NodeList
                  , condition: Box<Node>
                  , others: &mut NodeList, is_else_if: bool) -> Result<Node> {
-        println!("extend_if");
+        trace!("extend_if");
         let mut branches: NodeList = vec![];
         let mut size = others.len();
         while size > 0 && !others.is_empty() {
@@ -1026,7 +1414,7 @@ impl<'a> Parser<'a> {
             match others[i] {
                 Node::DomTag(_, ref next_attrs, _) => {
                     for next_attr in next_attrs {
-                        if next_attr.name.2[0] != '@' as u8 {
+                        if next_attr.name.value()[0] != '@' as u8 {
                             continue;
                         }
                         let len = next_attr.name.2.len();
@@ -1059,7 +1447,7 @@ impl<'a> Parser<'a> {
                     Node::DomTag(tag, mut attrs, children) => {
                         // TODO: 扩展指令
                         while !attrs.is_empty() {
-                            if attrs[0].name.2[0] != '@' as u8 {
+                            if attrs[0].name.value()[0] != '@' as u8 {
                                 continue;
                             }
                             let len = attrs[0].name.2.len();
@@ -1086,7 +1474,7 @@ impl<'a> Parser<'a> {
     fn extend_for(&mut self, tag: Token, mut attrs: Vec<ast::DomAttr>, children: NodeList
                   , key: Token, value: Token, iter: Box<Node>
                   , others: &mut NodeList) -> Result<Node> {
-        println!("extend_for");
+        trace!("extend_for");
         let mut for_else = Node::Empty;
         let mut size = others.len();
         while size > 0 && !others.is_empty() {
@@ -1096,7 +1484,7 @@ impl<'a> Parser<'a> {
             match others[i] {
                 Node::DomTag(_, ref next_attrs, _) => {
                     for next_attr in next_attrs {
-                        if next_attr.name.2[0] != '@' as u8 {
+                        if next_attr.name.value()[0] != '@' as u8 {
                             continue;
                         }
                         let len = next_attr.name.2.len();
@@ -1116,7 +1504,7 @@ impl<'a> Parser<'a> {
                     Node::DomTag(tag, mut attrs, children) => {
                         // TODO: 扩展指令
                         while !attrs.is_empty() {
-                            if attrs[0].name.2[0] != '@' as u8 {
+                            if attrs[0].name.value()[0] != '@' as u8 {
                                 continue;
                             }
                             let len = attrs[0].name.2.len();
@@ -1143,9 +1531,9 @@ impl<'a> Parser<'a> {
 
     fn extend_dom(&mut self, tag: Token, mut attrs: Vec<ast::DomAttr>, children: NodeList
                   , list: &mut NodeList, is_else_if: bool) -> Result<Node> {
-        println!("extend_dom");
+        trace!("extend_dom");
         for i in 0..attrs.len() {
-            if attrs[i].name.2[0] != '@' as u8 {
+            if attrs[i].name.value()[0] != '@' as u8 {
                 continue;
             }
             let len = attrs[i].name.2.len();
@@ -1160,7 +1548,7 @@ impl<'a> Parser<'a> {
 
                 let mut attr = attrs.remove(i);
                 if attr.value.len() == 0 {
-                    println!("extend_dom:{:?}", attr);
+                    trace!("extend_dom:{:?}", attr);
                     return Err(Error::Message("非法".to_string()));
                 }
 
@@ -1182,7 +1570,7 @@ impl<'a> Parser<'a> {
             } else if vec!['f' as u8, 'o' as u8, 'r' as u8].compare(&attrs[i].name.value()[1..len]) {
                 let mut attr = attrs.remove(i);
                 if attr.value.len() == 0 {
-                    println!("extend_dom:{:?}", attr);
+                    trace!("extend_dom:{:?}", attr);
                     return Err(Error::Message("非法".to_string()));
                 }
 
@@ -1210,7 +1598,7 @@ impl<'a> Parser<'a> {
     }
 
     fn extend_commands(&mut self, list: &mut NodeList) -> NoneResult {
-        println!("extend_commands");
+        trace!("extend_commands");
         let mut buf: NodeList = vec![];
         while !list.is_empty() {
             let mut node = list.remove(0);
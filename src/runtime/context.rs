@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use Result;
+use super::{Value, Function, Invoker};
+
+/// 定义模板渲染时的变量作用域链。
+#[derive(Debug, Clone)]
+pub struct Context {
+    scopes: Vec<HashMap<String, Value>>,
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context { scopes: vec![HashMap::new()] }
+    }
+
+    /// 在全局作用域中设置一个变量。
+    pub fn set<V: Into<Value>>(&mut self, name: &str, value: V) {
+        self.scopes[0].insert(name.to_string(), value.into());
+    }
+
+    /// 注册一个宿主函数，模板中以 `name(args...)` 调用。
+    pub fn register_function<F>(&mut self, name: &str, f: F)
+        where F: Fn(&mut dyn Invoker, Vec<Value>) -> Result<Value> + Send + Sync + 'static {
+        self.set(name, Function::host(name, f));
+    }
+
+    /// 在当前作用域中声明一个变量，它会遮蔽外层的同名变量。
+    pub fn declare(&mut self, name: &str, value: Value) {
+        let last = self.scopes.len() - 1;
+        self.scopes[last].insert(name.to_string(), value);
+    }
+
+    /// 由内向外查找变量。
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(value) = scope.get(name) {
+                return Some(value);
+            }
+        }
+        return None;
+    }
+
+    /// 进入一个新的作用域。
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// 退出当前作用域，全局作用域不会被移除。
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+}
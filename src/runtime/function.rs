@@ -0,0 +1,116 @@
+use std::fmt;
+use std::sync::Arc;
+use ast::{Node, NodeList};
+use Result;
+use super::Value;
+
+/// 调用模板中的函数值，由解释器实现。
+///
+/// 宿主函数通过它回调作为参数传入的箭头函数，如：`sort_by(items, (x) => x.price)`。
+pub trait Invoker {
+    fn call(&mut self, func: &Value, args: Vec<Value>) -> Result<Value>;
+}
+
+/// 由宿主注册的函数，模板可在多个线程中同时渲染，因此函数须是 `Send + Sync` 的。
+pub type HostFunction = dyn Fn(&mut dyn Invoker, Vec<Value>) -> Result<Value> + Send + Sync;
+
+/// 定义模板中可调用的函数。
+pub enum Function {
+    /// 箭头函数及其创建时捕获的变量
+    Lambda(Lambda),
+    /// 宿主函数及其名称
+    Host(String, Arc<HostFunction>),
+}
+
+impl Function {
+    /// 将闭包包装为宿主函数值。
+    pub fn host<F>(name: &str, f: F) -> Value
+        where F: Fn(&mut dyn Invoker, Vec<Value>) -> Result<Value> + Send + Sync + 'static {
+        Value::Function(Arc::new(Function::Host(name.to_string(), Arc::new(f))))
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Function::Lambda(ref lambda) => write!(f, "Lambda({:?})", lambda.params),
+            &Function::Host(ref name, _) => write!(f, "Host({})", name),
+        }
+    }
+}
+
+/// 箭头函数在运行时的表示。
+#[derive(Debug)]
+pub struct Lambda {
+    /// 参数名
+    pub params: Vec<String>,
+    /// 函数体表达式
+    pub body: Node,
+    /// 创建时从外层作用域捕获的自由变量
+    pub captured: Vec<(String, Value)>,
+}
+
+impl Lambda {
+    /// 收集函数体中引用但未被参数绑定的变量名。
+    pub fn free_names(params: &Vec<String>, body: &Node) -> Vec<String> {
+        let mut names = vec![];
+        let mut bound = params.clone();
+        collect_names(body, &mut bound, &mut names);
+        return names;
+    }
+}
+
+fn collect_list(list: &NodeList, bound: &mut Vec<String>, names: &mut Vec<String>) {
+    for node in list {
+        collect_names(node, bound, names);
+    }
+}
+
+fn collect_names(node: &Node, bound: &mut Vec<String>, names: &mut Vec<String>) {
+    match node {
+        &Node::Identifier(ref tok) => {
+            let name = tok.value_str().to_string();
+            if !bound.contains(&name) && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        &Node::Ternary(ref expr, ref left, ref right) => {
+            collect_names(expr, bound, names);
+            collect_names(left, bound, names);
+            collect_names(right, bound, names);
+        }
+        &Node::Binary(ref left, ref right, _) => {
+            collect_names(left, bound, names);
+            collect_names(right, bound, names);
+        }
+        &Node::Unary(ref body, _) => collect_names(body, bound, names),
+        &Node::Property(ref obj, ref params, _) | &Node::Method(ref obj, ref params, _) |
+        &Node::OptionalProperty(ref obj, ref params, _) | &Node::OptionalMethod(ref obj, ref params, _) => {
+            collect_names(obj, bound, names);
+            collect_list(params, bound, names);
+        }
+        &Node::Range(ref start, ref end, ref step, _) => {
+            collect_names(start, bound, names);
+            collect_names(end, bound, names);
+            collect_names(step, bound, names);
+        }
+        &Node::Slice(ref obj, ref start, ref end, ref step, _) => {
+            collect_names(obj, bound, names);
+            collect_names(start, bound, names);
+            collect_names(end, bound, names);
+            collect_names(step, bound, names);
+        }
+        &Node::Template(ref list) | &Node::Array(ref list) | &Node::Map(ref list) => collect_list(list, bound, names),
+        &Node::MapEntry(_, ref value) => collect_names(value, bound, names),
+        &Node::Lambda(ref params, ref body) => {
+            // 内层参数只在内层函数体中遮蔽外层变量
+            let depth = bound.len();
+            for param in params {
+                bound.push(param.value_str().to_string());
+            }
+            collect_names(body, bound, names);
+            bound.truncate(depth);
+        }
+        _ => {}
+    }
+}
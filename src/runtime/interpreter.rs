@@ -0,0 +1,854 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+use ast::{Node, NodeList, DomAttr, Operator, Constant, Visitor, VisitResult};
+use token::{Token, TokenKind};
+use {Error, Result};
+use super::{Value, Loop, Range, Context, Function, Lambda, Invoker, Sink};
+
+/// HTML 中不需要闭合标签的元素。
+static VOID_ELEMENTS: [&'static str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input",
+    "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// 判断是否为不需要闭合标签的元素，不区分大小写。
+pub fn is_void_element(name: &str) -> bool {
+    VOID_ELEMENTS.contains(&name.to_lowercase().as_str())
+}
+
+fn err(dev_prefix: &str, msg: String, offs: usize) -> Error {
+    Error::Visit(format!("{}:{}", dev_prefix, msg), offs)
+}
+
+/// 循环控制状态
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+}
+
+/// 对输出内容进行 HTML 转义。
+pub fn escape_html(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => buf.push_str("&amp;"),
+            '<' => buf.push_str("&lt;"),
+            '>' => buf.push_str("&gt;"),
+            '"' => buf.push_str("&quot;"),
+            '\'' => buf.push_str("&#39;"),
+            _ => buf.push(c),
+        }
+    }
+    return buf;
+}
+
+/// 未定义的变量或属性的处理方式。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Undefined {
+    /// 视为 null，输出空字符串（默认）
+    Empty,
+    /// 视为 null，但输出调试标记，如：`[undefined: user.nmae]`
+    Debug,
+    /// 产生带有源位置的错误
+    Strict,
+}
+
+/// 解析 `{{include 'name'}}` 引入的模板，由持有模板加载器的宿主实现。
+pub trait Includer {
+    /// 使用给定的解释器渲染名为 name 的模板，引入的模板与引入者共享变量。
+    fn include(&self, name: &str, interpreter: &mut Interpreter) -> VisitResult;
+}
+
+/// 引入模板的最大嵌套层数，超过时视为循环引入。
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+/// 以遍历语法树的方式直接渲染模板。
+///
+/// 表达式的计算结果被压入值栈，由上层节点弹出使用。
+pub struct Interpreter<'a> {
+    context: &'a mut Context,
+    parent: Option<&'a Context>,
+    output: Sink<'a>,
+    stack: Vec<Value>,
+    loops: Vec<Arc<Loop>>,
+    flow: Flow,
+    undefined: Undefined,
+    escape: bool,
+    includer: Option<&'a dyn Includer>,
+    depth: usize,
+}
+
+impl<'a> Interpreter<'a> {
+    /// 创建解释器，内容不经缓冲直接写入 output，即使不调用 `render` 也不会遗留未写出的内容。
+    pub fn new(context: &'a mut Context, output: &'a mut dyn Write) -> Interpreter<'a> {
+        let mut sink = Sink::new(output);
+        sink.set_capacity(0);
+        return Interpreter::with_sink(context, sink);
+    }
+
+    /// 使用给定的输出创建解释器，以便设置缓冲大小和刷新点。
+    pub fn with_sink(context: &'a mut Context, output: Sink<'a>) -> Interpreter<'a> {
+        Interpreter {
+            context: context,
+            parent: None,
+            output: output,
+            stack: vec![],
+            loops: vec![],
+            flow: Flow::Normal,
+            undefined: Undefined::Empty,
+            escape: true,
+            includer: None,
+            depth: 0,
+        }
+    }
+
+    /// 设置未定义的变量、不存在的属性以及 null 的属性的处理方式。
+    ///
+    /// 无论哪种方式，`?.`、`?[` 访问和 `??` 的左侧都允许未定义的值。
+    pub fn set_undefined(&mut self, undefined: Undefined) {
+        self.undefined = undefined;
+    }
+
+    /// 设置是否对 `{{expr}}` 的输出进行 HTML 转义，默认转义；`{{!expr}}` 始终不转义。
+    pub fn set_escape(&mut self, escape: bool) {
+        self.escape = escape;
+    }
+
+    /// 设置引入模板的解析方式，未设置时 `{{include}}` 产生错误。
+    pub fn set_includer(&mut self, includer: &'a dyn Includer) {
+        self.includer = Some(includer);
+    }
+
+    /// 设置只读的外层上下文，在解释器自身的上下文中找不到的变量到外层上下文中查找。
+    pub fn set_parent(&mut self, parent: &'a Context) {
+        self.parent = Some(parent);
+    }
+
+    /// 查找变量，先查找解释器自身的上下文，再查找外层上下文。
+    fn lookup(&self, name: &str) -> Option<&Value> {
+        return self.context.get(name).or_else(|| self.parent.and_then(|parent| parent.get(name)));
+    }
+
+    /// 按配置处理一个未定义的值，path 用于调试标记。
+    fn undefined(&self, path: String, msg: String, offset: usize) -> Result<Value> {
+        match self.undefined {
+            Undefined::Empty => Ok(Value::Null),
+            Undefined::Debug => Ok(Value::Undefined(path)),
+            Undefined::Strict => Err(err("undefined", msg, offset)),
+        }
+    }
+
+    /// 渲染一个语法树节点集合。
+    ///
+    /// 最外层的渲染成功完成时写出所有缓冲的内容并刷新输出；渲染出错时缓冲中尚未写出的内容被丢弃。
+    pub fn render(&mut self, list: &NodeList) -> VisitResult {
+        self.visit_list(list)?;
+        if self.depth > 0 {
+            // 被引入的模板，由引入者在渲染结束时刷新
+            return Ok(());
+        }
+        let offset = list.last().and_then(|node| node.offset()).unwrap_or(0);
+        return self.output.flush().map_err(|e| err("write", format!("{}", e), offset));
+    }
+
+    /// 计算表达式的值。
+    pub fn eval(&mut self, node: &Node) -> Result<Value> {
+        self.visit(node)?;
+        return self.stack.pop().ok_or(err("eval", format!("expression produces no value: {:?}", node), node.offset().unwrap_or(0)));
+    }
+
+    /// 写出内容，失败时以 offset 定位，即正在渲染的节点的位置。
+    fn write(&mut self, buf: &[u8], offset: usize) -> VisitResult {
+        return self.output.write(buf).map_err(|e| err("write", format!("{}", e), offset));
+    }
+
+    fn write_attr(&mut self, attr: &DomAttr) -> VisitResult {
+        let name = attr.name.value();
+        if name[0] == '@' as u8 {
+            // 未被解析器处理的扩展指令不输出
+            return Ok(());
+        }
+        let offset = attr.name.offset();
+        self.write(b" ", offset)?;
+        self.write(name, offset)?;
+        if attr.value.is_empty() {
+            return Ok(());
+        }
+        self.write(b"=\"", offset)?;
+        self.visit_list(&attr.value)?;
+        return self.write(b"\"", offset);
+    }
+
+    fn arithmetic(&self, left: Value, right: Value, operator: &Operator, offset: usize) -> Result<Value> {
+        match (left, right) {
+            (Value::Int(a), Value::Int(b)) => {
+                let value = match operator {
+                    &Operator::Add => a.checked_add(b),
+                    &Operator::Sub => a.checked_sub(b),
+                    &Operator::Mul => a.checked_mul(b),
+                    &Operator::Div | &Operator::Mod if b == 0 => {
+                        return Err(err("visit_binary", format!("division by zero"), offset));
+                    }
+                    &Operator::Div => a.checked_div(b),
+                    &Operator::Mod => a.checked_rem(b),
+                    _ => None,
+                };
+                return value.map(Value::Int).ok_or(err("visit_binary", format!("integer overflow: {} {:?} {}", a, operator, b), offset));
+            }
+            (Value::String(a), b) if operator == &Operator::Add => {
+                return Ok(Value::String(format!("{}{}", a, b)));
+            }
+            (a, Value::String(b)) if operator == &Operator::Add => {
+                return Ok(Value::String(format!("{}{}", a, b)));
+            }
+            (Value::Array(mut a), Value::Array(mut b)) if operator == &Operator::Add => {
+                a.append(&mut b);
+                return Ok(Value::Array(a));
+            }
+            (left, right) => {
+                let (a, b) = match (to_float(&left), to_float(&right)) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => {
+                        return Err(err("visit_binary", format!("unsupported operand types for {:?}: {} and {}", operator, left.type_name(), right.type_name()), offset));
+                    }
+                };
+                let value = match operator {
+                    &Operator::Add => a + b,
+                    &Operator::Sub => a - b,
+                    &Operator::Mul => a * b,
+                    &Operator::Div => a / b,
+                    &Operator::Mod => a % b,
+                    _ => { return Err(err("visit_binary", format!("unsupported operator {:?}", operator), offset)); }
+                };
+                return Ok(Value::Float(value));
+            }
+        }
+    }
+
+    fn power(&self, base: Value, exp: Value, offset: usize) -> Result<Value> {
+        match (base, exp) {
+            (Value::Int(a), Value::Int(b)) if b >= 0 => {
+                if b > u32::max_value() as i64 {
+                    return Err(err("visit_binary", format!("integer overflow: {} ** {}", a, b), offset));
+                }
+                return a.checked_pow(b as u32).map(Value::Int).ok_or(err("visit_binary", format!("integer overflow: {} ** {}", a, b), offset));
+            }
+            (base, exp) => {
+                match (to_float(&base), to_float(&exp)) {
+                    (Some(a), Some(b)) => { return Ok(Value::Float(a.powf(b))); }
+                    _ => {
+                        return Err(err("visit_binary", format!("unsupported operand types for Pow: {} and {}", base.type_name(), exp.type_name()), offset));
+                    }
+                }
+            }
+        }
+    }
+
+    fn compare(&self, left: &Value, right: &Value, operator: &Operator, offset: usize) -> Result<Value> {
+        let ordering = match (left, right) {
+            (&Value::String(ref a), &Value::String(ref b)) => a.partial_cmp(b),
+            _ => match (to_float(left), to_float(right)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => None,
+            },
+        };
+        let ordering = match ordering {
+            Some(ordering) => ordering,
+            None => {
+                return Err(err("visit_binary", format!("cannot compare {} with {}", left.type_name(), right.type_name()), offset));
+            }
+        };
+        let value = match operator {
+            &Operator::Gt => ordering.is_gt(),
+            &Operator::Gte => ordering.is_ge(),
+            &Operator::Lt => ordering.is_lt(),
+            &Operator::Lte => ordering.is_le(),
+            _ => false,
+        };
+        return Ok(Value::Bool(value));
+    }
+
+    /// 将可迭代的值转换为(键,值)迭代器及其长度。
+    fn iterate(&self, iter: Value, offset: usize) -> Result<(usize, Box<dyn Iterator<Item=(Value, Value)>>)> {
+        match iter {
+            Value::Null | Value::Undefined(_) => Ok((0, Box::new(Vec::new().into_iter()))),
+            Value::Array(items) => {
+                Ok((items.len(), Box::new(items.into_iter().enumerate().map(|(i, v)| (Value::Int(i as i64), v)))))
+            }
+            Value::Map(entries) => {
+                Ok((entries.len(), Box::new(entries.into_iter().map(|(k, v)| (Value::String(k), v)))))
+            }
+            Value::String(s) => {
+                let chars: Vec<char> = s.chars().collect();
+                Ok((chars.len(), Box::new(chars.into_iter().enumerate().map(|(i, c)| (Value::Int(i as i64), Value::String(c.to_string()))))))
+            }
+            Value::Range(r) => {
+                Ok((r.len(), Box::new(r.iter().enumerate().map(|(i, v)| (Value::Int(i as i64), Value::Int(v))))))
+            }
+            other => Err(err("visit_for", format!("{} is not iterable", other.type_name()), offset)),
+        }
+    }
+
+    /// 计算可省略的整数参数，Empty 返回 None。
+    fn eval_optional_int(&mut self, node: &Node, what: &str, offset: usize) -> Result<Option<i64>> {
+        if let &Node::Empty = node {
+            return Ok(None);
+        }
+        match self.eval(node)? {
+            Value::Int(i) => Ok(Some(i)),
+            other => Err(err("eval", format!("{} must be int, found {}", what, other.type_name()), offset)),
+        }
+    }
+
+    fn eval_args(&mut self, params: &NodeList) -> Result<Vec<Value>> {
+        let mut args = vec![];
+        for param in params {
+            args.push(self.eval(param)?);
+        }
+        return Ok(args);
+    }
+
+    /// 计算成员访问链中的一环。
+    ///
+    /// 可选链 `?.`、`?[` 遇到 null 时返回 None，使整个访问链的结果为 null 而不继续访问。
+    fn member(&mut self, node: &Node) -> Result<Option<Value>> {
+        match node {
+            &Node::Property(ref obj, ref params, ref operator) => self.property(obj, params, operator, false),
+            &Node::OptionalProperty(ref obj, ref params, ref operator) => self.property(obj, params, operator, true),
+            &Node::Method(ref obj, ref params, ref operator) => self.method(obj, params, operator, false),
+            &Node::OptionalMethod(ref obj, ref params, ref operator) => self.method(obj, params, operator, true),
+            &Node::Slice(ref obj, ref start, ref end, ref step, ref operator) => self.slice(obj, start, end, step, operator),
+            _ => Ok(Some(self.eval(node)?)),
+        }
+    }
+
+    fn property(&mut self, obj: &Node, params: &NodeList, operator: &Token, optional: bool) -> Result<Option<Value>> {
+        let object = match self.member(obj)? {
+            Some(object) => object,
+            None => { return Ok(None); }
+        };
+        if optional && object.is_null() {
+            return Ok(None);
+        }
+        let key = match params.first() {
+            Some(node) => self.eval(node)?,
+            None => { return Err(err("visit_property", format!("expected property name"), operator.offset())); }
+        };
+        match object.get(&key) {
+            Some(value) => Ok(Some(value)),
+            None if optional => Ok(Some(Value::Null)),
+            None => {
+                let path = match key {
+                    Value::String(ref name) if operator.value() == b"." || operator.value() == b"?." => format!("{}.{}", describe(obj), name),
+                    ref key => format!("{}[{}]", describe(obj), key),
+                };
+                let msg = if object.is_null() {
+                    format!("cannot read property {} of {}", key, object.type_name())
+                } else {
+                    format!("undefined property {} on {}", key, object.type_name())
+                };
+                self.undefined(path, msg, operator.offset()).map(Some)
+            }
+        }
+    }
+
+    fn method(&mut self, obj: &Node, params: &NodeList, operator: &Token, optional: bool) -> Result<Option<Value>> {
+        let callee = match obj {
+            &Node::Property(ref receiver, ref names, _) |
+            &Node::OptionalProperty(ref receiver, ref names, _) if names.len() == 1 => {
+                // receiver.name(...) 形式的方法调用
+                let receiver = match self.member(receiver)? {
+                    Some(receiver) => receiver,
+                    None => { return Ok(None); }
+                };
+                if let &Node::OptionalProperty(..) = obj {
+                    if receiver.is_null() {
+                        return Ok(None);
+                    }
+                }
+                let name = match self.eval(&names[0])? {
+                    Value::String(name) => name,
+                    other => { return Err(err("visit_method", format!("illegal method name {}", other), operator.offset())); }
+                };
+                let args = self.eval_args(params)?;
+                return self.call_method(receiver, &name, args, operator).map(Some);
+            }
+            &Node::Identifier(ref name) => {
+                match self.lookup(name.value_str()).cloned() {
+                    Some(func @ Value::Function(_)) => func,
+                    Some(Value::Null) | None if optional => { return Ok(None); }
+                    Some(other) => {
+                        return Err(err("visit_method", format!("{} is not a function, found {}", name.value_str(), other.type_name()), name.offset()));
+                    }
+                    None => {
+                        return Err(err("visit_method", format!("undefined function {}", name.value_str()), name.offset()));
+                    }
+                }
+            }
+            _ => {
+                match self.member(obj)? {
+                    Some(callee) => callee,
+                    None => { return Ok(None); }
+                }
+            }
+        };
+        if optional && callee.is_null() {
+            return Ok(None);
+        }
+        if let Value::Function(_) = callee {
+            let args = self.eval_args(params)?;
+            return self.invoke(&callee, args, operator.offset()).map(Some);
+        }
+        return Err(err("visit_method", format!("{} is not callable", callee.type_name()), operator.offset()));
+    }
+
+    fn slice(&mut self, obj: &Node, start: &Node, end: &Node, step: &Node, operator: &Token) -> Result<Option<Value>> {
+        let object = match self.member(obj)? {
+            Some(object) => object,
+            None => { return Ok(None); }
+        };
+        if object.is_null() && operator.value() == b"?[" {
+            return Ok(None);
+        }
+        let first = self.eval_optional_int(start, "slice start", operator.offset())?;
+        let last = self.eval_optional_int(end, "slice end", operator.offset())?;
+        let step = self.eval_optional_int(step, "slice step", operator.offset())?.unwrap_or(1);
+        if step == 0 {
+            return Err(err("visit_slice", format!("slice step cannot be zero"), operator.offset()));
+        }
+        match object.slice(first, last, step) {
+            Some(value) => Ok(Some(value)),
+            None => Err(err("visit_slice", format!("{} cannot be sliced", object.type_name()), operator.offset())),
+        }
+    }
+
+    fn call_method(&mut self, receiver: Value, name: &str, args: Vec<Value>, operator: &Token) -> Result<Value> {
+        match (&receiver, name) {
+            (&Value::Loop(ref info), "cycle") => {
+                return Ok(info.cycle(&args));
+            }
+            _ => {}
+        }
+        // 以属性保存的函数，如：{fmt: (x) => x}.fmt(1)
+        if let Some(func @ Value::Function(_)) = receiver.get(&Value::String(name.to_string())) {
+            return self.invoke(&func, args, operator.offset());
+        }
+        return Err(err("visit_method", format!("undefined method {} on {}", name, receiver.type_name()), operator.offset()));
+    }
+}
+
+/// 描述成员访问链，用于未定义值的调试标记，如：user.profile[0]。
+fn describe(node: &Node) -> String {
+    match node {
+        &Node::Identifier(ref tok) => tok.value_str().to_string(),
+        &Node::Property(ref obj, ref params, ref operator) | &Node::OptionalProperty(ref obj, ref params, ref operator) => {
+            match (params.first(), operator.value()) {
+                (Some(&Node::Const(Constant::String(_, ref name))), b".") => format!("{}.{}", describe(obj), name),
+                (Some(&Node::Const(Constant::String(_, ref name))), b"?.") => format!("{}?.{}", describe(obj), name),
+                (Some(&Node::Const(Constant::String(_, ref name))), _) => format!("{}['{}']", describe(obj), name),
+                (Some(&Node::Const(Constant::Integer(_, index))), _) => format!("{}[{}]", describe(obj), index),
+                _ => format!("{}[...]", describe(obj)),
+            }
+        }
+        &Node::Method(ref obj, _, _) | &Node::OptionalMethod(ref obj, _, _) => format!("{}(...)", describe(obj)),
+        _ => format!("(...)"),
+    }
+}
+
+fn to_float(value: &Value) -> Option<f64> {
+    match value {
+        &Value::Int(i) => Some(i as f64),
+        &Value::Float(f) => Some(f),
+        _ => None,
+    }
+}
+
+impl<'a> Visitor for Interpreter<'a> {
+    fn visit_list(&mut self, list: &NodeList) -> VisitResult {
+        for n in list {
+            match self.visit(n) {
+                Ok(_) | Err(Error::None) => {}
+                err => { return err; }
+            }
+            if self.flow != Flow::Normal {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    fn visit_literal(&mut self, tok: &Token) -> VisitResult {
+        let mut text = tok.value();
+        while let Some(end) = self.output.find_end_tag(text) {
+            self.write(&text[..end], tok.offset())?;
+            self.output.flush().map_err(|e| err("write", format!("{}", e), tok.offset()))?;
+            text = &text[end..];
+        }
+        return self.write(text, tok.offset());
+    }
+
+    fn visit_dom_tag(&mut self, name: &Token, attrs: &Vec<DomAttr>, children: &NodeList) -> VisitResult {
+        let offset = name.offset();
+        self.write(b"<", offset)?;
+        self.write(name.value(), offset)?;
+        for attr in attrs {
+            self.write_attr(attr)?;
+        }
+        if children.is_empty() && is_void_element(name.value_str()) {
+            return self.write(b"/>", offset);
+        }
+        self.write(b">", offset)?;
+        self.visit_list(children)?;
+        self.write(b"</", offset)?;
+        self.write(name.value(), offset)?;
+        self.write(b">", offset)?;
+        return self.output.end_tag(name.value_str()).map_err(|e| err("write", format!("{}", e), offset));
+    }
+
+    fn visit_ternary(&mut self, expr: &Node, left: &Node, right: &Node) -> VisitResult {
+        let value = if self.eval(expr)?.is_true() { self.eval(left)? } else { self.eval(right)? };
+        self.stack.push(value);
+        return Ok(());
+    }
+
+    fn visit_binary(&mut self, left: &Node, right: &Node, operator: &Operator) -> VisitResult {
+        let lhs = if operator == &Operator::NullCond {
+            // ?? 的左侧允许未定义的值，即使在严格模式下
+            let undefined = self.undefined;
+            self.undefined = Undefined::Empty;
+            let lhs = self.eval(left);
+            self.undefined = undefined;
+            lhs?
+        } else {
+            self.eval(left)?
+        };
+        let offset = left.offset().or(right.offset()).unwrap_or(0);
+        let value = match operator {
+            &Operator::And => {
+                Value::Bool(lhs.is_true() && self.eval(right)?.is_true())
+            }
+            &Operator::Or => {
+                Value::Bool(lhs.is_true() || self.eval(right)?.is_true())
+            }
+            &Operator::NullCond => {
+                if lhs.is_null() { self.eval(right)? } else { lhs }
+            }
+            &Operator::Eq => {
+                let rhs = self.eval(right)?;
+                Value::Bool(lhs.equals(&rhs))
+            }
+            &Operator::NotEq => {
+                let rhs = self.eval(right)?;
+                Value::Bool(!lhs.equals(&rhs))
+            }
+            &Operator::Gt | &Operator::Gte | &Operator::Lt | &Operator::Lte => {
+                let rhs = self.eval(right)?;
+                self.compare(&lhs, &rhs, operator, offset)?
+            }
+            &Operator::In | &Operator::NotIn => {
+                let rhs = self.eval(right)?;
+                match rhs.contains(&lhs) {
+                    Some(found) => Value::Bool(found == (operator == &Operator::In)),
+                    None => {
+                        return Err(err("visit_binary", format!("cannot test membership of {} in {}", lhs.type_name(), rhs.type_name()), offset));
+                    }
+                }
+            }
+            &Operator::Pow => {
+                let rhs = self.eval(right)?;
+                self.power(lhs, rhs, offset)?
+            }
+            _ => {
+                let rhs = self.eval(right)?;
+                self.arithmetic(lhs, rhs, operator, offset)?
+            }
+        };
+        self.stack.push(value);
+        return Ok(());
+    }
+
+    fn visit_unary(&mut self, body: &Node, operator: &Operator) -> VisitResult {
+        let value = self.eval(body)?;
+        let offset = body.offset().unwrap_or(0);
+        let value = match (operator, value) {
+            (&Operator::Not, value) => Value::Bool(!value.is_true()),
+            (&Operator::Neg, Value::Int(i)) => {
+                match i.checked_neg() {
+                    Some(i) => Value::Int(i),
+                    None => { return Err(err("visit_unary", format!("integer overflow: -({})", i), offset)); }
+                }
+            }
+            (&Operator::Neg, Value::Float(f)) => Value::Float(-f),
+            (&Operator::Pos, value @ Value::Int(_)) | (&Operator::Pos, value @ Value::Float(_)) => value,
+            (_, value) => {
+                return Err(err("visit_unary", format!("unsupported operand type for {:?}: {}", operator, value.type_name()), offset));
+            }
+        };
+        self.stack.push(value);
+        return Ok(());
+    }
+
+    fn visit_property(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult {
+        let value = self.property(obj, params, operator, false)?.unwrap_or(Value::Null);
+        self.stack.push(value);
+        return Ok(());
+    }
+
+    fn visit_method(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult {
+        let value = self.method(obj, params, operator, false)?.unwrap_or(Value::Null);
+        self.stack.push(value);
+        return Ok(());
+    }
+
+    fn visit_optional_property(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult {
+        let value = self.property(obj, params, operator, true)?.unwrap_or(Value::Null);
+        self.stack.push(value);
+        return Ok(());
+    }
+
+    fn visit_optional_method(&mut self, obj: &Node, params: &NodeList, operator: &Token) -> VisitResult {
+        let value = self.method(obj, params, operator, true)?.unwrap_or(Value::Null);
+        self.stack.push(value);
+        return Ok(());
+    }
+
+    fn visit_range(&mut self, start: &Node, end: &Node, step: &Node, inclusive: &bool) -> VisitResult {
+        let offset = start.offset().or(end.offset()).unwrap_or(0);
+        let first = self.eval_optional_int(start, "range start", start.offset().unwrap_or(offset))?.unwrap_or(0);
+        let last = self.eval_optional_int(end, "range end", end.offset().unwrap_or(offset))?.unwrap_or(0);
+        let step_offset = step.offset().unwrap_or(offset);
+        let step = self.eval_optional_int(step, "range step", step_offset)?.unwrap_or(1);
+        if step == 0 {
+            return Err(err("visit_range", format!("range step cannot be zero"), step_offset));
+        }
+        let range = if *inclusive { Range::inclusive(first, last, step) } else { Range::new(first, last, step) };
+        self.stack.push(Value::Range(range));
+        return Ok(());
+    }
+
+    fn visit_slice(&mut self, obj: &Node, start: &Node, end: &Node, step: &Node, operator: &Token) -> VisitResult {
+        let value = self.slice(obj, start, end, step, operator)?.unwrap_or(Value::Null);
+        self.stack.push(value);
+        return Ok(());
+    }
+
+    fn visit_const(&mut self, tok: &Constant) -> VisitResult {
+        let value = match tok {
+            &Constant::Break(_) => {
+                self.flow = Flow::Break;
+                Value::Null
+            }
+            &Constant::Continue(_) => {
+                self.flow = Flow::Continue;
+                Value::Null
+            }
+            &Constant::None => Value::Null,
+            &Constant::True => Value::Bool(true),
+            &Constant::False => Value::Bool(false),
+            &Constant::String(_, ref value) => Value::String(value.clone()),
+            &Constant::Integer(_, value) => Value::Int(value),
+            &Constant::Float(_, value) => Value::Float(value),
+        };
+        self.stack.push(value);
+        return Ok(());
+    }
+
+    fn visit_template(&mut self, parts: &NodeList) -> VisitResult {
+        let mut buf = String::new();
+        for part in parts {
+            let value = self.eval(part)?;
+            buf += &format!("{}", value);
+        }
+        self.stack.push(Value::String(buf));
+        return Ok(());
+    }
+
+    fn visit_identifier(&mut self, tok: &Token) -> VisitResult {
+        let value = match self.lookup(tok.value_str()) {
+            Some(value) => value.clone(),
+            None => self.undefined(tok.value_str().to_string(), format!("undefined variable {}", tok.value_str()), tok.offset())?,
+        };
+        self.stack.push(value);
+        return Ok(());
+    }
+
+    fn visit_if(&mut self, condition: &Node, body: &NodeList, branches: &NodeList, _is_else_if: &bool) -> VisitResult {
+        if self.eval(condition)?.is_true() {
+            return self.visit_list(body);
+        }
+        for branch in branches {
+            match branch {
+                &Node::If(ref condition, ref body, _, _) => {
+                    if self.eval(condition)?.is_true() {
+                        return self.visit_list(body);
+                    }
+                }
+                &Node::Else(ref body) => {
+                    return self.visit_list(body);
+                }
+                _ => {}
+            }
+        }
+        return Ok(());
+    }
+
+    fn visit_for(&mut self, key: &Token, value: &Token, iter: &Node, body: &NodeList, for_else: &Node) -> VisitResult {
+        let iter = self.eval(iter)?;
+        let (length, items) = self.iterate(iter, key.offset())?;
+        if length == 0 {
+            return self.visit(for_else);
+        }
+        let parent = self.loops.last().cloned();
+        for (index, (k, v)) in items.enumerate() {
+            let info = Arc::new(Loop::new(index, length, parent.clone()));
+            self.context.push_scope();
+            if value.kind() == &TokenKind::Ignore {
+                self.context.declare(key.value_str(), v);
+            } else {
+                self.context.declare(key.value_str(), k);
+                self.context.declare(value.value_str(), v);
+            }
+            self.context.declare("loop", Value::Loop(info.clone()));
+            self.loops.push(info);
+            let result = self.visit_list(body);
+            self.loops.pop();
+            self.context.pop_scope();
+            result?;
+            match self.flow {
+                Flow::Break => {
+                    self.flow = Flow::Normal;
+                    break;
+                }
+                Flow::Continue => {
+                    self.flow = Flow::Normal;
+                }
+                Flow::Normal => {}
+            }
+        }
+        return Ok(());
+    }
+
+    fn visit_with(&mut self, expr: &Node, alias: &Token, body: &NodeList, with_else: &Node) -> VisitResult {
+        let value = self.eval(expr)?;
+        if value.is_null() {
+            return self.visit(with_else);
+        }
+        self.context.push_scope();
+        self.context.declare(alias.value_str(), value);
+        let result = self.visit_list(body);
+        self.context.pop_scope();
+        return result;
+    }
+
+    fn visit_print(&mut self, body: &Node, escape: &bool) -> VisitResult {
+        let value = self.eval(body)?;
+        let s = format!("{}", value);
+        let offset = body.offset().unwrap_or(0);
+        if *escape && self.escape {
+            return self.write(escape_html(&s).as_bytes(), offset);
+        }
+        return self.write(s.as_bytes(), offset);
+    }
+
+    fn visit_array(&mut self, items: &NodeList) -> VisitResult {
+        let mut list = vec![];
+        for item in items {
+            list.push(self.eval(item)?);
+        }
+        self.stack.push(Value::Array(list));
+        return Ok(());
+    }
+
+    fn visit_map(&mut self, entries: &NodeList) -> VisitResult {
+        let mut map = BTreeMap::new();
+        for entry in entries {
+            if let &Node::MapEntry(ref key, ref value) = entry {
+                let value = self.eval(value)?;
+                map.insert(key.value_str().to_string(), value);
+            }
+        }
+        self.stack.push(Value::Map(map));
+        return Ok(());
+    }
+
+    fn visit_map_entry(&mut self, _key: &Token, value: &Node) -> VisitResult {
+        self.visit(value)
+    }
+
+    fn visit_lambda(&mut self, params: &Vec<Token>, body: &Node) -> VisitResult {
+        let params: Vec<String> = params.iter().map(|p| p.value_str().to_string()).collect();
+        let mut captured = vec![];
+        for name in Lambda::free_names(&params, body) {
+            if let Some(value) = self.lookup(&name) {
+                captured.push((name, value.clone()));
+            }
+        }
+        let lambda = Lambda { params: params, body: body.clone(), captured: captured };
+        self.stack.push(Value::Function(Arc::new(Function::Lambda(lambda))));
+        return Ok(());
+    }
+
+    fn visit_include(&mut self, tok: &Token, name: &String) -> VisitResult {
+        let includer = match self.includer {
+            Some(includer) => includer,
+            None => { return Err(err("visit_include", format!("cannot include {}: no template loader", name), tok.offset())); }
+        };
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(err("visit_include", format!("cannot include {}: nested too deeply, possibly recursive", name), tok.offset()));
+        }
+        self.depth += 1;
+        let result = includer.include(name, self);
+        self.depth -= 1;
+        // 被引入模板中的错误以引入处定位，消息中保留它在被引入模板中的位置
+        return match result {
+            Ok(_) => Ok(()),
+            Err(Error::RefMessage(msg, line, column, file)) => {
+                Err(err("visit_include", format!("in {}:{}:{}: {}", file, line, column, msg), tok.offset()))
+            }
+            Err(Error::Message(msg)) => Err(err("visit_include", format!("cannot include {}: {}", name, msg), tok.offset())),
+            Err(e) => Err(e),
+        };
+    }
+}
+
+impl<'a> Invoker for Interpreter<'a> {
+    fn call(&mut self, func: &Value, args: Vec<Value>) -> Result<Value> {
+        self.invoke(func, args, 0)
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    /// 调用函数值，offset 为调用处的位置，用于定位调用失败及宿主函数返回的未定位的错误。
+    fn invoke(&mut self, func: &Value, args: Vec<Value>, offset: usize) -> Result<Value> {
+        let func = match func {
+            &Value::Function(ref func) => func.clone(),
+            other => { return Err(err("call", format!("{} is not callable", other.type_name()), offset)); }
+        };
+        match *func {
+            Function::Lambda(ref lambda) => {
+                self.context.push_scope();
+                for &(ref name, ref value) in &lambda.captured {
+                    self.context.declare(name, value.clone());
+                }
+                // 缺少的参数为 null，多余的参数被忽略
+                let mut args = args.into_iter();
+                for param in &lambda.params {
+                    self.context.declare(param, args.next().unwrap_or(Value::Null));
+                }
+                let result = self.eval(&lambda.body);
+                self.context.pop_scope();
+                return result;
+            }
+            Function::Host(_, ref host) => {
+                return host(self, args).map_err(|e| match e {
+                    Error::Visit(msg, 0) => Error::Visit(msg, offset),
+                    Error::Message(msg) => Error::Visit(msg, offset),
+                    e => e,
+                });
+            }
+        }
+    }
+}
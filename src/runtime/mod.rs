@@ -0,0 +1,11 @@
+mod value;
+mod context;
+mod function;
+mod interpreter;
+mod output;
+
+pub use self::value::{Value, Loop, Range, RangeIter};
+pub use self::context::Context;
+pub use self::function::{Function, Lambda, Invoker, HostFunction};
+pub use self::output::Sink;
+pub use self::interpreter::{Interpreter, Includer, Undefined, escape_html, is_void_element};
@@ -0,0 +1,92 @@
+use std::io::{self, Write};
+
+/// 默认的输出缓冲大小。
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+/// 渲染的输出：缓冲写入任意 `io::Write`，缓冲的内容达到容量或渲染到刷新点时写出。
+///
+/// 刷新点是一组标签名，渲染完这些标签的结束标签后立即写出已缓冲的内容并刷新底层的输出，
+/// 如：在 `</head>` 之后刷新，客户端可以在页面的其余部分渲染完成前开始加载样式和脚本。
+pub struct Sink<'a> {
+    writer: Box<dyn Write + 'a>,
+    buffer: Vec<u8>,
+    capacity: usize,
+    flush_points: Vec<String>,
+}
+
+impl<'a> Sink<'a> {
+    /// 创建输出，writer 可以是输出的所有者或可变引用。
+    pub fn new<W: Write + 'a>(writer: W) -> Sink<'a> {
+        Sink { writer: Box::new(writer), buffer: vec![], capacity: DEFAULT_CAPACITY, flush_points: vec![] }
+    }
+
+    /// 设置缓冲大小，为 0 时每次写入都直接写出。
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    /// 添加一个刷新点，在渲染完名为 tag 的标签后刷新，标签名不区分大小写。
+    pub fn add_flush_point(&mut self, tag: &str) {
+        self.flush_points.push(tag.to_lowercase());
+    }
+
+    /// 尚未写出的内容的长度。
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// 写入内容，缓冲的内容达到容量时写出，写出失败的错误由本次写入返回。
+    pub fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= self.capacity {
+            return self.write_buffer();
+        }
+        return Ok(());
+    }
+
+    /// 一个标签渲染完成，标签是刷新点时刷新。
+    pub fn end_tag(&mut self, name: &str) -> io::Result<()> {
+        if self.flush_points.iter().any(|tag| tag.eq_ignore_ascii_case(name)) {
+            return self.flush();
+        }
+        return Ok(());
+    }
+
+    /// 查找内容中第一个刷新点的结束标签，返回结束标签之后的位置。
+    ///
+    /// 预渲染的标签以字面量输出，其中的结束标签同样是刷新点。
+    pub fn find_end_tag(&self, buf: &[u8]) -> Option<usize> {
+        if self.flush_points.is_empty() {
+            return None;
+        }
+        let mut i = 0;
+        while i + 2 < buf.len() {
+            if buf[i] == b'<' && buf[i + 1] == b'/' {
+                if let Some(len) = buf[i + 2..].iter().position(|&c| c == b'>') {
+                    let name = &buf[i + 2..i + 2 + len];
+                    if self.flush_points.iter().any(|tag| tag.as_bytes().eq_ignore_ascii_case(name)) {
+                        return Some(i + 3 + len);
+                    }
+                }
+            }
+            i += 1;
+        }
+        return None;
+    }
+
+    /// 写出缓冲的内容并刷新底层的输出。
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_buffer()?;
+        return self.writer.flush();
+    }
+
+    fn write_buffer(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        // 写出失败时丢弃缓冲的内容，不再重试
+        let result = self.writer.write_all(&self.buffer);
+        self.buffer.clear();
+        return result;
+    }
+}
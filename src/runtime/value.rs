@@ -0,0 +1,427 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use json::Json;
+use super::Function;
+
+/// 定义模板运行时的值。
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// 空值 null
+    Null,
+    /// 布尔值
+    Bool(bool),
+    /// 整数
+    Int(i64),
+    /// 浮点数
+    Float(f64),
+    /// 字符串
+    String(String),
+    /// 数组
+    Array(Vec<Value>),
+    /// 键值对集合
+    Map(BTreeMap<String, Value>),
+    /// 循环体内的 `loop` 对象
+    Loop(Arc<Loop>),
+    /// 整数区间
+    Range(Range),
+    /// 箭头函数或宿主函数
+    Function(Arc<Function>),
+    /// 以调试方式处理的未定义值，保存其访问路径，除输出调试标记外与 null 相同
+    Undefined(String),
+}
+
+impl Value {
+    /// 获取值的类型名称，用于错误提示。
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            &Value::Null => "null",
+            &Value::Bool(_) => "bool",
+            &Value::Int(_) => "int",
+            &Value::Float(_) => "float",
+            &Value::String(_) => "string",
+            &Value::Array(_) => "array",
+            &Value::Map(_) => "map",
+            &Value::Loop(_) => "loop",
+            &Value::Range(_) => "range",
+            &Value::Function(_) => "function",
+            &Value::Undefined(_) => "undefined",
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        match self {
+            &Value::Null | &Value::Undefined(_) => true,
+            _ => false,
+        }
+    }
+
+    /// 判断值在条件表达式中是否为真。
+    pub fn is_true(&self) -> bool {
+        match self {
+            &Value::Null => false,
+            &Value::Bool(b) => b,
+            &Value::Int(i) => i != 0,
+            &Value::Float(f) => f != 0.0,
+            &Value::String(ref s) => !s.is_empty(),
+            &Value::Array(ref items) => !items.is_empty(),
+            &Value::Map(ref entries) => !entries.is_empty(),
+            &Value::Loop(_) => true,
+            &Value::Range(ref r) => r.len() > 0,
+            &Value::Function(_) => true,
+            &Value::Undefined(_) => false,
+        }
+    }
+
+    /// 比较两个值是否相等，整数与浮点数按数值比较。
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            _ if self.is_null() && other.is_null() => true,
+            (&Value::Bool(a), &Value::Bool(b)) => a == b,
+            (&Value::Int(a), &Value::Int(b)) => a == b,
+            (&Value::Int(a), &Value::Float(b)) => (a as f64) == b,
+            (&Value::Float(a), &Value::Int(b)) => a == (b as f64),
+            (&Value::Float(a), &Value::Float(b)) => a == b,
+            (&Value::String(ref a), &Value::String(ref b)) => a == b,
+            (&Value::Array(ref a), &Value::Array(ref b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.equals(y))
+            }
+            (&Value::Map(ref a), &Value::Map(ref b)) => {
+                a.len() == b.len() && a.iter().all(|(k, v)| b.get(k).map_or(false, |x| v.equals(x)))
+            }
+            (&Value::Loop(ref a), &Value::Loop(ref b)) => Arc::ptr_eq(a, b),
+            (&Value::Range(ref a), &Value::Range(ref b)) => a == b,
+            (&Value::Function(ref a), &Value::Function(ref b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// 获取成员属性，不存在时返回 None。
+    pub fn get(&self, key: &Value) -> Option<Value> {
+        match (self, key) {
+            (&Value::Map(ref entries), &Value::String(ref name)) => entries.get(name).cloned(),
+            (&Value::Array(ref items), &Value::Int(index)) => {
+                if index < 0 {
+                    let index = items.len() as i64 + index;
+                    if index < 0 { return None; }
+                    return items.get(index as usize).cloned();
+                }
+                return items.get(index as usize).cloned();
+            }
+            (&Value::Array(ref items), &Value::String(ref name)) if name == "length" => {
+                Some(Value::Int(items.len() as i64))
+            }
+            (&Value::String(ref s), &Value::String(ref name)) if name == "length" => {
+                Some(Value::Int(s.chars().count() as i64))
+            }
+            (&Value::Loop(ref info), &Value::String(ref name)) => info.get(name),
+            (&Value::Range(ref r), &Value::Int(index)) => {
+                let index = if index < 0 { r.len() as i128 + index as i128 } else { index as i128 };
+                if index < 0 { return None; }
+                return r.get(index as usize).map(Value::Int);
+            }
+            (&Value::Range(ref r), &Value::String(ref name)) if name == "length" => {
+                // 超出 i64 的长度以浮点数表示
+                let len = r.len();
+                if len > i64::max_value() as usize {
+                    return Some(Value::Float(len as f64));
+                }
+                Some(Value::Int(len as i64))
+            }
+            _ => None,
+        }
+    }
+
+    /// 判断集合中是否包含给定的值，不支持的类型返回 None。
+    ///
+    /// 数组比较元素，键值对集合比较键，字符串判断子串，区间判断整数是否在其中。
+    pub fn contains(&self, item: &Value) -> Option<bool> {
+        match (self, item) {
+            (&Value::Array(ref items), _) => Some(items.iter().any(|x| x.equals(item))),
+            (&Value::Map(ref entries), &Value::String(ref key)) => Some(entries.contains_key(key)),
+            (&Value::Map(_), _) => Some(false),
+            (&Value::String(ref s), &Value::String(ref sub)) => Some(s.contains(sub.as_str())),
+            (&Value::Range(ref r), &Value::Int(i)) => Some(r.contains(i)),
+            (&Value::Range(_), _) => Some(false),
+            _ => None,
+        }
+    }
+
+    /// 按 Python 的规则切片，不支持切片的类型返回 None。
+    pub fn slice(&self, start: Option<i64>, end: Option<i64>, step: i64) -> Option<Value> {
+        match self {
+            &Value::Array(ref items) => {
+                let (first, count) = slice_bounds(items.len(), start, end, step);
+                let list = (0..count).map(|i| items[(first + i as i128 * step as i128) as usize].clone()).collect();
+                return Some(Value::Array(list));
+            }
+            &Value::String(ref s) => {
+                let chars: Vec<char> = s.chars().collect();
+                let (first, count) = slice_bounds(chars.len(), start, end, step);
+                let sub = (0..count).map(|i| chars[(first + i as i128 * step as i128) as usize]).collect();
+                return Some(Value::String(sub));
+            }
+            &Value::Range(ref r) => {
+                let (first, count) = slice_bounds(r.len(), start, end, step);
+                if count == 0 {
+                    return Some(Value::Range(Range::new(0, 0, 1)));
+                }
+                let last = first + (count as i128 - 1) * step as i128;
+                let (new_start, new_end) = (r.get(first as usize).unwrap(), r.get(last as usize).unwrap());
+                if count == 1 {
+                    return Some(Value::Range(Range::inclusive(new_start, new_end, 1)));
+                }
+                return match r.step.checked_mul(step) {
+                    Some(new_step) => match new_end.checked_add(new_step) {
+                        Some(after) => Some(Value::Range(Range::new(new_start, after, new_step))),
+                        None => Some(Value::Range(Range::inclusive(new_start, new_end, new_step))),
+                    },
+                    // 步长溢出时只有两个元素
+                    None => Some(Value::Array(vec![Value::Int(new_start), Value::Int(new_end)])),
+                };
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 计算切片的首个索引和元素个数，以 i128 计算以免长度或步长接近边界时溢出。
+fn slice_bounds(len: usize, start: Option<i64>, end: Option<i64>, step: i64) -> (i128, usize) {
+    let (len, step) = (len as i128, step as i128);
+    let normalize = |i: i64, lower: i128, upper: i128| -> i128 {
+        let i = if i < 0 { i as i128 + len } else { i as i128 };
+        if i < lower { lower } else if i > upper { upper } else { i }
+    };
+    if step > 0 {
+        let first = start.map_or(0, |i| normalize(i, 0, len));
+        let last = end.map_or(len, |i| normalize(i, 0, len));
+        if last <= first {
+            return (first, 0);
+        }
+        return (first, ((last - first + step - 1) / step) as usize);
+    }
+    let first = start.map_or(len - 1, |i| normalize(i, -1, len - 1));
+    let last = end.map_or(-1, |i| normalize(i, -1, len - 1));
+    if first <= last {
+        return (first, 0);
+    }
+    return (first, ((first - last - step - 1) / -step) as usize);
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Value::Null => Ok(()),
+            &Value::Bool(b) => write!(f, "{}", b),
+            &Value::Int(i) => write!(f, "{}", i),
+            &Value::Float(v) => write!(f, "{}", v),
+            &Value::String(ref s) => write!(f, "{}", s),
+            &Value::Array(ref items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 { write!(f, ",")?; }
+                    write!(f, "{}", item)?;
+                }
+                return Ok(());
+            }
+            &Value::Map(_) => write!(f, "[object Map]"),
+            &Value::Loop(_) => write!(f, "[object Loop]"),
+            &Value::Range(ref r) => write!(f, "{}", r),
+            &Value::Function(_) => write!(f, "[object Function]"),
+            &Value::Undefined(ref path) => write!(f, "[undefined: {}]", path),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Value { Value::Bool(v) }
+}
+
+impl From<i32> for Value {
+    fn from(v: i32) -> Value { Value::Int(v as i64) }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Value { Value::Int(v) }
+}
+
+impl From<usize> for Value {
+    fn from(v: usize) -> Value { Value::Int(v as i64) }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Value { Value::Float(v) }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(v: &'a str) -> Value { Value::String(v.to_string()) }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Value { Value::String(v) }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(v: Vec<T>) -> Value { Value::Array(v.into_iter().map(|x| x.into()).collect()) }
+}
+
+impl From<BTreeMap<String, Value>> for Value {
+    fn from(v: BTreeMap<String, Value>) -> Value { Value::Map(v) }
+}
+
+/// 没有小数部分的数字转换为整数，对象转换为键值对集合。
+impl From<Json> for Value {
+    fn from(v: Json) -> Value {
+        match v {
+            Json::Null => Value::Null,
+            Json::Bool(b) => Value::Bool(b),
+            Json::Number(n) => {
+                if n.fract() == 0.0 && n.abs() < 9007199254740992.0 {
+                    return Value::Int(n as i64);
+                }
+                return Value::Float(n);
+            }
+            Json::String(s) => Value::String(s),
+            Json::Array(items) => items.into(),
+            Json::Object(entries) => Value::Map(entries.into_iter().map(|(k, v)| (k, v.into())).collect()),
+        }
+    }
+}
+
+/// 循环元数据，在 `for` 循环体内以 `loop` 变量访问。
+///
+/// 可用属性：`index`、`index0`、`first`、`last`、`length`、`revindex`、`revindex0`、`parent`，
+/// 以及方法 `cycle(a, b, ...)`。
+#[derive(Debug)]
+pub struct Loop {
+    /// 从 0 开始的当前迭代序号
+    pub index0: usize,
+    /// 被迭代集合的长度
+    pub length: usize,
+    /// 外层循环，如果有的话
+    pub parent: Option<Arc<Loop>>,
+}
+
+impl Loop {
+    pub fn new(index0: usize, length: usize, parent: Option<Arc<Loop>>) -> Loop {
+        Loop { index0: index0, length: length, parent: parent }
+    }
+
+    /// 按名称获取循环属性。
+    pub fn get(&self, name: &str) -> Option<Value> {
+        let value = match name {
+            "index" => Value::Int((self.index0 + 1) as i64),
+            "index0" => Value::Int(self.index0 as i64),
+            "first" => Value::Bool(self.index0 == 0),
+            "last" => Value::Bool(self.index0 + 1 == self.length),
+            "length" => Value::Int(self.length as i64),
+            "revindex" => Value::Int((self.length - self.index0) as i64),
+            "revindex0" => Value::Int((self.length - self.index0 - 1) as i64),
+            "parent" => match self.parent {
+                Some(ref parent) => Value::Loop(parent.clone()),
+                None => Value::Null,
+            },
+            _ => { return None; }
+        };
+        return Some(value);
+    }
+
+    /// 根据当前迭代序号在给定参数间轮换取值。
+    pub fn cycle(&self, items: &[Value]) -> Value {
+        if items.is_empty() {
+            return Value::Null;
+        }
+        return items[self.index0 % items.len()].clone();
+    }
+}
+
+/// 惰性的整数区间 [start, end) 或 [start, end]，迭代时不会分配元素数组。
+///
+/// 长度和元素以 i128 计算，结束值接近 i64 的边界时也不会溢出。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub start: i64,
+    /// 结束值
+    pub end: i64,
+    /// 步长，不能为 0
+    pub step: i64,
+    /// 是否包含结束值
+    pub inclusive: bool,
+}
+
+impl Range {
+    pub fn new(start: i64, end: i64, step: i64) -> Range {
+        Range { start: start, end: end, step: step, inclusive: false }
+    }
+
+    /// 创建包含结束值的区间。
+    pub fn inclusive(start: i64, end: i64, step: i64) -> Range {
+        Range { start: start, end: end, step: step, inclusive: true }
+    }
+
+    /// 区间内元素的个数，超出 usize 的范围时为 `usize::MAX`。
+    pub fn len(&self) -> usize {
+        let (start, end, step) = (self.start as i128, self.end as i128, self.step as i128);
+        let distance = if step > 0 { end - start } else { start - end };
+        let step = step.abs();
+        let count = if distance < 0 || (distance == 0 && !self.inclusive) {
+            0
+        } else if self.inclusive {
+            distance / step + 1
+        } else {
+            (distance + step - 1) / step
+        };
+        if count > usize::max_value() as i128 {
+            return usize::max_value();
+        }
+        return count as usize;
+    }
+
+    pub fn get(&self, index: usize) -> Option<i64> {
+        if index >= self.len() {
+            return None;
+        }
+        return Some((self.start as i128 + index as i128 * self.step as i128) as i64);
+    }
+
+    /// 判断整数是否在区间中，不需要迭代。
+    pub fn contains(&self, value: i64) -> bool {
+        let diff = value as i128 - self.start as i128;
+        let step = self.step as i128;
+        if diff % step != 0 {
+            return false;
+        }
+        let index = diff / step;
+        return index >= 0 && index < self.len() as i128;
+    }
+
+    pub fn iter(&self) -> RangeIter {
+        RangeIter { range: *self, index: 0 }
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operator = if self.inclusive { "..=" } else { ".." };
+        if self.step == 1 {
+            return write!(f, "{}{}{}", self.start, operator, self.end);
+        }
+        return write!(f, "{}{}{} step {}", self.start, operator, self.end, self.step);
+    }
+}
+
+/// 区间迭代器
+#[derive(Debug)]
+pub struct RangeIter {
+    range: Range,
+    index: usize,
+}
+
+impl Iterator for RangeIter {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        let value = self.range.get(self.index);
+        self.index += 1;
+        return value;
+    }
+}
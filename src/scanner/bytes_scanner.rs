@@ -1,13 +1,14 @@
 use super::{Tokenizer, Source};
 use std::path::Path;
-use token::{ascii, TokenKind, Token};
+use token::{ascii, number, TokenKind, Token};
 use token::ascii::{is_digit, is_whitespace, is_upper_letter, is_lower_letter};
 use util::{BinarySearch, Stack};
 use {Error, Result};
-use std::str::from_utf8_unchecked;
+use std::str::from_utf8;
+use unicode_xid::UnicodeXID;
 
 /// 符号表
-static SYMBOLS: [u8; 20] = [
+static SYMBOLS: [u8; 21] = [
     '+' as u8,
     '-' as u8,
     '*' as u8,
@@ -28,6 +29,7 @@ static SYMBOLS: [u8; 20] = [
     ',' as u8,
     '{' as u8,
     '}' as u8,
+    '?' as u8,
 ];
 
 fn err(dev_prefix: &str, msg: String, offs: usize) -> Error {
@@ -41,6 +43,8 @@ pub struct BytesScanner<'a> {
     // immutable state ->
     /// 源:2进制slice
     source: &'a [u8],
+    /// 源:已校验的UTF-8文本，与 source 指向同一内容
+    text: &'a str,
     /// 源文件名
     filename: &'a Path,
     /// OTPL定界符开始
@@ -61,16 +65,22 @@ pub struct BytesScanner<'a> {
     tok_buf: Vec<Token>,
     in_stmt: bool,
     mark_buf: Vec<Vec<Token>>,
+    /// 正在扫描的模板字符串插值，每层 ${...} 记录其中未闭合的 { 的数量
+    templates: Vec<usize>,
+    /// 是否将模板注释作为 Comment 标记输出，默认忽略
+    keep_comments: bool,
 }
 
 impl<'a> BytesScanner<'a> {
-    pub fn new(source: &'a [u8], filename: &'a Path) -> BytesScanner<'a> {
+    pub fn new(text: &'a str, filename: &'a Path) -> BytesScanner<'a> {
+        let source = text.as_bytes();
         let mut ch = ascii::EOF;
         if source.len() > 0 {
             ch = source[0];
         }
         let mut scanner = BytesScanner {
             source: source,
+            text: text,
             filename: filename,
             stmt_start: "{{".as_bytes(),
             stmt_end: "}}".as_bytes(),
@@ -81,11 +91,43 @@ impl<'a> BytesScanner<'a> {
             tok_buf: vec![],
             in_stmt: false,
             mark_buf: vec![],
+            templates: vec![],
+            keep_comments: false,
         };
-        scanner.set_line();
+        scanner.index_lines();
         return scanner;
     }
 
+    /// 从字节创建扫描器，源必须是有效的 UTF-8，否则返回指向首个非法字节的错误。
+    pub fn from_bytes(source: &'a [u8], filename: &'a Path) -> Result<BytesScanner<'a>> {
+        return match from_utf8(source) {
+            Ok(text) => Ok(BytesScanner::new(text, filename)),
+            Err(e) => Err(err("from_bytes", format!("invalid utf-8 sequence"), e.valid_up_to())),
+        };
+    }
+
+    /// 设置是否保留模板注释，保留时注释以 `TokenKind::Comment` 标记输出，供格式化等工具使用。
+    pub fn set_keep_comments(&mut self, keep: bool) {
+        self.keep_comments = keep;
+    }
+
+    /// 设置语句的开始和结束定界符，默认为 `{{` 和 `}}`，定界符不能为空。
+    pub fn set_delimiters(&mut self, start: &'a str, end: &'a str) {
+        assert!(!start.is_empty() && !end.is_empty(), "empty delimiter");
+        self.stmt_start = start.as_bytes();
+        self.stmt_end = end.as_bytes();
+    }
+
+    /// 设置是否解析 HTML 标签，不解析时标签作为普通文本输出，默认解析。
+    pub fn set_parse_xhtml(&mut self, parse: bool) {
+        self.is_parse_xhtml = parse;
+    }
+
+    /// 获取当前偏移处的字符，如果当前偏移不在字符边界上则返回 None。
+    fn current_char(&self) -> Option<char> {
+        return self.text.get(self.offset..).and_then(|s| s.chars().next());
+    }
+
     fn set_current(&mut self) {
         if self.offset < self.source.len() {
             self.ch = self.source[self.offset];
@@ -102,26 +144,24 @@ impl<'a> BytesScanner<'a> {
     fn is_eof(&self) -> bool {
         self.ch == ascii::EOF
     }
-    fn set_line(&mut self) {
-        if self.ch == ascii::CR {
-            //add line
-            let offs = self.offset;
-            if self.lines.is_empty() {
-                self.lines.push((0, offs, 1));//offs, 0, 1
-            } else {
-                if let None = self.find_line_index(offs) {
-                    let (_, end, no) = self.lines[self.lines.len() - 1];
-                    self.lines.push((end, offs, no + 1));//[offs, start, no + 1]
-                }
+    /// 建立行索引，每行为(开始偏移, 换行符偏移或源的结尾, 从 1 开始的行号)。
+    fn index_lines(&mut self) {
+        let mut start = 0;
+        for (offs, &b) in self.source.iter().enumerate() {
+            if b == ascii::LF {
+                let no = self.lines.len() + 1;
+                self.lines.push((start, offs, no));
+                start = offs + 1;
             }
-            // self.offset += 1; //吃掉回车？
         }
+        let no = self.lines.len() + 1;
+        self.lines.push((start, self.source.len(), no));
     }
+
     /// 当前偏移位置+1，并处理行标和列标。
     fn forward(&mut self) -> bool {
         self.offset += 1;
         self.set_current();
-        self.set_line();
         return self.ch != ascii::EOF;
     }
 
@@ -188,7 +228,7 @@ impl<'a> BytesScanner<'a> {
 
     /// 消费掉连续的空白字符串
     fn consume_whitespace(&mut self) {
-        println!("consume_whitespace");
+        trace!("consume_whitespace");
         while !self.is_eof() {
             if is_whitespace(self.ch) {
                 self.forward();
@@ -199,9 +239,7 @@ impl<'a> BytesScanner<'a> {
     }
 
     fn new_token(&self, kind: TokenKind, start: usize, end: usize) -> Token {
-        let mut content = vec![];
-        content.extend_from_slice(&self.source[start..end]);
-        return Token(kind, start, content);
+        return Token(kind, start, self.text[start..end].to_string());
     }
 
     /// 查找边界符
@@ -211,7 +249,7 @@ impl<'a> BytesScanner<'a> {
         //            return None;
         //        }
         //内部方法，不做过多的判断
-        println!("find_delimiter");
+        trace!("find_delimiter");
         let pos = self.offset;
         if kind == TokenKind::LDelimiter && self.ch == self.stmt_start[0] {
             for i in 0..self.stmt_start.len() {
@@ -235,7 +273,7 @@ impl<'a> BytesScanner<'a> {
             for i in 0..self.stmt_end.len() {
                 if self.offset + i >= self.source.len() || self.source[self.offset + i] != self.stmt_end[i] {
                     self.seek((self.stmt_end.len() - 1) as isize);
-                    return Some(self.new_token(kind, pos - 1, self.offset));
+                    return Some(self.new_token(kind, pos, self.offset));
                 }
             }
             self.back_pos_diff(pos);
@@ -264,7 +302,7 @@ impl<'a> BytesScanner<'a> {
 
     /// 查找字符串，未找到返回 None
     fn find_str(&mut self, end: u8) -> Range {
-        println!("find_str");
+        trace!("find_str");
         let pos = self.offset;
         while self.forward() {
             let c = self.ch;
@@ -284,7 +322,7 @@ impl<'a> BytesScanner<'a> {
 
     /// 查找到指定字符串
     fn find(&mut self, ends: Vec<u8>) -> Range {
-        println!("find");
+        trace!("find");
         let match_more = true;
         let skip_str = true;
         let start = self.offset;
@@ -344,6 +382,81 @@ impl<'a> BytesScanner<'a> {
         return Range(0, 0);
     }
 
+    /// 消费连续的数字及分组下划线
+    fn consume_digits(&mut self, radix: u32) {
+        while !self.is_eof() && ((self.ch as char).is_digit(radix) || self.ch == ascii::UND) {
+            self.forward();
+        }
+    }
+
+    /// 扫描数字字面量，如：123、1_000、0xFF、0b1010、1.5、2e-3
+    fn scan_number(&mut self) -> Result<Token> {
+        let pos = self.offset;
+        let mut kind = TokenKind::Int;
+        let radix = match self.source.get(pos + 1).map(|c| *c | 0x20) {
+            Some(c) if self.ch == '0' as u8 && c == 'x' as u8 => 16,
+            Some(c) if self.ch == '0' as u8 && c == 'o' as u8 => 8,
+            Some(c) if self.ch == '0' as u8 && c == 'b' as u8 => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.seek(2);
+            self.consume_digits(36);
+        } else {
+            self.consume_digits(10);
+            // 小数部分，注意区分区间符号 ..
+            if self.ch == ascii::DOT && self.source.get(self.offset + 1).map_or(false, |c| is_digit(*c)) {
+                kind = TokenKind::Float;
+                self.forward();
+                self.consume_digits(10);
+            }
+            // 指数部分
+            if self.ch == 'e' as u8 || self.ch == 'E' as u8 {
+                let sign = self.match_forward(ascii::PLS) || self.match_forward(ascii::SUB);
+                let n = if sign { 2 } else { 1 };
+                if self.source.get(self.offset + n).map_or(false, |c| is_digit(*c)) {
+                    kind = TokenKind::Float;
+                    self.seek(n as isize);
+                    self.consume_digits(10);
+                }
+            }
+        }
+        if !self.is_eof() && !self.find_sp() {
+            let c = self.current_char().unwrap_or(self.ch as char);
+            return Err(err("scan_number", format!("unexpected character {:?} in numeric literal", c), self.offset));
+        }
+        let tok = self.new_token(kind, pos, self.offset);
+        let checked = match tok.kind() {
+            &TokenKind::Float => number::parse_float(tok.value_str()).map(|_| ()),
+            _ => number::parse_int(tok.value_str()).map(|_| ()),
+        };
+        return match checked {
+            Ok(_) => Ok(tok),
+            Err(msg) => Err(err("scan_number", msg, pos)),
+        };
+    }
+
+    /// 扫描标识符，首字符为 _ 或 XID_Start，其后为 XID_Continue
+    fn scan_identifier(&mut self) -> Result<Token> {
+        let pos = self.offset;
+        while let Some(c) = self.current_char() {
+            let accept = if self.offset == pos {
+                c == '_' || UnicodeXID::is_xid_start(c)
+            } else {
+                UnicodeXID::is_xid_continue(c)
+            };
+            if !accept {
+                break;
+            }
+            self.seek(c.len_utf8() as isize);
+        }
+        if self.offset == pos || (!self.is_eof() && !self.find_sp()) {
+            let c = self.current_char().unwrap_or(self.ch as char);
+            return Err(err("scan_identifier", format!("unexpected  character {:?}", c), self.offset));
+        }
+        return Ok(self.new_token(TokenKind::Identifier, pos, self.offset));
+    }
+
     /// 扫描OTPL代码
     fn scan_stmt(&mut self) -> Result<Token> {
         self.consume_whitespace();
@@ -358,13 +471,50 @@ impl<'a> BytesScanner<'a> {
                 }
                 return Err(err("scan_stmt", format!("expected string , but not found end character {}", ch as char), start));
             }
-            //扫描重叠符号 ++ -- || == ?? && !!
-            ascii::PLS | ascii::SUB | ascii::VER | ascii::EQS | ascii::QUM | ascii::AMP | ascii::NOT
+            //扫描模板字符串 `
+            ascii::GRV => {
+                self.forward();
+                return self.scan_template(self.offset - 1);
+            }
+            //模板字符串插值中的 { }，与 ${ 匹配的 } 之后继续扫描模板字符串
+            ascii::LBK if !self.templates.is_empty() => {
+                *self.templates.last_mut().unwrap() += 1;
+                self.forward();
+                return Ok(self.new_token(TokenKind::Symbol, self.offset - 1, self.offset));
+            }
+            ascii::RBK if !self.templates.is_empty() => {
+                self.forward();
+                if *self.templates.last().unwrap() == 0 {
+                    self.templates.pop();
+                    return self.scan_template(self.offset - 1);
+                }
+                *self.templates.last_mut().unwrap() -= 1;
+                return Ok(self.new_token(TokenKind::Symbol, self.offset - 1, self.offset));
+            }
+            //扫描区间符号 ..=
+            ascii::DOT if self.match_forward(ascii::DOT) && self.match_forward_n(2, ascii::EQS) => {
+                self.seek(3);
+                return Ok(self.new_token(TokenKind::Symbol, self.offset - 3, self.offset));
+            }
+            //扫描重叠符号 ++ -- || == ?? && !! .. **
+            ascii::PLS | ascii::SUB | ascii::VER | ascii::EQS | ascii::QUM | ascii::AMP | ascii::NOT | ascii::DOT | ascii::MUL
             if self.match_forward(ch) => {
                 self.forward();
                 self.forward();
                 return Ok(self.new_token(TokenKind::Symbol, self.offset - 2, self.offset));
             }
+            //扫描可选链符号 ?. ?[
+            ascii::QUM if self.match_forward(ascii::DOT) || self.match_forward(ascii::LSQ) => {
+                self.forward();
+                self.forward();
+                return Ok(self.new_token(TokenKind::Symbol, self.offset - 2, self.offset));
+            }
+            //扫描双符号 =>
+            ascii::EQS if self.match_forward(ascii::GTR) => {
+                self.forward();
+                self.forward();
+                return Ok(self.new_token(TokenKind::Symbol, self.offset - 2, self.offset));
+            }
             //扫描双符号 !=
             ascii::NOT if self.match_forward(ascii::EQS) => {
                 self.forward();
@@ -392,30 +542,46 @@ impl<'a> BytesScanner<'a> {
             }
             // 扫描数字 0-9
             48 ... 57 => {
-                let pos = self.offset;
-                while self.forward() {
-                    if self.find_sp() {
-                        return Ok(self.new_token(TokenKind::Int, pos, self.offset));
-                    } else if is_digit(self.ch) { continue; }
-                    return Err(err("scan_stmt", format!("unexpected  character {:?}", ch as char), pos));
-                }
+                return self.scan_number();
             }
-            // 扫描标识 a-zA-Z
+            // 扫描标识 a-zA-Z_ 及 Unicode 标识符
             97 ... 122 | 65 ... 90 | ascii::UND => {
-                let pos = self.offset;
-                while self.forward() {
-                    let ch = self.ch;
-                    if self.find_sp() {
-                        let ret = Ok(self.new_token(TokenKind::Identifier, pos, self.offset));
-                        //self.back();
-                        return ret;
-                    } else if is_digit(ch) || is_lower_letter(ch) || is_upper_letter(ch) || ch == ascii::UND { continue; }
-                    return Err(err("scan_stmt", format!("unexpected  character {:?}", ch as char), pos));
-                }
+                return self.scan_identifier();
+            }
+            _ if ch >= 0x80 && self.current_char().map_or(false, |c| UnicodeXID::is_xid_start(c)) => {
+                return self.scan_identifier();
             }
             _ => {}
         }
-        return Err(err("scan_stmt", format!("unexpected  character {:?}", ch as char), self.offset));
+        let c = self.current_char().unwrap_or(ch as char);
+        return Err(err("scan_stmt", format!("unexpected  character {:?}", c), self.offset));
+    }
+
+    /// 扫描模板字符串的一段文本，直到结束的 ` 或插值的 ${，pos 为该段之前的 ` 或 } 的位置。
+    ///
+    /// 以 ` 结束的一段为 `TokenKind::Template`，以 ${ 结束的一段为 `TokenKind::TemplatePart`，
+    /// 其后的插值表达式按普通语句扫描，直到与 ${ 匹配的 }。
+    fn scan_template(&mut self, pos: usize) -> Result<Token> {
+        let start = self.offset;
+        while !self.is_eof() {
+            if self.ch == ascii::BKS {
+                self.seek(2);
+                continue;
+            }
+            if self.ch == ascii::GRV {
+                let tok = self.new_token(TokenKind::Template, start, self.offset);
+                self.forward();
+                return Ok(tok);
+            }
+            if self.ch == ascii::DLS && self.match_forward(ascii::LBK) {
+                let tok = self.new_token(TokenKind::TemplatePart, start, self.offset);
+                self.seek(2);
+                self.templates.push(0);
+                return Ok(tok);
+            }
+            self.forward();
+        }
+        return Err(err("scan_template", format!("expected template string, but not found end character {}", ascii::GRV as char), pos));
     }
 
     /// 扫描字面含义输出段
@@ -423,20 +589,25 @@ impl<'a> BytesScanner<'a> {
         if self.ch == ascii::REM {
             let pos = self.offset;
             // {{%}}字面输出{{%}}
+            self.forward();
             self.consume_whitespace();
             if let Option::Some(_) = self.find_delimiter(TokenKind::RDelimiter) {
                 let start = self.offset;
                 while self.can_forward() {
+                    let end = self.offset;
                     if let Some(_) = self.find_delimiter(TokenKind::LDelimiter) {
+                        self.consume_whitespace();
                         if self.ch == ascii::REM {
+                            self.forward();
                             self.consume_whitespace();
                             if let Option::Some(_) = self.find_delimiter(TokenKind::RDelimiter) {
                                 // 结束
-                                // let offs = self.source.offset();
-                                return Some(self.new_token(TokenKind::Literal, start, self.offset));
+                                return Some(self.new_token(TokenKind::Literal, start, end));
                             }
                         }
+                        continue;
                     }
+                    self.forward();
                 }
             }
             self.back_pos_diff(pos);
@@ -444,16 +615,15 @@ impl<'a> BytesScanner<'a> {
         return None;
     }
 
-    /// 扫描注释
-    fn scan_comment(&mut self) -> bool {
+    /// 扫描注释，start 为左边界符的位置，返回的标记包含边界符在内的原文
+    fn scan_comment(&mut self, start: usize) -> Option<Token> {
         let pos = self.offset;
         if self.ch == ascii::SLA && self.match_forward(ascii::SLA) {
             // {{//单行注释}}
             self.forward();
             while self.forward() {
                 if let Some(_) = self.find_delimiter(TokenKind::RDelimiter) {
-                    //忽略注释
-                    return true;
+                    return Some(self.new_token(TokenKind::Comment, start, self.offset));
                 }
             }
         } else if self.ch == ascii::SLA && self.match_forward(ascii::MUL) {
@@ -464,50 +634,49 @@ impl<'a> BytesScanner<'a> {
                     self.seek(2);
                     self.consume_whitespace();
                     if let Some(_) = self.find_delimiter(TokenKind::RDelimiter) {
-                        //忽略注释
-                        return true;
+                        return Some(self.new_token(TokenKind::Comment, start, self.offset));
                     }
                 }
             }
         }
         self.back_pos_diff(pos);
-        return false;
+        return None;
+    }
+
+    /// 判断是否为dom标签或属性名称的结束字符，参考 HTML 规范的标签名及属性名状态。
+    fn is_dom_name_end(&self, ch: u8, is_attr: bool) -> bool {
+        return ch == ascii::EOF
+            || is_whitespace(ch)
+            || ch == ascii::SLA
+            || ch == ascii::GTR
+            || ch == ascii::EQS
+            || (is_attr && (ch == ascii::QUO || ch == ascii::APO || ch == ascii::LSS));
     }
 
     /// 提取dom标签或属性名称
+    ///
+    /// 标签名须以 ASCII 字母开头；属性名可以任意非分隔字符开头。
+    /// 其后的字符（含 Unicode）直到遇到分隔字符为止。
     fn find_dom_name(&mut self, allow_at_prefix: bool, allow_colon_prefix: bool) -> Range {
         let none = Range(0, 0);
         let ch = self.ch;
+        let is_attr = allow_at_prefix || allow_colon_prefix;
         // 检查首字母
-        if !(is_lower_letter(ch)
-            || is_upper_letter(ch)
-            || (allow_at_prefix && ch == ascii::ATS)
-            || (allow_colon_prefix && ch == ascii::COLON)) {
+        if is_attr {
+            if self.is_dom_name_end(ch, true) || ch == self.stmt_start[0]
+                || (!allow_at_prefix && ch == ascii::ATS)
+                || (!allow_colon_prefix && ch == ascii::COLON) {
+                return none;
+            }
+        } else if !(is_lower_letter(ch) || is_upper_letter(ch)) {
             return none;
         }
 
         let pos = self.offset;
         while self.forward() {
-            let ch = self.ch;
-            // 匹配 / > = 和空白
-            if is_whitespace(ch)
-                || ch == ascii::SLA
-                || ch == ascii::GTR
-                || ch == ascii::EQS {
+            if self.is_dom_name_end(self.ch, is_attr) {
                 break;
             }
-            //println!("{:?}", ch as char);
-            //允许字母数字+下划线+点+冒号
-            if !(is_lower_letter(ch)
-                || is_upper_letter(ch)
-                || is_digit(ch)
-                || ch == ascii::SUB
-                || ch == ascii::DOT
-                || ch == ascii::COLON
-                || ch == ascii::UND) {
-                self.back_pos_diff(pos);
-                return none;
-            }
         }
         return Range(pos, self.offset); //TODO: 后一个字符
     }
@@ -530,11 +699,13 @@ impl<'a> BytesScanner<'a> {
             let offs = self.offset;
             let Range(start, end) = self.find_dom_name(false, false);
             if start == 0 {
-                return Err(err("scan_dom", format!("illegal dom-tag-identifier, near character {}.", self.ch as char), offs));
+                let c = self.current_char().unwrap_or(self.ch as char);
+                return Err(err("scan_dom", format!("illegal dom-tag-identifier, near character {}.", c), offs));
             }
             self.consume_whitespace();
             if self.ch != ascii::GTR {
-                return Err(err("scan_dom", format!("expected character {}, found {}.", ascii::GTR as char, self.ch as char), self.offset));
+                let c = self.current_char().unwrap_or(self.ch as char);
+                return Err(err("scan_dom", format!("expected character {}, found {}.", ascii::GTR as char, c), self.offset));
             }
             self.forward();
             // let end = self.offset;
@@ -566,7 +737,7 @@ impl<'a> BytesScanner<'a> {
 
         // 匹配dom属性
         while self.ch != ascii::EOF {
-            println!("scan_dom while");
+            trace!("scan_dom while");
             self.consume_whitespace();
             //            println!("bbbbbbbbbbbbbbb{:?}", self.ch as char);
             // 匹配dom标签结束 /> or >
@@ -586,7 +757,8 @@ impl<'a> BytesScanner<'a> {
 
             let Range(attr_start, attr_end) = self.find_dom_name(true, true);
             if attr_end == 0 {
-                return Err(err("scan_dom", format!("unexpected character {:?}.", self.ch as char), offs));
+                let c = self.current_char().unwrap_or(self.ch as char);
+                return Err(err("scan_dom", format!("unexpected character {:?}.", c), offs));
             }
             //            println!("0=>>>>>>>>>> {} = {}", self.source[attr_start] as char, unsafe { from_utf8_unchecked(&self.source[attr_start..attr_end]) });
             self.offer_token(TokenKind::DomAttrStart, attr_start, attr_end);
@@ -607,12 +779,12 @@ impl<'a> BytesScanner<'a> {
             self.consume_whitespace();
 
             let pos = self.offset;
-            let ch = self.ch;
+            let ch = self.current_char().unwrap_or(self.ch as char);
             if let Some(_) = self.find_delimiter(TokenKind::LDelimiter) {
                 // 扩展语法只能是字符串形式
                 if self.source[attr_start] == ascii::ATS {
                     //期待一个字符串，找到一个代码块
-                    return Err(err("scan_dom", format!("expected character {}, found {}.", ascii::QUO as char, ch as char), pos));
+                    return Err(err("scan_dom", format!("expected character {}, found {}.", ascii::QUO as char, ch), pos));
                 }
                 let mut end_s = vec![];
                 for c in self.stmt_end {
@@ -620,21 +792,20 @@ impl<'a> BytesScanner<'a> {
                 }
                 let Range(attr_val_s, attr_val_e) = self.find(end_s);
                 if attr_val_e == 0 {
-                    return Err(err("scan_dom", format!("语法错误, 代码块未结束, near character {},", ch as char), pos));
+                    return Err(err("scan_dom", format!("语法错误, 代码块未结束, near character {},", ch), pos));
                 }
-                let s = unsafe { from_utf8_unchecked(&self.source[pos..attr_val_e + self.stmt_end.len()]) };
-                println!("1=>>>>>>>>>> {:?}", s);
+                trace!("1=>>>>>>>>>> {:?}", &self.text[pos..attr_val_e + self.stmt_end.len()]);
                 self.offer_token(TokenKind::DomAttrValue, pos, attr_val_e + self.stmt_end.len());
                 let pos = self.offset;
                 self.offer_token(TokenKind::DomAttrEnd, pos - 1, pos);
             } else {
                 //匹配字符串
                 if self.ch != ascii::QUO {
-                    return Err(err("scan_dom", format!("expected character {}, found {}.", ascii::QUO as char, ch as char), pos));
+                    return Err(err("scan_dom", format!("expected character {}, found {}.", ascii::QUO as char, ch), pos));
                 }
                 let Range(start, end) = self.find_str(ascii::QUO);
                 if end == 0 {
-                    return Err(err("scan_dom", format!("语法错误, 字符串未结束, near character {},", ch as char), pos));
+                    return Err(err("scan_dom", format!("语法错误, 字符串未结束, near character {},", ch), pos));
                 }
                 self.offer_token(TokenKind::DomAttrValue, start, end);
 
@@ -654,7 +825,7 @@ impl<'a> BytesScanner<'a> {
 
     /// 扫描下一个
     fn scan_next(&mut self) -> Result<Token> {
-        println!("scan_next");
+        trace!("scan_next");
         if !self.tok_buf.is_empty() {
             return Ok(self.tok_buf.pop().unwrap());
         }
@@ -664,6 +835,12 @@ impl<'a> BytesScanner<'a> {
         }
 
         if self.in_stmt {
+            // 结束边界符前允许有空白，如：{{ name }}
+            self.consume_whitespace();
+            // 模板字符串的插值中的 }} 不是结束边界符，如：`${ {a: {b: 1}}.a }`
+            if !self.templates.is_empty() {
+                return self.scan_stmt();
+            }
             if let Some(tok) = self.find_delimiter(TokenKind::RDelimiter) {
                 self.in_stmt = false;
                 //println!("EOFbbbbbbbbbbbbbbbbbbbbbbbbbbbbb");
@@ -675,7 +852,10 @@ impl<'a> BytesScanner<'a> {
             if let Some(tok) = self.scan_literal() {
                 return Ok(tok);
             }
-            if self.scan_comment() {
+            if let Some(comment) = self.scan_comment(tok.offset()) {
+                if self.keep_comments {
+                    return Ok(comment);
+                }
                 return self.scan_next(); // 忽略注释后，重新扫描并返回
             }
             self.in_stmt = true;
@@ -701,7 +881,7 @@ impl<'a> BytesScanner<'a> {
 
 
         while self.can_forward() {
-            println!("scan_next can_forward");
+            trace!("scan_next can_forward");
             if self.is_parse_xhtml && ascii::LSS == self.ch {
                 //为扫描下个dom标签预留符号
                 break;
@@ -717,18 +897,14 @@ impl<'a> BytesScanner<'a> {
 
         let mut tok = self.new_token(TokenKind::Data, pos, self.offset);
 
-        let (start, end) = optimize_literal(tok.2.as_ref());
+        let (start, end) = optimize_literal(tok.value());
         if end == 0 {
             return self.scan_next();
         }
 
-        let mut content = vec![];
-        {
-            let src = tok.2.as_slice();
-            content.extend_from_slice(&src[start..end]);
-        }
-
-        tok.2 = content;
+        tok.2 = tok.2[start..end].to_string();
+        // 偏移指向去除空白后的文本
+        tok.1 += start;
         return Ok(tok);
     }
 }
@@ -737,24 +913,13 @@ impl<'a> Source for BytesScanner<'a> {
     fn line(&self, offset: usize) -> usize {
         if let Some(index) = self.find_line_index(offset) {
             return self.lines[index].2;
-        } else if self.source.len() > 0 {
-            if self.lines.is_empty() {
-                return 1;
-            } else if offset < self.source.len() {
-                return self.lines[self.lines.len() - 1].2 + 1;
-            }
         }
         return 0;
     }
 
     fn column(&self, offset: usize) -> usize {
         if let Some(index) = self.find_line_index(offset) {
-            return offset - self.lines[index].0;
-        } else if self.source.len() > 0 {
-            return offset + 1;
-            //            if offset < self.source.len() {
-            //                return offset - self.lines[self.lines.len() - 1][0];
-            //            }else { return offset + 1; }
+            return offset - self.lines[index].0 + 1;
         }
         return 0;
     }
@@ -808,6 +973,10 @@ impl<'a> Tokenizer for BytesScanner<'a> {
         self
     }
 
+    fn delimiters(&self) -> (&[u8], &[u8]) {
+        (self.stmt_start, self.stmt_end)
+    }
+
     fn mark(&mut self) {
         self.mark_buf.push(vec![]);
     }
@@ -851,7 +1020,7 @@ fn optimize_literal(value: &[u8]) -> (usize, usize) {
         }
         end = i;
     }
-    println!("abc:{} {} {}", start, end, value.len());
+    trace!("abc:{} {} {}", start, end, value.len());
     return (start, end);
 }
 
@@ -9,9 +9,9 @@ use std::str::from_utf8_unchecked;
 
 /// 定义的要解析的输入源。
 pub trait Source: Debug {
-    /// 获取给定 `Token` 的用于定位源的行号.
+    /// 获取给定 `Token` 的用于定位源的行号，从 1 开始，偏移超出源时返回 0.
     fn line(&self, offset: usize) -> usize;
-    /// 获取给定 `Token` 的用于定位源的行的开始位置.
+    /// 获取给定 `Token` 在所在行中的列号（按字节计），从 1 开始，偏移超出源时返回 0.
     fn column(&self, offset: usize) -> usize;
     /// 获取给定 `Token` 的输入源文件名.
     /// 注意：该文件名只是用于错误定位的提示。
@@ -24,6 +24,8 @@ pub trait Tokenizer: Debug {
     fn back_token(&mut self, tok: Token);
     fn scan(&mut self) -> Result<Token>;
     fn source(&self) -> &Source;
+    /// 获取语句的开始和结束定界符
+    fn delimiters(&self) -> (&[u8], &[u8]);
     /// 标记一个还原点
     fn mark(&mut self);
     /// 取消一个还原点
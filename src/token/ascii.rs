@@ -61,6 +61,8 @@ pub const BKS: u8 = 92;
 pub const RSQ: u8 = 93;
 /// 下划线 _
 pub const UND: u8 = 95;
+/// 反引号 `
+pub const GRV: u8 = '`' as u8;
 /// 左大括号 {
 pub const LBK: u8 = '{' as u8;
 /// 竖线 |
@@ -1,6 +1,6 @@
 pub mod ascii;
-
-use std::str::from_utf8_unchecked;
+pub mod number;
+pub mod string;
 
 /// 标记的种类
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -15,8 +15,14 @@ pub enum TokenKind {
     Symbol,
     /// 字符串
     String,
+    /// 模板字符串的最后一段文本，如：`hello ${name}!` 中的 !，没有插值时为全部内容
+    Template,
+    /// 模板字符串中插值之前的一段文本，如：`hello ${name}!` 中的 hello
+    TemplatePart,
     /// 整数
     Int,
+    /// 浮点数
+    Float,
     /// 标识符
     Identifier,
     /// DOM标签的开始
@@ -39,12 +45,18 @@ pub enum TokenKind {
     RDelimiter,
     /// 字面量
     Literal,
+    /// 模板注释，如：{{// ...}}、{{/* ... */}}，值为包含边界符的原文，仅在扫描器保留注释时产生
+    Comment,
+    /// 未被扫描器单独输出的界定符号，如：<、="、-->，仅出现在无损语法树中
+    Punct,
 }
 
 /// 定义的源码中最小词法的含义。
-/// Token([`TokenKind`], start-offset, end-offset)
+/// Token([`TokenKind`], start-offset, value)
+///
+/// 输入源在构造时已校验为 UTF-8，且标记总是在字符边界上切分，因此值以字符串保存。
 #[derive(Debug, Clone)]
-pub struct Token(pub TokenKind, pub usize, pub Vec<u8>);
+pub struct Token(pub TokenKind, pub usize, pub String);
 
 impl Token {
     pub fn kind(&self) -> &TokenKind {
@@ -56,16 +68,15 @@ impl Token {
     }
 
     pub fn value(&self) -> &[u8] {
-        self.2.as_slice()
+        self.2.as_bytes()
     }
 
     pub fn value_str(&self) -> &str {
-        let s = self.value();
-        return unsafe { from_utf8_unchecked(s) };
+        self.2.as_str()
     }
 
     pub fn empty() -> Token {
-        Token(TokenKind::Ignore, 0, vec![])
+        Token(TokenKind::Ignore, 0, String::new())
     }
 }
 
@@ -0,0 +1,76 @@
+//! 数字字面量的解析。
+//!
+//! 支持的格式：
+//! - 十进制整数：`123`、`1_000`
+//! - 十六进制、八进制、二进制整数：`0xFF`、`0o17`、`0b1010`
+//! - 浮点数：`1.5`、`1e10`、`2.5E-3`、`1_000.5`
+//!
+//! 下划线仅用于分组，必须位于两个数字之间。
+
+/// 检查下划线是否都位于两个数字之间。
+fn check_underscores(digits: &str) -> Result<(), String> {
+    let bytes = digits.as_bytes();
+    for i in 0..bytes.len() {
+        if bytes[i] != '_' as u8 {
+            continue;
+        }
+        let prev_ok = i > 0 && (bytes[i - 1] as char).is_digit(36);
+        let next_ok = i + 1 < bytes.len() && ((bytes[i + 1] as char).is_digit(36) || bytes[i + 1] == '_' as u8);
+        if !prev_ok || !next_ok {
+            return Err(format!("invalid underscore in numeric literal {:?}", digits));
+        }
+    }
+    return Ok(());
+}
+
+/// 获取整数字面量的进制及去除前缀后的数字部分。
+fn split_radix(s: &str) -> (u32, &str) {
+    let lower = s.get(0..2).map(|p| p.to_ascii_lowercase());
+    match lower.as_ref().map(|p| p.as_str()) {
+        Some("0x") => (16, &s[2..]),
+        Some("0o") => (8, &s[2..]),
+        Some("0b") => (2, &s[2..]),
+        _ => (10, s),
+    }
+}
+
+/// 解析整数字面量，溢出时返回错误。
+pub fn parse_int(s: &str) -> Result<i64, String> {
+    let (radix, digits) = split_radix(s);
+    if digits.is_empty() {
+        return Err(format!("missing digits after integer prefix in {:?}", s));
+    }
+    check_underscores(digits)?;
+    let digits: String = digits.chars().filter(|c| *c != '_').collect();
+    for c in digits.chars() {
+        if !c.is_digit(radix) {
+            return Err(format!("invalid digit {:?} for a base {} literal {:?}", c, radix, s));
+        }
+    }
+    return i64::from_str_radix(&digits, radix).map_err(|_| format!("integer literal {:?} is too large", s));
+}
+
+/// 解析浮点数字面量，超出范围时返回错误。
+pub fn parse_float(s: &str) -> Result<f64, String> {
+    check_underscores(s)?;
+    let digits: String = s.chars().filter(|c| *c != '_').collect();
+    return match digits.parse::<f64>() {
+        Ok(f) if f.is_infinite() => Err(format!("float literal {:?} is out of range", s)),
+        Ok(f) => Ok(f),
+        Err(_) => Err(format!("invalid float literal {:?}", s)),
+    };
+}
+
+#[test]
+fn test_parse_numbers() {
+    assert_eq!(parse_int("1_000"), Ok(1000));
+    assert_eq!(parse_int("0xff"), Ok(255));
+    assert_eq!(parse_int("0b1010"), Ok(10));
+    assert_eq!(parse_int("0o17"), Ok(15));
+    assert!(parse_int("9223372036854775808").is_err());
+    assert!(parse_int("1__").is_err());
+    assert!(parse_int("0x").is_err());
+    assert_eq!(parse_float("2.5e-3"), Ok(0.0025));
+    assert_eq!(parse_float("1_0.5"), Ok(10.5));
+    assert!(parse_float("1e400").is_err());
+}
@@ -0,0 +1,71 @@
+//! 字符串字面量的转义处理。
+//!
+//! 支持的转义序列：`\n`、`\r`、`\t`、`\0`、`\\`、`\'`、`\"`、`` \` ``、`\$`、
+//! `\xHH`（ASCII）及 `\u{HHHHHH}`（Unicode 码点）。
+
+/// 解码字符串中的转义序列，出错时返回错误信息及其在原始内容中的偏移。
+pub fn unescape(raw: &str) -> Result<String, (String, usize)> {
+    let mut buf = String::with_capacity(raw.len());
+    let mut chars = raw.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != '\\' {
+            buf.push(c);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some((_, c)) => c,
+            None => { return Err((format!("unterminated escape sequence"), i)); }
+        };
+        match escaped {
+            'n' => buf.push('\n'),
+            'r' => buf.push('\r'),
+            't' => buf.push('\t'),
+            '0' => buf.push('\0'),
+            '\\' | '\'' | '"' | '`' | '$' => buf.push(escaped),
+            'x' => {
+                let mut code = String::new();
+                for _ in 0..2 {
+                    match chars.next() {
+                        Some((_, c)) if c.is_digit(16) => code.push(c),
+                        _ => { return Err((format!("invalid \\x escape, expected two hex digits"), i)); }
+                    }
+                }
+                let value = u8::from_str_radix(&code, 16).unwrap();
+                if value > 0x7f {
+                    return Err((format!("\\x escape must be in range 00-7F, found {}", code), i));
+                }
+                buf.push(value as char);
+            }
+            'u' => {
+                match chars.next() {
+                    Some((_, '{')) => {}
+                    _ => { return Err((format!("invalid \\u escape, expected '{{'"), i)); }
+                }
+                let mut code = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '}')) => { break; }
+                        Some((_, c)) if c.is_digit(16) && code.len() < 6 => code.push(c),
+                        _ => { return Err((format!("invalid \\u escape, expected 1-6 hex digits and '}}'"), i)); }
+                    }
+                }
+                let value = u32::from_str_radix(&code, 16).map_err(|_| (format!("invalid \\u escape"), i))?;
+                match ::std::char::from_u32(value) {
+                    Some(c) => buf.push(c),
+                    None => { return Err((format!("invalid unicode code point \\u{{{}}}", code), i)); }
+                }
+            }
+            _ => { return Err((format!("unknown escape sequence \\{}", escaped), i)); }
+        }
+    }
+    return Ok(buf);
+}
+
+#[test]
+fn test_unescape() {
+    assert_eq!(unescape(r#"a\"b"#), Ok("a\"b".to_string()));
+    assert_eq!(unescape(r"x\ny\t\\"), Ok("x\ny\t\\".to_string()));
+    assert_eq!(unescape(r"\u{4e2d}\x41"), Ok("中A".to_string()));
+    assert!(unescape(r"\q").is_err());
+    assert!(unescape(r"\u{110000}").is_err());
+}
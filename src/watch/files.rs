@@ -0,0 +1,75 @@
+//! 模板文件的查找、读取和解析，解析失败时生成带源位置的诊断信息。
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::result;
+use ast::NodeList;
+use parser::Parser;
+use scanner::{BytesScanner, Source};
+use Error;
+
+/// 检查目录时识别为模板的文件扩展名。
+pub static TEMPLATE_EXTENSIONS: [&'static str; 4] = ["html", "htm", "tpl", "otpl"];
+
+/// 生成带源位置的诊断信息，如：`page.html:3:12: error: ...`。
+pub fn diagnostic(path: &Path, text: &str, err: &Error) -> String {
+    let (msg, offset) = match err {
+        &Error::Scan(ref msg, offset) | &Error::Parse(ref msg, offset) | &Error::Visit(ref msg, offset) => (msg.clone(), offset),
+        &Error::Message(ref msg) => { return format!("{}: error: {}", path.display(), msg); }
+        &Error::RefMessage(ref msg, line, column, _) => { return format!("{}:{}:{}: error: {}", path.display(), line, column, msg); }
+        err => { return format!("{}: error: {:?}", path.display(), err); }
+    };
+    let source = BytesScanner::new(text, path);
+    return format!("{}:{}:{}: error: {}", path.display(), source.line(offset), source.column(offset), msg);
+}
+
+/// 读取模板文件，失败时返回诊断信息。
+pub fn read_file(path: &Path) -> result::Result<String, String> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) => { return Err(format!("{}: error: {}", path.display(), err)); }
+    };
+    return match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(err) => {
+            let offset = err.utf8_error().valid_up_to();
+            let text = String::from_utf8_lossy(err.as_bytes()).into_owned();
+            Err(diagnostic(path, &text, &Error::Scan(format!("invalid utf-8 sequence"), offset)))
+        }
+    };
+}
+
+/// 读取并解析模板文件，返回源码和语法树，失败时返回诊断信息。
+pub fn parse_file(path: &Path) -> result::Result<(String, NodeList), String> {
+    let text = read_file(path)?;
+    let list = {
+        let mut scanner = BytesScanner::new(&text, path);
+        match Parser::new(&mut scanner).parse_all() {
+            Ok(list) => list,
+            Err(err) => { return Err(diagnostic(path, &text, &err)); }
+        }
+    };
+    return Ok((text, list));
+}
+
+/// 列出路径下的模板文件：文件直接返回，目录按名称顺序递归查找。
+pub fn template_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = vec![];
+    for entry in fs::read_dir(path)? {
+        entries.push(entry?.path());
+    }
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            template_files(&entry, files)?;
+        } else if entry.extension().and_then(|e| e.to_str()).map(|e| TEMPLATE_EXTENSIONS.contains(&e)).unwrap_or(false) {
+            files.push(entry);
+        }
+    }
+    return Ok(());
+}
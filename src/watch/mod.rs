@@ -0,0 +1,212 @@
+//! 监视模式：轮询目录树中模板文件的变化，只重新解析变化的模板，并找出依赖它们的模板。
+//!
+//! 模板之间的依赖来自 `{{include 'name'}}`，名称相对监视的根目录查找，与以该目录为根的 `FileLoader` 加载模板的方式一致。
+//! 被引入的模板变化或被删除时，所有直接或间接引入它的模板都会出现在本次轮询的结果中，
+//! 以便调用者重新检查或渲染它们。
+
+mod files;
+
+pub use self::files::{diagnostic, parse_file, read_file, template_files, TEMPLATE_EXTENSIONS};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use analysis;
+use engine::FileLoader;
+use ast::NodeList;
+
+/// 一次轮询中受影响的模板。
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// 模板解析成功，它本身或它依赖的模板发生了变化
+    Changed(PathBuf),
+    /// 模板无法读取、解析失败或引入的模板不存在，附带诊断信息
+    Failed(PathBuf, Vec<String>),
+    /// 模板被删除
+    Removed(PathBuf),
+}
+
+impl Event {
+    pub fn path(&self) -> &Path {
+        match self {
+            &Event::Changed(ref path) | &Event::Failed(ref path, _) | &Event::Removed(ref path) => path,
+        }
+    }
+}
+
+/// 文件的修改时间和长度，任何一个不同即视为文件已修改。
+type Stamp = (Option<SystemTime>, u64);
+
+#[derive(Debug)]
+struct Entry {
+    stamp: Stamp,
+    /// 解析成功时的源码和语法树，失败时为诊断信息
+    parsed: Result<(String, NodeList), String>,
+    /// 引入的模板名称及解析到的路径，找不到时为 None
+    includes: Vec<(String, Option<PathBuf>)>,
+}
+
+/// 轮询目录树的监视器。
+#[derive(Debug)]
+pub struct Watcher {
+    root: PathBuf,
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+impl Watcher {
+    pub fn new<P: AsRef<Path>>(root: P) -> Watcher {
+        Watcher { root: root.as_ref().to_path_buf(), entries: BTreeMap::new() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// 获取最近一次解析成功的模板的源码和语法树。
+    pub fn template(&self, path: &Path) -> Option<(&str, &NodeList)> {
+        match self.entries.get(path) {
+            Some(&Entry { parsed: Ok((ref text, ref list)), .. }) => Some((text, list)),
+            _ => None,
+        }
+    }
+
+    /// 直接或间接引入给定模板的所有模板，按路径排序。
+    pub fn dependents(&self, path: &Path) -> Vec<PathBuf> {
+        let mut found = BTreeSet::new();
+        let mut pending = vec![path.to_path_buf()];
+        while let Some(target) = pending.pop() {
+            for (file, entry) in self.entries.iter() {
+                let includes = entry.includes.iter().any(|&(_, ref p)| p.as_ref() == Some(&target));
+                if includes && found.insert(file.clone()) {
+                    pending.push(file.clone());
+                }
+            }
+        }
+        found.remove(path);
+        return found.into_iter().collect();
+    }
+
+    /// 扫描目录并处理变化，返回受影响的模板。首次调用时所有模板都被视为新增。
+    ///
+    /// 列出目录失败时返回错误，已记录的模板保持不变；单个文件无法读取状态时作为解析失败的模板报告。
+    pub fn poll(&mut self) -> io::Result<Vec<Event>> {
+        let mut listed = vec![];
+        template_files(&self.root, &mut listed)?;
+        let mut files = vec![];
+        let mut stamps = vec![];
+        let mut failed = vec![];
+        for path in listed {
+            match stamp(&path) {
+                Ok(stamp) => {
+                    stamps.push((path.clone(), stamp));
+                    files.push(path);
+                }
+                // 列出后即被删除的文件视为已删除
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    failed.push(Event::Failed(path.clone(), vec![format!("{}: error: {}", path.display(), err)]));
+                    files.push(path);
+                }
+            }
+        }
+
+        let mut changed = BTreeSet::new();
+        let mut removed = BTreeSet::new();
+        let mut affected = BTreeSet::new();
+        for path in self.entries.keys() {
+            if !files.contains(path) {
+                removed.insert(path.clone());
+                // 删除前的依赖关系
+                affected.extend(self.dependents(path));
+            }
+        }
+        for path in removed.iter() {
+            self.entries.remove(path);
+        }
+        for (path, stamp) in stamps {
+            if self.entries.get(&path).map(|e| e.stamp != stamp).unwrap_or(true) {
+                let parsed = parse_file(&path);
+                self.entries.insert(path.clone(), Entry { stamp: stamp, parsed: parsed, includes: vec![] });
+                changed.insert(path);
+            }
+        }
+        // 模板的增删会改变其他模板中名称解析的结果，有变化时重新解析所有模板的依赖
+        if !changed.is_empty() || !removed.is_empty() {
+            let paths: Vec<PathBuf> = self.entries.keys().cloned().collect();
+            for path in paths {
+                let includes = self.resolve(&path);
+                self.entries.get_mut(&path).unwrap().includes = includes;
+            }
+        }
+        for path in changed {
+            affected.extend(self.dependents(&path));
+            affected.insert(path);
+        }
+
+        let mut events: Vec<Event> = removed.into_iter().map(Event::Removed).collect();
+        for path in affected {
+            if failed.iter().any(|e: &Event| e.path() == path.as_path()) {
+                continue;
+            }
+            if let Some(entry) = self.entries.get(&path) {
+                let mut diagnostics = vec![];
+                if let Err(ref msg) = entry.parsed {
+                    diagnostics.push(msg.clone());
+                }
+                for &(ref name, ref target) in entry.includes.iter() {
+                    if target.is_none() {
+                        diagnostics.push(format!("{}: error: included template {:?} not found", path.display(), name));
+                    }
+                }
+                if diagnostics.is_empty() {
+                    events.push(Event::Changed(path));
+                } else {
+                    events.push(Event::Failed(path, diagnostics));
+                }
+            }
+        }
+        events.extend(failed);
+        events.sort_by(|a, b| a.path().cmp(b.path()));
+        return Ok(events);
+    }
+
+    /// 按给定的间隔持续轮询，有模板受影响时调用 handler，handler 返回 false 时停止。
+    ///
+    /// 轮询失败不会停止监视，错误作为根目录的 `Event::Failed` 交给 handler，下次轮询时重试。
+    pub fn watch<F>(&mut self, interval: Duration, mut handler: F) -> io::Result<()>
+        where F: FnMut(&Watcher, &[Event]) -> bool {
+        loop {
+            let events = match self.poll() {
+                Ok(events) => events,
+                Err(err) => vec![Event::Failed(self.root.clone(), vec![format!("{}: error: {}", self.root.display(), err)])],
+            };
+            if !events.is_empty() && !handler(self, &events) {
+                return Ok(());
+            }
+            thread::sleep(interval);
+        }
+    }
+
+    /// 解析模板引入的名称到被监视的模板文件。
+    fn resolve(&self, path: &Path) -> Vec<(String, Option<PathBuf>)> {
+        let list = match self.entries.get(path) {
+            Some(&Entry { parsed: Ok((_, ref list)), .. }) => list,
+            _ => { return vec![]; }
+        };
+        let loader = FileLoader::new(&self.root);
+        let mut includes = vec![];
+        for name in analysis::analyze(list).includes {
+            let target = loader.path(&name).ok().filter(|p| self.entries.contains_key(p));
+            includes.push((name, target));
+        }
+        return includes;
+    }
+}
+
+fn stamp(path: &Path) -> io::Result<Stamp> {
+    let metadata = fs::metadata(path)?;
+    return Ok((metadata.modified().ok(), metadata.len()));
+}
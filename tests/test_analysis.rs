@@ -0,0 +1,61 @@
+mod prelude;
+
+use self::prelude::*;
+use self::otpl::analysis::{analyze, check, Type};
+
+fn parse(source: &str) -> NodeList {
+    let mut scanner = BytesScanner::new(source, "source".as_ref());
+    return Parser::new(&mut scanner).parse_all().expect("Parse Error");
+}
+
+#[test]
+fn test_analyze_free_variables() {
+    let root = parse("{{include 'header.html'}}<h1 title=\"{{site.title}}\">{{user.name}}</h1>\
+        {{for i, item : order.items}}{{item.price * rate}}{{loop.index}}{{i}}{{/for}}\
+        {{with order.customer.address as a}}{{a.city}}{{else}}{{fallback}}{{/with}}\
+        {{sort_by(products, (p) => p.price + tax)}}{{include 'footer.html'}}");
+    let report = analyze(&root);
+    assert_eq!(report.variables, vec!["site", "user", "order", "rate", "fallback", "products", "tax"]);
+    assert_eq!(report.properties, vec!["site.title", "user.name", "order.items[].price", "order.customer.address.city"]);
+    assert_eq!(report.functions, vec!["sort_by"]);
+    assert_eq!(report.includes, vec!["header.html", "footer.html"]);
+}
+
+#[test]
+fn test_analyze_dom_extend_for() {
+    let root = parse("<ul><li @for=\"v : rows\">{{v.id}}{{loop.first}}</li></ul>");
+    let report = analyze(&root);
+    assert_eq!(report.variables, vec!["rows"]);
+    assert_eq!(report.properties, vec!["rows[].id"]);
+}
+
+fn order_schema() -> Type {
+    Type::object(vec![
+        ("title", Type::String),
+        ("count", Type::Int),
+        ("active", Type::Bool),
+        ("items", Type::array(Type::object(vec![("name", Type::String), ("price", Type::Float)]))),
+        ("customer", Type::optional(Type::object(vec![("name", Type::String)]))),
+    ])
+}
+
+#[test]
+fn test_check_valid_template() {
+    let root = parse("{{if active && count > 0}}{{for i, item : items}}{{item.name + ': ' + item.price * 2}}{{loop.index}}{{/for}}{{/if}}\
+        {{with customer as c}}{{c.name}}{{/with}}{{customer?.name ?? title}}");
+    assert_eq!(check(&root, &order_schema()), vec![]);
+}
+
+#[test]
+fn test_check_reports_errors() {
+    let source = "{{item.name}}{{for x : count}}{{/for}}{{if title}}{{/if}}{{title - 1}}{{for it : items}}{{it.nmae}}{{/for}}";
+    let root = parse(source);
+    let messages: Vec<(String, usize)> = check(&root, &order_schema()).into_iter().map(|d| (d.message, d.offset)).collect();
+    assert_eq!(messages, vec![
+        ("unknown variable item".to_string(), source.find("item").unwrap()),
+        ("int is not iterable".to_string(), source.find("count").unwrap()),
+        ("condition must be bool, found string".to_string(), source.find("title").unwrap()),
+        ("unsupported operand types for Sub: string and int".to_string(), source.rfind("title").unwrap()),
+        ("unknown field nmae on object".to_string(), source.find(".nmae").unwrap()),
+    ]);
+}
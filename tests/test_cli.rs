@@ -0,0 +1,151 @@
+mod prelude;
+
+use self::prelude::*;
+use std::fs;
+use std::path::PathBuf;
+use otpl::cli;
+
+fn workspace(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("otpl-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("partials")).unwrap();
+    return dir;
+}
+
+fn run(args: Vec<&str>, input: &str) -> (i32, String, String) {
+    let args: Vec<String> = args.into_iter().map(|a| a.to_string()).collect();
+    let (mut output, mut errors) = (vec![], vec![]);
+    let code = cli::run(&args, &mut input.as_bytes(), &mut output, &mut errors);
+    return (code, String::from_utf8(output).unwrap(), String::from_utf8(errors).unwrap());
+}
+
+#[test]
+fn test_cli_check() {
+    let dir = workspace("check");
+    fs::write(dir.join("ok.html"), "<p>{{a}}</p>").unwrap();
+    fs::write(dir.join("partials/bad.html"), "<p>\n  {{a +}}</p>").unwrap();
+    fs::write(dir.join("notes.txt"), "{{a +}}").unwrap();
+
+    let (code, _, errors) = run(vec!["check", dir.to_str().unwrap()], "");
+    assert_eq!(code, 1);
+    assert!(errors.contains("bad.html:2:"), "{}", errors);
+    assert!(errors.ends_with("checked 2 template(s), 1 with errors\n"), "{}", errors);
+
+    let (code, _, _) = run(vec!["check", dir.join("ok.html").to_str().unwrap()], "");
+    assert_eq!(code, 0);
+    assert_eq!(run(vec!["check"], "").0, 2);
+    assert_eq!(run(vec!["unknown"], "").0, 2);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cli_render() {
+    let dir = workspace("render");
+    let template = dir.join("page.html");
+    fs::write(&template, "<ul>{{for v : items}}<li>{{v.name}}</li>{{/for}}</ul>{{total * 2}}").unwrap();
+    fs::write(dir.join("data.json"), "{\"items\": [{\"name\": \"<a>\"}, {\"name\": \"b\"}], \"total\": 1.5}").unwrap();
+    let path = template.to_str().unwrap();
+    let expected = "<ul><li>&lt;a&gt;</li><li>b</li></ul>3";
+
+    let data = dir.join("data.json");
+    let (code, output, _) = run(vec!["render", path, "--data", data.to_str().unwrap()], "");
+    assert_eq!((code, output.as_str()), (0, expected));
+    // 从标准输入读取数据
+    let (code, output, _) = run(vec!["render", "--data", "-", path], &fs::read_to_string(&data).unwrap());
+    assert_eq!((code, output.as_str()), (0, expected));
+
+    let (code, output, errors) = run(vec!["render", path, "--undefined", "strict"], "");
+    assert_eq!((code, output.as_str()), (1, ""));
+    assert!(errors.starts_with(&format!("{}:1:", path)), "{}", errors);
+    let (code, _, errors) = run(vec!["render", path, "-d", "-"], "[1]");
+    assert_eq!(code, 1);
+    assert!(errors.contains("must be a JSON object"));
+
+    // 引入的模板相对被渲染模板所在的目录查找
+    fs::write(&template, "<ul>{{for v : items}}{{include 'partials/item.html'}}{{/for}}</ul>").unwrap();
+    fs::write(dir.join("partials/item.html"), "<li>{{v.name}}</li>").unwrap();
+    let (code, output, _) = run(vec!["render", path, "--data", data.to_str().unwrap()], "");
+    assert_eq!((code, output.as_str()), (0, "<ul><li>&lt;a&gt;</li><li>b</li></ul>"));
+    fs::write(dir.join("partials/item.html"), "<li>{{v.name +}}</li>").unwrap();
+    let (code, _, errors) = run(vec!["render", path, "--data", data.to_str().unwrap()], "");
+    assert_eq!(code, 1);
+    assert!(errors.contains("partials/item.html:1:"), "{}", errors);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cli_inspect() {
+    let dir = workspace("inspect");
+    let template = dir.join("page.html");
+    fs::write(&template, "<p>\n  {{title}}</p>").unwrap();
+    let path = template.to_str().unwrap();
+
+    let (code, output, _) = run(vec!["tokens", path], "");
+    assert_eq!(code, 0);
+    assert!(output.contains("     2:5  Identifier       \"title\"\n"), "{}", output);
+    let (_, output, _) = run(vec!["tokens", "--json", path], "");
+    let tokens = otpl::json::Json::parse(&output).unwrap();
+    assert_eq!(tokens.as_array().unwrap()[0].to_string(), "{\"column\":2,\"kind\":\"DomTagStart\",\"line\":1,\"offset\":1,\"value\":\"p\"}");
+
+    let (code, output, _) = run(vec!["ast", path], "");
+    assert_eq!(code, 0);
+    assert_eq!(output, "DomTag name=\"p\" @1:2\n  children:\n    Statement @2:5\n      children:\n        Print escape=true @2:5\n          expression:\n            Identifier name=\"title\" @2:5\n");
+    let (_, output, _) = run(vec!["ast", path, "--json"], "");
+    let tree = otpl::json::Json::parse(&output).unwrap();
+    assert_eq!(tree.as_array().unwrap()[0].get("type"), Some(&"DomTag".into()));
+
+    // 出错时输出已扫描的标记和诊断信息
+    fs::write(&template, "<p>{{ 1e }}</p>").unwrap();
+    let (code, output, errors) = run(vec!["tokens", path], "");
+    assert_eq!(code, 1);
+    assert!(output.contains("DomTagStart"));
+    assert!(errors.starts_with(&format!("{}:1:8: error:", path)), "{}", errors);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cli_watch_once() {
+    let dir = workspace("watch");
+    fs::write(dir.join("page.html"), "<p>{{name}}</p>").unwrap();
+    fs::write(dir.join("partials/item.html"), "<li>{{name}}</li>").unwrap();
+    fs::write(dir.join("data.json"), "{\"name\": \"otpl\"}").unwrap();
+    let out = dir.join("out");
+    let args = vec!["watch", dir.to_str().unwrap(), "--once", "--render", out.to_str().unwrap(), "--data"];
+    let data = dir.join("data.json");
+
+    let mut full = args.clone();
+    full.push(data.to_str().unwrap());
+    let (code, output, _) = run(full, "");
+    assert_eq!(code, 0);
+    assert_eq!(output.lines().count(), 2);
+    assert_eq!(fs::read_to_string(out.join("page.html")).unwrap(), "<p>otpl</p>");
+    assert_eq!(fs::read_to_string(out.join("partials/item.html")).unwrap(), "<li>otpl</li>");
+
+    fs::write(dir.join("page.html"), "<p>{{name +}}</p>").unwrap();
+    let (code, _, errors) = run(vec!["watch", "--once", dir.to_str().unwrap()], "");
+    assert_eq!(code, 1);
+    assert!(errors.contains("page.html:1:"), "{}", errors);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cli_watch_includes() {
+    let dir = workspace("watch-includes");
+    fs::write(dir.join("page.html"), "<div>{{include 'partials/header.html'}}</div>").unwrap();
+    fs::write(dir.join("partials/header.html"), "<h1>{{include 'partials/logo.html'}}</h1>").unwrap();
+    fs::write(dir.join("partials/logo.html"), "<img/>").unwrap();
+    let out = dir.join("out");
+
+    // 引入的名称相对监视的根目录，与渲染所用的引擎一致
+    let (code, _, errors) = run(vec!["watch", dir.to_str().unwrap(), "--once", "--render", out.to_str().unwrap()], "");
+    assert_eq!(code, 0, "{}", errors);
+    assert_eq!(fs::read_to_string(out.join("page.html")).unwrap(), "<div><h1><img/></h1></div>");
+    assert_eq!(fs::read_to_string(out.join("partials/header.html")).unwrap(), "<h1><img/></h1>");
+
+    // 相对引入者所在目录的名称找不到
+    fs::write(dir.join("partials/header.html"), "<h1>{{include 'logo.html'}}</h1>").unwrap();
+    let (code, _, errors) = run(vec!["watch", "--once", dir.to_str().unwrap()], "");
+    assert_eq!(code, 1);
+    assert!(errors.contains("\"logo.html\" not found"), "{}", errors);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,54 @@
+mod prelude;
+
+use self::prelude::*;
+use otpl::cst::{self, SyntaxKind, SyntaxNode, TriviaKind};
+
+#[test]
+fn test_cst_round_trip() {
+    let sources = vec![
+        include_str!("dom_extend_if.html"),
+        include_str!("dom_extend_for.html"),
+        include_str!("dom_pure.html"),
+        "  {{// 注释 }}\r\n<p  title = \"标题{{ t }}\" hidden>\t文本 {{ a+b }}</p >{{/* x */}}<!-- c -->\n{{%}} {{raw}} {{%}}  \n",
+        "",
+    ];
+    for src in sources {
+        let tree = cst::parse(src).unwrap();
+        assert_eq!(tree.to_string(), src);
+        assert_eq!(tree.full_span(), 0..src.len());
+    }
+}
+
+#[test]
+fn test_cst_structure() {
+    let tree = cst::parse("<ul>{{for v : items}}<li>{{v}}</li>{{/for}}<br></ul>{{// note }}\n").unwrap();
+    let children = tree.children();
+    assert_eq!(children[0].kind(), Some(SyntaxKind::Element));
+    let kinds: Vec<Option<SyntaxKind>> = children[0].children().iter().map(|n| n.kind()).collect();
+    assert_eq!(kinds, vec![Some(SyntaxKind::StartTag), Some(SyntaxKind::Block), Some(SyntaxKind::Element), Some(SyntaxKind::EndTag)]);
+    let block = &children[0].children()[1];
+    assert_eq!(block.children()[1].kind(), Some(SyntaxKind::Element));
+    match &children[1] {
+        &SyntaxNode::Token(ref tok) => {
+            assert_eq!(tok.kind, TokenKind::Comment);
+            assert_eq!(tok.text, "{{// note }}");
+            assert_eq!(tok.span(), 52..64);
+            assert_eq!(tok.trailing[0].kind, TriviaKind::Newline);
+        }
+        node => panic!("expected comment token, found {:?}", node),
+    }
+}
+
+#[test]
+fn test_cst_trivia() {
+    let tree = cst::parse("a\n  {{ x }}  b").unwrap();
+    let tokens = tree.tokens();
+    let texts: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+    assert_eq!(texts, vec!["a", "{{", "x", "}}", "b", ""]);
+    // 换行属于上一行的尾随部分，缩进属于下一个标记的前导部分
+    assert_eq!(tokens[0].trailing[0].text, "\n");
+    assert_eq!(tokens[1].leading[0].text, "  ");
+    assert_eq!(tokens[1].span(), 4..6);
+    assert_eq!(tokens[3].trailing[0].text, "  ");
+    assert_eq!(tokens[4].full_span(), 13..14);
+}
@@ -2,7 +2,7 @@ mod prelude;
 
 use self::prelude::*;
 
-fn compile(source: &[u8]) {
+fn compile(source: &str) {
     let mut scanner = BytesScanner::new(source, "source".as_ref());
     let root: ast::NodeList;
     {
@@ -0,0 +1,318 @@
+mod prelude;
+
+use self::prelude::*;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use otpl::engine::{Engine, Loader, LruCache, MemoryLoader};
+use otpl::runtime::{Context, Sink, Value, Undefined};
+use otpl::{Error, Result};
+
+fn engine(templates: Vec<(&str, &str)>) -> Engine {
+    let mut loader = MemoryLoader::new();
+    for (name, text) in templates {
+        loader.insert(name, text);
+    }
+    return Engine::new(loader);
+}
+
+#[test]
+fn test_engine_render() {
+    let mut engine = engine(vec![
+        ("page.html", "<ul>{{for v : items}}{{include 'item.html'}}{{/for}}</ul>{{upper(title)}}"),
+        ("item.html", "<li>{{v}}</li>"),
+    ]);
+    engine.register_function("upper", |_, args| {
+        Ok(Value::String(format!("{}", args[0]).to_uppercase()))
+    });
+    let mut ctx = Context::new();
+    ctx.set("items", Value::Array(vec![Value::from("<a>"), Value::Int(2)]));
+    ctx.set("title", "otpl");
+
+    let template = engine.get_template("page.html").unwrap();
+    assert_eq!(template.name(), "page.html");
+    assert_eq!(template.render(&ctx).unwrap(), "<ul><li>&lt;a&gt;</li><li>2</li></ul>OTPL");
+    // 渲染不修改上下文，注册的函数只在渲染期间可见
+    assert!(ctx.get("upper").is_none());
+    // 上下文中的同名变量优先于注册的函数
+    ctx.set("upper", "x");
+    assert!(template.render(&ctx).is_err());
+
+    engine.set_escape(false);
+    let mut ctx = Context::new();
+    ctx.set("title", "");
+    ctx.set("items", Value::Array(vec![Value::from("<a>")]));
+    assert!(engine.get_template("page.html").unwrap().render(&ctx).unwrap().starts_with("<ul><li><a></li></ul>"));
+}
+
+#[test]
+fn test_engine_delimiters() {
+    let mut engine = engine(vec![
+        ("page.html", "<p class=\"<% cls %>\"><% if ok %>{{<% name %>}}<% else %>no<% /if %></p>"),
+    ]);
+    engine.set_delimiters("<%", "%>");
+    let mut ctx = Context::new();
+    ctx.set("cls", "a");
+    ctx.set("ok", true);
+    ctx.set("name", "otpl");
+    assert_eq!(engine.get_template("page.html").unwrap().render(&ctx).unwrap(), "<p class=\"a\">{{otpl}}</p>");
+}
+
+#[test]
+fn test_engine_errors() {
+    let mut engine = engine(vec![
+        ("bad.html", "<p>\n  {{a +}}</p>"),
+        ("page.html", "<p>\n{{include 'part.html'}}</p>"),
+        ("part.html", "{{missing}}"),
+        ("loop.html", "{{include 'loop.html'}}"),
+    ]);
+    match engine.get_template("bad.html") {
+        Err(Error::RefMessage(_, line, _, name)) => assert_eq!((line, name.as_str()), (2, "bad.html")),
+        other => panic!("{:?}", other.map(|t| t.name().to_string())),
+    }
+    assert!(engine.get_template("none.html").is_err());
+
+    engine.set_undefined(Undefined::Strict);
+    match engine.get_template("page.html").unwrap().render(&Context::new()) {
+        Err(Error::RefMessage(msg, 2, _, name)) => {
+            assert_eq!(name, "page.html");
+            assert!(msg.contains("in part.html:1:3:"), "{}", msg);
+        }
+        other => panic!("{:?}", other),
+    }
+    assert!(engine.get_template("loop.html").unwrap().render(&Context::new()).is_err());
+}
+
+/// 可在引擎外修改模板并记录加载次数的加载器。
+#[derive(Clone)]
+struct SharedLoader {
+    templates: Arc<Mutex<HashMap<String, String>>>,
+    loads: Arc<Mutex<Vec<String>>>,
+}
+
+impl SharedLoader {
+    fn set(&self, name: &str, text: Option<&str>) {
+        let mut templates = self.templates.lock().unwrap();
+        match text {
+            Some(text) => templates.insert(name.to_string(), text.to_string()),
+            None => templates.remove(name),
+        };
+    }
+
+    fn loads(&self) -> Vec<String> {
+        let mut loads = self.loads.lock().unwrap();
+        let list = loads.clone();
+        loads.clear();
+        return list;
+    }
+}
+
+impl Loader for SharedLoader {
+    fn load(&self, name: &str) -> Result<String> {
+        self.loads.lock().unwrap().push(name.to_string());
+        return self.templates.lock().unwrap().get(name).cloned().ok_or(Error::Message(format!("{} not found", name)));
+    }
+
+    fn version(&self, name: &str) -> Result<u64> {
+        return self.templates.lock().unwrap().get(name).map(|t| t.len() as u64).ok_or(Error::Message(format!("{} not found", name)));
+    }
+}
+
+#[test]
+fn test_engine_cache() {
+    let loader = SharedLoader { templates: Arc::new(Mutex::new(HashMap::new())), loads: Arc::new(Mutex::new(vec![])) };
+    loader.set("page.html", Some("<p>{{include 'part.html'}}</p>"));
+    loader.set("part.html", Some("{{include 'leaf.html'}}"));
+    loader.set("leaf.html", Some("leaf"));
+    loader.set("other.html", Some("other"));
+    let mut engine = Engine::new(loader.clone());
+    let render = |engine: &Engine, name: &str| engine.get_template(name).unwrap().render(&Context::new()).unwrap();
+
+    assert_eq!(render(&engine, "page.html"), "<p>leaf</p>");
+    // 收集依赖时解析的模板被缓存，每个模板只加载一次
+    assert_eq!(loader.loads(), vec!["page.html", "part.html", "leaf.html"]);
+    assert_eq!(render(&engine, "page.html"), "<p>leaf</p>");
+    assert!(loader.loads().is_empty());
+
+    // 间接引入的模板变化时，引入者被重新编译
+    loader.set("leaf.html", Some("leaf2"));
+    assert_eq!(render(&engine, "page.html"), "<p>leaf2</p>");
+    let loads = loader.loads();
+    assert!(loads.contains(&"page.html".to_string()) && loads.contains(&"leaf.html".to_string()), "{:?}", loads);
+    loader.set("leaf.html", None);
+    assert!(engine.get_template("page.html").unwrap().render(&Context::new()).is_err());
+    loader.set("leaf.html", Some("leaf"));
+    assert_eq!(render(&engine, "page.html"), "<p>leaf</p>");
+
+    engine.set_auto_reload(false);
+    loader.set("page.html", Some("changed"));
+    assert_eq!(render(&engine, "page.html"), "<p>leaf</p>");
+    engine.set_auto_reload(true);
+    assert_eq!(render(&engine, "page.html"), "changed");
+
+    // 超出容量时淘汰最久未使用的模板
+    engine.set_cache_capacity(2);
+    assert_eq!(engine.cached_templates(), vec!["page.html", "leaf.html"]);
+    render(&engine, "other.html");
+    render(&engine, "page.html");
+    assert_eq!(engine.cached_templates(), vec!["page.html", "other.html"]);
+    engine.set_cache_capacity(0);
+    render(&engine, "page.html");
+    assert!(engine.cached_templates().is_empty());
+}
+
+#[test]
+fn test_engine_lru_cache() {
+    let mut cache = LruCache::new(3);
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    assert_eq!(cache.get("a"), Some(&1));
+    cache.insert("b", 4);
+    assert_eq!(cache.names(), vec!["b", "a", "c"]);
+    cache.insert("d", 5);
+    assert_eq!(cache.names(), vec!["d", "b", "a"]);
+    assert_eq!(cache.peek("c"), None);
+    assert_eq!(cache.remove("b"), Some(4));
+    cache.set_capacity(1);
+    assert_eq!(cache.names(), vec!["d"]);
+    cache.clear();
+    assert!(cache.is_empty() && cache.names().is_empty());
+}
+
+#[test]
+fn test_memory_loader_version() {
+    let mut loader = MemoryLoader::new();
+    loader.insert("a.html", "a");
+    loader.insert("b.html", "b");
+    let version = loader.version("a.html").unwrap();
+    assert_eq!(loader.version("a.html").unwrap(), version);
+    assert!(loader.version("b.html").unwrap() != version);
+    // 替换模板时版本变化，即使内容相同
+    loader.insert("a.html", "a");
+    assert!(loader.version("a.html").unwrap() != version);
+    assert!(loader.version("c.html").is_err());
+}
+
+#[test]
+fn test_engine_optimize() {
+    let mut engine = engine(vec![
+        ("page.html", "<p class=\"a\">{{1 + 2}}{{if false}}{{x}}{{/if}}{{('<')}}</p>{{v}}"),
+    ]);
+    let mut ctx = Context::new();
+    ctx.set("v", "<b>");
+    engine.get_template("page.html").unwrap();
+    // 缓存的模板已经过优化，静态的部分只剩下一个字面量
+    let template = engine.get_template("page.html").unwrap();
+    match template.nodes()[0] {
+        Node::Literal(ref tok) => assert_eq!(tok.value_str(), "<p class=\"a\">3&lt;</p>"),
+        ref other => panic!("expected literal, found {:?}", other),
+    }
+    assert_eq!(template.nodes().len(), 2);
+    assert_eq!(template.render(&ctx).unwrap(), "<p class=\"a\">3&lt;</p>&lt;b&gt;");
+
+    // 折叠的输出按引擎的转义设置处理
+    engine.set_escape(false);
+    assert_eq!(engine.get_template("page.html").unwrap().render(&ctx).unwrap(), "<p class=\"a\">3<</p><b>");
+    engine.set_optimize(false);
+    match engine.get_template("page.html").unwrap().nodes()[0] {
+        Node::DomTag(..) => {}
+        ref other => panic!("expected dom tag, found {:?}", other),
+    }
+}
+
+fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+#[test]
+fn test_engine_threads() {
+    let mut engine = engine(vec![
+        ("page.html", "<ul>{{for v : 1..=n}}{{include 'item.html'}}{{/for}}</ul>"),
+        ("item.html", "<li>{{label(v)}}</li>"),
+    ]);
+    engine.register_function("label", |_, args| Ok(Value::String(format!("#{}", args[0]))));
+    let engine = Arc::new(engine);
+    let template = engine.get_template("page.html").unwrap();
+    assert_send_sync(&engine);
+    assert_send_sync(&template);
+
+    let workers: Vec<_> = (1..9).map(|n| {
+        let (engine, template) = (engine.clone(), template.clone());
+        thread::spawn(move || {
+            let mut ctx = Context::new();
+            ctx.set("n", n as i64);
+            let shared = template.render(&ctx).unwrap();
+            assert_eq!(shared, engine.get_template("page.html").unwrap().render(&ctx).unwrap());
+            return shared;
+        })
+    }).collect();
+    for (n, worker) in workers.into_iter().enumerate() {
+        let items: String = (1..n + 2).map(|i| format!("<li>#{}</li>", i)).collect();
+        assert_eq!(worker.join().unwrap(), format!("<ul>{}</ul>", items));
+    }
+}
+
+/// 记录每次刷新时已写入内容的输出，写入的总长度超过 limit 时失败。
+struct Recorder {
+    chunks: Vec<String>,
+    pending: Vec<u8>,
+    limit: usize,
+}
+
+impl io::Write for Recorder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.chunks.iter().map(|c| c.len()).sum::<usize>() + self.pending.len() + buf.len() > self.limit {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "connection closed"));
+        }
+        self.pending.extend_from_slice(buf);
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.chunks.push(String::from_utf8(self.pending.split_off(0)).unwrap());
+        return Ok(());
+    }
+}
+
+#[test]
+fn test_engine_stream() {
+    let engine = engine(vec![
+        ("page.html", "<html><HEAD><title>{{title}}</title></HEAD>\n<body>{{include 'body.html'}}</body></html>"),
+        ("body.html", "<p>{{title}}</p>"),
+    ]);
+    let template = engine.get_template("page.html").unwrap();
+    let mut ctx = Context::new();
+    ctx.set("title", "otpl");
+
+    let mut recorder = Recorder { chunks: vec![], pending: vec![], limit: 1024 };
+    {
+        let mut sink = Sink::new(&mut recorder);
+        sink.add_flush_point("head");
+        template.stream(&ctx, sink).unwrap();
+    }
+    assert_eq!(recorder.chunks, vec!["<html><HEAD><title>otpl</title></HEAD>", "<body><p>otpl</p></body></html>"]);
+
+    // 缓冲时，写出失败的位置是使缓冲达到容量的节点
+    let mut recorder = Recorder { chunks: vec![], pending: vec![], limit: 20 };
+    let mut sink = Sink::new(&mut recorder);
+    sink.set_capacity(20);
+    match template.stream(&ctx, sink) {
+        Err(Error::RefMessage(msg, line, column, _)) => {
+            assert_eq!((line, column), (1, 22));
+            assert!(msg.contains("write:connection closed"), "{}", msg);
+        }
+        other => panic!("{:?}", other),
+    }
+
+    // 不缓冲时，写出失败的位置即正在渲染的节点
+    let mut recorder = Recorder { chunks: vec![], pending: vec![], limit: 50 };
+    let mut sink = Sink::new(&mut recorder);
+    sink.set_capacity(0);
+    match template.stream(&ctx, sink) {
+        Err(Error::RefMessage(msg, 2, _, name)) => {
+            assert_eq!(name, "page.html");
+            assert!(msg.contains("in body.html:1:6: write:connection closed"), "{}", msg);
+        }
+        other => panic!("{:?}", other),
+    }
+}
@@ -0,0 +1,60 @@
+mod prelude;
+
+use self::prelude::*;
+use otpl::formatter::{format, FormatOptions, AttributeOrder};
+use otpl::runtime::{Context, Interpreter};
+
+fn render(source: &str) -> String {
+    let mut ctx = Context::new();
+    ctx.set("items", vec!["a", "b"]);
+    ctx.set("x", "<x>");
+    ctx.set("i", 1);
+    ctx.set("arr", vec![1, 2, 3]);
+    let mut scanner = BytesScanner::new(source, "source".as_ref());
+    let root = Parser::new(&mut scanner).parse_all().expect("Parse Error");
+    let mut output = vec![];
+    Interpreter::new(&mut ctx, &mut output).render(&root).expect("Render Error");
+    return String::from_utf8(output).unwrap();
+}
+
+#[test]
+fn test_format_statements() {
+    let src = "{{// note }}<ul   class=\"list\">{{for i,v:items}}<li>{{v|| 'x'}}</li>{{else}}<li>none</li>{{/for}}</ul>\
+               {{%}}{{raw}} <b>{{%}}{{if a&&(b||c)}}{{-x**2}}{{!!x?.y}}{{/if}}{{/* end */}}";
+    let out = format(src, &FormatOptions::default()).unwrap();
+    assert_eq!(out, "{{// note }}\n\
+                     <ul class=\"list\">\n\
+                     \x20   {{for i, v : items}}\n\
+                     \x20       <li>{{ v || 'x' }}</li>\n\
+                     \x20   {{else}}\n\
+                     \x20       <li>none</li>\n\
+                     \x20   {{/for}}\n\
+                     </ul>\n\
+                     {{%}}{{raw}} <b>{{%}}\n\
+                     {{if a && (b || c)}}\n\
+                     \x20   {{ -x ** 2 }}\n\
+                     \x20   {{!! x?.y }}\n\
+                     {{/if}}\n\
+                     {{/* end */}}\n");
+}
+
+#[test]
+fn test_format_options() {
+    let options = FormatOptions { indent: "  ".to_string(), line_width: 20, attribute_order: AttributeOrder::Alphabetical };
+    let out = format("<div id=\"a\" class=\"b\"><p>short</p><p>this text is too long</p></div>", &options).unwrap();
+    assert_eq!(out, "<div class=\"b\" id=\"a\">\n  <p>short</p>\n  <p>\n    this text is too long\n  </p>\n</div>\n");
+}
+
+#[test]
+fn test_format_round_trip() {
+    let sources = vec![
+        include_str!("dom_extend_if.html"),
+        include_str!("dom_extend_for.html"),
+        "{{for v : items}}<b>{{v}}</b>{{/for}}{{'<' + x}}{{('<' + x)}}{{`a${x}`}}{{arr[1:]}}{{[1, 2][0]}}{{(1 + 2) * 3}}",
+    ];
+    for src in sources {
+        let once = format(src, &FormatOptions::default()).unwrap();
+        assert_eq!(format(&once, &FormatOptions::default()).unwrap(), once);
+        assert_eq!(render(&once), render(src));
+    }
+}
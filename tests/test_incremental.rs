@@ -0,0 +1,79 @@
+mod prelude;
+
+use self::prelude::*;
+use std::ops::Range;
+use otpl::parser::{Document, TextEdit};
+
+fn full(source: &str) -> String {
+    let mut scanner = BytesScanner::new(source, "source".as_ref());
+    let list = Parser::new(&mut scanner).parse_all().expect("Parse Error");
+    return format!("{:?}", list);
+}
+
+fn apply(doc: &mut Document, start: usize, end: usize, text: &str) -> Range<usize> {
+    let changed = doc.edit(&TextEdit::new(start..end, text)).expect("Edit Error");
+    assert_eq!(format!("{:?}", doc.nodes()), full(doc.source()));
+    return changed;
+}
+
+#[test]
+fn test_incremental_reuse() {
+    let src = "<h1>{{title}}</h1>\n<ul>{{for v : items}}<li>{{v}}</li>{{/for}}</ul>\n<p>end</p>\n";
+    let mut doc = Document::parse(src).unwrap();
+    assert_eq!(format!("{:?}", doc.nodes()), full(src));
+
+    // 只重新解析元素中被修改的内容
+    let pos = src.find("end").unwrap();
+    let changed = apply(&mut doc, pos + 1, pos + 1, "xte");
+    assert_eq!(changed, pos..pos + 6);
+
+    // 修改前面的元素，后面元素的偏移随之调整
+    let changed = apply(&mut doc, 6, 11, "page.title");
+    assert_eq!(changed, 4..18);
+
+    // 编辑嵌套的元素和代码块中的内容时只重新解析最内层的段
+    let pos = doc.source().find("{{v}}").unwrap();
+    let changed = apply(&mut doc, pos + 2, pos + 3, "v.name");
+    assert_eq!(&doc.source()[changed], "{{v.name}}");
+    // 编辑范围包含结束标签时，重新解析外层内容中的段
+    let pos = doc.source().find("</li>").unwrap();
+    let changed = apply(&mut doc, pos + 2, pos + 4, "li><li>x</li");
+    assert_eq!(&doc.source()[changed], "<li>{{v.name}}</li><li>x</li>");
+
+    // 插入新的元素
+    let len = doc.source().len();
+    apply(&mut doc, len, len, "<footer>{{year}}</footer>");
+    assert!(doc.source().ends_with("<footer>{{year}}</footer>"));
+}
+
+#[test]
+fn test_incremental_expand() {
+    let src = "<div>a</div><div>{{if x}}b{{/if}}</div><span>c</span>";
+    let mut doc = Document::parse(src).unwrap();
+    // 删除结束标签后结构不完整，扩大重新解析的范围
+    let pos = src.find("</div><span>").unwrap();
+    apply(&mut doc, pos, pos + 6, "");
+    // 扩展指令的分支须与前一元素一起解析
+    let mut doc = Document::parse("<p @if=\"x\">a</p><p>b</p>").unwrap();
+    apply(&mut doc, 18, 18, " @else");
+    // 内容中出现不完整的结构时改为重新解析外层的段
+    let src = "<ul>{{for v : a}}<li>{{v}}</li>{{/for}}</ul><p>x</p>";
+    let mut doc = Document::parse(src).unwrap();
+    let pos = src.find("{{/for}}").unwrap();
+    let changed = apply(&mut doc, pos, pos, "{{/for}}{{for w : b}}");
+    assert_eq!(&doc.source()[changed], "{{for v : a}}<li>{{v}}</li>{{/for}}{{for w : b}}{{/for}}");
+}
+
+#[test]
+fn test_incremental_errors() {
+    let src = "<p>{{a}}</p><p>b</p>";
+    let mut doc = Document::parse(src).unwrap();
+    let before = format!("{:?}", doc.nodes());
+    // 未完成的输入：源码被更新，语法树保留上一次的结果
+    assert!(doc.edit(&TextEdit::new(6..6, " +")).is_err());
+    assert_eq!(doc.source(), "<p>{{a +}}</p><p>b</p>");
+    assert_eq!(format!("{:?}", doc.nodes()), before);
+    let changed = apply(&mut doc, 6, 8, "");
+    assert_eq!(changed, 0..doc.source().len());
+    assert!(doc.edit(&TextEdit::new(10..100, "")).is_err());
+}
@@ -0,0 +1,17 @@
+mod prelude;
+
+use self::prelude::*;
+use otpl::json::Json;
+
+#[test]
+fn test_json_parse() {
+    let value = Json::parse(" {\"a\": [1, -2.5, true, null], \"b\": {\"c\": \"x\\n\\u4e2d\\ud83d\\ude00\"}} ").unwrap();
+    assert_eq!(value.find(&["b", "c"]).and_then(|c| c.as_str()), Some("x\n中😀"));
+    assert_eq!(value.get("a").unwrap().as_array().unwrap()[1], Json::Number(-2.5));
+    assert_eq!(value.to_string(), "{\"a\":[1,-2.5,true,null],\"b\":{\"c\":\"x\\n中😀\"}}");
+    assert_eq!(Json::parse(&value.to_string()).unwrap(), value);
+
+    for text in vec!["", "[1,", "{\"a\" 1}", "\"abc", "tru", "1 2"] {
+        assert!(Json::parse(text).is_err(), "{:?}", text);
+    }
+}
@@ -0,0 +1,135 @@
+mod prelude;
+
+use self::prelude::*;
+use std::io::Cursor;
+use std::path::Path;
+use otpl::json::Json;
+use otpl::lsp::{self, Server};
+
+fn request(id: i64, method: &str, params: Json) -> Json {
+    Json::object(vec![("jsonrpc", "2.0".into()), ("id", id.into()), ("method", method.into()), ("params", params)])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![("jsonrpc", "2.0".into()), ("method", method.into()), ("params", params)])
+}
+
+fn document(uri: &str) -> Json {
+    Json::object(vec![("textDocument", Json::object(vec![("uri", uri.into())]))])
+}
+
+fn position(line: i64, character: i64) -> Json {
+    Json::object(vec![("line", line.into()), ("character", character.into())])
+}
+
+#[test]
+fn test_lsp_diagnostics() {
+    let uri = "file:///tmp/page.html";
+    let mut server = Server::new();
+    let open = Json::object(vec![("textDocument", Json::object(vec![("uri", uri.into()), ("text", "<p>\n  中文{{a +}}</p>".into())]))]);
+    let replies = server.handle(&notification("textDocument/didOpen", open));
+    let diagnostics = replies[0].find(&["params", "diagnostics"]).unwrap().as_array().unwrap().clone();
+    assert_eq!(diagnostics.len(), 1);
+    // 行号从 0 开始，列号按 UTF-16 计
+    assert_eq!(diagnostics[0].find(&["range", "start", "line"]), Some(&Json::from(1i64)));
+    assert_eq!(diagnostics[0].find(&["range", "start", "character"]), Some(&Json::from(9i64)));
+    // 代理对字符占两个 UTF-16 编码单元
+    let open = Json::object(vec![("textDocument", Json::object(vec![("uri", "file:///tmp/emoji.html".into()), ("text", "<p>\n  😀{{a +}}</p>".into())]))]);
+    let replies = server.handle(&notification("textDocument/didOpen", open));
+    let diagnostics = replies[0].find(&["params", "diagnostics"]).unwrap().as_array().unwrap().clone();
+    assert_eq!(diagnostics[0].find(&["range", "start"]), Some(&position(1, 9)));
+
+    // 增量修改后错误消失
+    let change = Json::object(vec![
+        ("range", Json::object(vec![("start", position(1, 7)), ("end", position(1, 9))])),
+        ("text", "".into()),
+    ]);
+    let params = Json::object(vec![
+        ("textDocument", Json::object(vec![("uri", uri.into()), ("version", 2i64.into())])),
+        ("contentChanges", Json::Array(vec![change])),
+    ]);
+    let replies = server.handle(&notification("textDocument/didChange", params));
+    assert_eq!(replies[0].find(&["params", "diagnostics"]), Some(&Json::Array(vec![])));
+
+    let replies = server.handle(&request(1, "textDocument/hover", document(uri)));
+    assert_eq!(replies[0].find(&["error", "code"]), Some(&Json::from(-32601i64)));
+}
+
+#[test]
+fn test_lsp_symbols_and_folding() {
+    let uri = "file:///tmp/list.html";
+    let text = "<ul>\n{{for v : items}}\n<li>{{v}}</li>\n{{/for}}\n</ul>\n{{include 'footer.html'}}";
+    let mut server = Server::new();
+    let open = Json::object(vec![("textDocument", Json::object(vec![("uri", uri.into()), ("text", text.into())]))]);
+    server.handle(&notification("textDocument/didOpen", open));
+
+    let replies = server.handle(&request(1, "textDocument/documentSymbol", document(uri)));
+    let symbols = replies[0].get("result").unwrap().as_array().unwrap();
+    let names: Vec<&str> = symbols.iter().map(|s| s.get("name").unwrap().as_str().unwrap()).collect();
+    assert_eq!(names, vec!["ul", "include 'footer.html'"]);
+    let block = &symbols[0].get("children").unwrap().as_array().unwrap()[0];
+    assert_eq!(block.get("name"), Some(&Json::from("for v : items")));
+    assert_eq!(block.find(&["range", "end", "line"]), Some(&Json::from(3i64)));
+    assert_eq!(block.get("children").unwrap().as_array().unwrap()[0].get("name"), Some(&Json::from("li")));
+
+    let replies = server.handle(&request(2, "textDocument/foldingRange", document(uri)));
+    assert_eq!(replies[0].get("result").unwrap().to_string(), "[{\"endLine\":3,\"startLine\":0},{\"endLine\":2,\"startLine\":1}]");
+}
+
+#[test]
+fn test_lsp_definition() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let uri = lsp::path_to_uri(&dir.join("page.html"));
+    let text = "<div>{{include 'dom_pure.html'}}{{include 'missing.html'}}</div>";
+    let messages = vec![
+        request(1, "initialize", Json::object(vec![])),
+        notification("textDocument/didOpen", Json::object(vec![("textDocument", Json::object(vec![("uri", uri.as_str().into()), ("text", text.into())]))])),
+        request(2, "textDocument/definition", Json::object(vec![("textDocument", Json::object(vec![("uri", uri.as_str().into())])), ("position", position(0, 18))])),
+        request(3, "textDocument/definition", Json::object(vec![("textDocument", Json::object(vec![("uri", uri.as_str().into())])), ("position", position(0, 45))])),
+        request(4, "shutdown", Json::Null),
+        notification("exit", Json::Null),
+    ];
+    let mut input = vec![];
+    for message in messages {
+        lsp::write_message(&mut input, &message).unwrap();
+    }
+    let mut output = vec![];
+    assert_eq!(lsp::run(&mut Cursor::new(input), &mut output).unwrap(), 0);
+
+    let mut output = Cursor::new(output);
+    let mut replies = vec![];
+    while let Some(message) = lsp::read_message(&mut output).unwrap() {
+        replies.push(message);
+    }
+    assert_eq!(replies.len(), 5);
+    assert_eq!(replies[0].find(&["result", "capabilities", "definitionProvider"]), Some(&Json::Bool(true)));
+    let target = replies[2].find(&["result", "uri"]).unwrap().as_str().unwrap();
+    assert_eq!(lsp::uri_to_path(target), dir.join("dom_pure.html"));
+    assert_eq!(replies[3].get("result"), Some(&Json::Null));
+}
+
+#[test]
+fn test_lsp_malformed_messages() {
+    let mut input = vec![];
+    input.extend_from_slice(b"Content-Length: 8\r\n\r\n{\"id\": 1");
+    input.extend_from_slice(b"Content-Length: 2\r\n\r\n\xff\xfe");
+    for message in vec![request(1, "shutdown", Json::Null), notification("exit", Json::Null)] {
+        lsp::write_message(&mut input, &message).unwrap();
+    }
+    let mut output = vec![];
+    // 格式错误的消息不会使服务器退出
+    assert_eq!(lsp::run(&mut Cursor::new(input), &mut output).unwrap(), 0);
+
+    let mut output = Cursor::new(output);
+    let mut replies = vec![];
+    while let Some(message) = lsp::read_message(&mut output).unwrap() {
+        replies.push(message);
+    }
+    assert_eq!(replies.len(), 3);
+    for reply in &replies[..2] {
+        assert_eq!(reply.get("id"), Some(&Json::Null));
+        assert_eq!(reply.find(&["error", "code"]), Some(&Json::from(-32700i64)));
+    }
+    assert_eq!(replies[2].get("id"), Some(&Json::from(1i64)));
+    assert_eq!(replies[2].get("result"), Some(&Json::Null));
+}
@@ -0,0 +1,132 @@
+mod prelude;
+
+use self::prelude::*;
+use self::otpl::optimizer::{optimize, prerender};
+use self::otpl::runtime::{Context, Interpreter};
+
+fn parse(source: &str) -> NodeList {
+    let mut scanner = BytesScanner::new(source, "source".as_ref());
+    return Parser::new(&mut scanner).parse_all().expect("Parse Error");
+}
+
+fn render(root: &NodeList, context: &mut Context) -> String {
+    let mut output = vec![];
+    Interpreter::new(context, &mut output).render(root).expect("Render Error");
+    return String::from_utf8(output).unwrap();
+}
+
+#[test]
+fn test_fold_constants_into_literal() {
+    let root = optimize(parse("<p>a{{1 + 2 * 3}}b{{('<' + `x${2 ** 3}`)}}{{!!'<br>'}}{{true ? 'y' : n}}{{null ?? 'z'}}</p>"));
+    match root[0] {
+        Node::DomTag(_, _, ref children) => {
+            assert_eq!(children.len(), 1);
+            match children[0] {
+                Node::Literal(ref tok) => assert_eq!(tok.value_str(), "a7b&lt;x8<br>yz"),
+                ref other => panic!("expected literal, found {:?}", other),
+            }
+        }
+        ref other => panic!("expected dom tag, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_keep_runtime_errors_and_dynamic_parts() {
+    let root = optimize(parse("{{1 / 0}}{{x + 1 * 2}}{{false && x}}{{x && false}}"));
+    assert_eq!(root.len(), 4);
+    match root[0] {
+        Node::Print(ref body, _) => match **body {
+            Node::Binary(..) => {}
+            ref other => panic!("expected unfolded division, found {:?}", other),
+        },
+        ref other => panic!("expected print, found {:?}", other),
+    }
+    match root[1] {
+        Node::Print(ref body, _) => match **body {
+            Node::Binary(_, ref right, _) => match **right {
+                Node::Const(ast::Constant::Integer(_, 2)) => {}
+                ref other => panic!("expected folded 2, found {:?}", other),
+            },
+            ref other => panic!("expected binary, found {:?}", other),
+        },
+        ref other => panic!("expected print, found {:?}", other),
+    }
+    match root[2] {
+        Node::Literal(ref tok) => assert_eq!(tok.value_str(), "false"),
+        ref other => panic!("expected literal, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_remove_unreachable_branches() {
+    let source = "{{if false}}a{{/if}}{{if 1 > 2}}b{{else}}c{{/if}}{{if x}}d{{else}}{{if true}}e{{/if}}{{/if}}{{for i : items}}{{if true}}{{i}}{{/if}}{{/for}}";
+    let root = optimize(parse(source));
+    match root[0] {
+        Node::Literal(ref tok) => assert_eq!(tok.value_str(), "c"),
+        ref other => panic!("expected literal, found {:?}", other),
+    }
+    match root[1] {
+        Node::If(_, _, ref branches, _) => {
+            assert_eq!(branches.len(), 1);
+            match branches[0] {
+                Node::Else(ref body) => match body[..] {
+                    [Node::Literal(ref tok)] => assert_eq!(tok.value_str(), "e"),
+                    ref other => panic!("expected literal, found {:?}", other),
+                },
+                ref other => panic!("expected else, found {:?}", other),
+            }
+        }
+        ref other => panic!("expected if, found {:?}", other),
+    }
+    match root[2] {
+        Node::For(_, _, _, ref body, _) => match body[0] {
+            Node::Print(..) => {}
+            ref other => panic!("expected print, found {:?}", other),
+        },
+        ref other => panic!("expected for, found {:?}", other),
+    }
+    let mut ctx = Context::new();
+    ctx.set("items", vec![1, 2]);
+    ctx.set("x", false);
+    assert_eq!(render(&root, &mut ctx), render(&parse(source), &mut ctx));
+}
+
+#[test]
+fn test_prerender_static_dom_page() {
+    let source = include_str!("dom_pure.html");
+    let root = prerender(parse(source));
+    let mut ctx = Context::new();
+    let expected = render(&parse(source), &mut ctx);
+    // 只有最后一个标签的 class 属性是动态的
+    assert_eq!(root.len(), 3, "{:?}", root);
+    match (&root[0], &root[1], &root[2]) {
+        (&Node::Literal(ref head), &Node::Statement(_), &Node::Literal(ref tail)) => {
+            assert!(head.value_str().starts_with("<div class=\"wrap\"><h1>"));
+            assert_eq!(tail.value_str(), "\"></div>");
+        }
+        other => panic!("expected literal chunks around one hole, found {:?}", other),
+    }
+    assert_eq!(render(&root, &mut ctx), expected);
+}
+
+#[test]
+fn test_prerender_keeps_dynamic_holes() {
+    let source = "<ul class=\"list\" @for=\"v : items\"><li id=\"i-{{v}}\" hidden>{{v}}<br></li></ul><img src=\"a.png\">";
+    let root = prerender(parse(source));
+    match root[0] {
+        Node::For(_, _, _, ref body, _) => {
+            let kinds: Vec<&str> = body.iter().map(|node| match node {
+                &Node::Literal(_) => "literal",
+                &Node::Print(..) | &Node::Statement(_) => "hole",
+                _ => "other",
+            }).collect();
+            assert_eq!(kinds, vec!["literal", "hole", "literal", "hole", "literal"]);
+        }
+        ref other => panic!("expected for, found {:?}", other),
+    }
+    let mut ctx = Context::new();
+    ctx.set("items", vec![1, 2]);
+    let expected = render(&parse(source), &mut ctx);
+    assert_eq!(render(&root, &mut ctx), expected);
+    assert_eq!(expected, "<ul class=\"list\"><li id=\"i-1\" hidden>1<br/></li></ul><ul class=\"list\"><li id=\"i-2\" hidden>2<br/></li></ul><img src=\"a.png\"/>");
+}
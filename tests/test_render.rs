@@ -0,0 +1,334 @@
+mod prelude;
+
+use std::collections::BTreeMap;
+use self::prelude::*;
+use self::otpl::runtime::{Context, Interpreter, Value, Invoker, Undefined};
+
+fn render(source: &str, context: &mut Context) -> String {
+    let mut scanner = BytesScanner::new(source, "source".as_ref());
+    let root = Parser::new(&mut scanner).parse_all().expect("Parse Error");
+    let mut output = vec![];
+    Interpreter::new(context, &mut output).render(&root).expect("Render Error");
+    return String::from_utf8(output).unwrap();
+}
+
+#[test]
+fn test_loop_metadata() {
+    let mut ctx = Context::new();
+    ctx.set("items", vec!["a", "b", "c"]);
+    let out = render("{{for v : items}}{{loop.index}}/{{loop.length}}{{v}}{{if loop.last}}!{{/if}};{{/for}}", &mut ctx);
+    assert_eq!(out, "1/3a;2/3b;3/3c!;");
+}
+
+#[test]
+fn test_loop_parent_and_cycle() {
+    let mut ctx = Context::new();
+    ctx.set("rows", vec![vec![1, 2], vec![3]]);
+    let out = render("{{for row : rows}}{{for cell : row}}{{loop.parent.index0}}{{loop.cycle('x','y')}}{{/for}}{{/for}}", &mut ctx);
+    assert_eq!(out, "0x0y1x");
+}
+
+#[test]
+fn test_loop_in_dom_extend_for() {
+    let mut ctx = Context::new();
+    ctx.set("arr", Value::from(vec![10, 20]));
+    let out = render("<ul><li @for=\"i : arr\">{{loop.index0}}-{{i}}</li></ul>", &mut ctx);
+    assert_eq!(out, "<ul><li>0-10</li><li>1-20</li></ul>");
+}
+
+#[test]
+fn test_with_renders_else_when_null() {
+    let mut ctx = Context::new();
+    ctx.set("name", "otpl");
+    let out = render("{{with name as n}}{{n}}{{else}}none{{/with}}|{{with missing as m}}{{m}}{{else}}none{{/with}}", &mut ctx);
+    assert_eq!(out, "otpl|none");
+}
+
+#[test]
+fn test_range_iteration() {
+    let mut ctx = Context::new();
+    ctx.set("n", 3);
+    let out = render("{{for i : 0..n}}{{i}}{{/for}}|{{for i : 1..=9 step 4}}{{i}},{{/for}}|{{for i : 3..0 step -1}}{{i}}{{/for}}", &mut ctx);
+    assert_eq!(out, "012|1,5,9,|321");
+}
+
+#[test]
+fn test_slice() {
+    let mut ctx = Context::new();
+    ctx.set("items", vec![1, 2, 3, 4, 5]);
+    ctx.set("s", "abcdef");
+    let out = render("{{items[1:3]}}|{{items[:2]}}|{{items[-2:]}}|{{items[::-2]}}|{{s[2:]}}|{{with 0..100 as r}}{{r[10:13]}}{{/with}}", &mut ctx);
+    assert_eq!(out, "2,3|1,2|4,5|5,3,1|cdef|10..13");
+}
+
+#[test]
+fn test_numeric_literals() {
+    let mut ctx = Context::new();
+    let out = render("{{0xff + 1_000}}|{{0b101}}|{{1.5 * 2}}|{{2.5e-1}}|{{for i : 1..3}}{{i}}{{/for}}", &mut ctx);
+    assert_eq!(out, "1255|5|3|0.25|12");
+}
+
+#[test]
+fn test_integer_literal_overflow() {
+    let mut scanner = BytesScanner::new("{{99999999999999999999}}", "source".as_ref());
+    match Parser::new(&mut scanner).parse_all() {
+        Err(otpl::Error::Scan(msg, offset)) => {
+            assert!(msg.contains("too large"), "{}", msg);
+            assert_eq!(offset, 2);
+        }
+        other => panic!("expected scan error, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_string_escapes() {
+    let mut ctx = Context::new();
+    let out = render(r#"{{!! "a\"b" + '\u{4e2d}\x41' + "\\"}}"#, &mut ctx);
+    assert_eq!(out, "a\"b中A\\");
+}
+
+#[test]
+fn test_template_string() {
+    let mut ctx = Context::new();
+    ctx.set("name", "otpl");
+    ctx.set("items", vec![1, 2]);
+    let out = render("{{`hello ${name}, ${items.length + 1} items, \\${raw} ${name + '}'}`}}", &mut ctx);
+    assert_eq!(out, "hello otpl, 3 items, ${raw} otpl}");
+    // 插值由语句的扫描器直接扫描：}} 不结束语句，插值后的文本不作为运算符
+    let out = render("{{ `${ {a: {b: name}}.a.b }.${name}?${ `<${name}>` }}` }}", &mut ctx);
+    assert_eq!(out, "otpl.otpl?<otpl>}");
+
+    for &(source, offset) in [("<p>{{ `a ${name`}}</p>", 15), ("<p>{{ `a ${name b}` }}</p>", 16)].iter() {
+        let mut scanner = BytesScanner::new(source, "source".as_ref());
+        match Parser::new(&mut scanner).parse_all() {
+            Err(otpl::Error::Scan(_, offs)) | Err(otpl::Error::Parse(_, offs)) => assert_eq!(offs, offset, "{}", source),
+            other => panic!("{}: {:?}", source, other.map(|_| ())),
+        }
+    }
+}
+
+#[test]
+fn test_whitespace_before_delimiter() {
+    let mut ctx = Context::new();
+    ctx.set("name", "otpl");
+    ctx.set("items", vec![1, 2]);
+    let out = render("{{ name }}|{{for v : items\n}}{{ v  }}{{/for }}|{{ `${ name }` }}", &mut ctx);
+    assert_eq!(out, "otpl|12|otpl");
+}
+
+#[test]
+fn test_unicode_identifiers() {
+    let mut ctx = Context::new();
+    ctx.set("用户名", "张三");
+    ctx.set("订单", vec![1, 2]);
+    let out = render("<p data-名称=\"x\">{{用户名}}:{{for 项 : 订单}}{{项}}{{/for}}</p><my-élément/>", &mut ctx);
+    assert_eq!(out, "<p data-名称=\"x\">张三:12</p><my-élément></my-élément>");
+}
+
+#[test]
+fn test_invalid_utf8_source() {
+    match BytesScanner::from_bytes(b"ab\xff{{x}}", "source".as_ref()) {
+        Err(otpl::Error::Scan(_, offset)) => assert_eq!(offset, 2),
+        other => panic!("expected scan error, found {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_unicode_error_messages() {
+    for &(source, found) in [("{{ 😀 }}", "'😀'"), ("{{ 1名 }}", "'名'"), ("<p></😀>", "😀"), ("<p 名=中>", "中")].iter() {
+        let mut scanner = BytesScanner::new(source, "source".as_ref());
+        match Parser::new(&mut scanner).parse_all() {
+            Err(otpl::Error::Scan(msg, _)) => assert!(msg.contains(found), "{}: {}", source, msg),
+            other => panic!("{}: {:?}", source, other.map(|_| ())),
+        }
+    }
+}
+
+#[test]
+fn test_operator_precedence() {
+    let mut ctx = Context::new();
+    ctx.set("a", true);
+    ctx.set("b", false);
+    ctx.set("items", vec!["x", "y"]);
+    let out = render("{{a || b && b}}|{{null ?? b || a}}|{{1 + 2 * 3 ** 2}}|{{-2 ** 2}}|{{2 ** -1}}|{{2 ** 3 ** 2}}|{{1 < 2 == 2 > 1}}|{{10 - 4 - 3}}", &mut ctx);
+    assert_eq!(out, "true|true|19|-4|0.5|512|true|3");
+}
+
+#[test]
+fn test_membership_operators() {
+    let mut ctx = Context::new();
+    ctx.set("items", vec!["x", "y"]);
+    let out = render("{{'x' in items}}|{{'z' not in items}}|{{'bc' in 'abcd'}}|{{4 in 0..10 step 2}}|{{5 in 0..10 step 2}}|{{-(3)}}{{+1}}", &mut ctx);
+    assert_eq!(out, "true|true|true|true|false|-31");
+}
+
+fn register_list_functions(ctx: &mut Context) {
+    ctx.register_function("map", |invoker: &mut dyn Invoker, args: Vec<Value>| {
+        let mut list = vec![];
+        if let Value::Array(ref items) = args[0] {
+            for item in items {
+                list.push(invoker.call(&args[1], vec![item.clone()])?);
+            }
+        }
+        return Ok(Value::Array(list));
+    });
+    ctx.register_function("sort_by", |invoker: &mut dyn Invoker, args: Vec<Value>| {
+        let mut keyed = vec![];
+        if let Value::Array(ref items) = args[0] {
+            for item in items {
+                let key = match invoker.call(&args[1], vec![item.clone()])? {
+                    Value::Int(i) => i,
+                    _ => 0,
+                };
+                keyed.push((key, item.clone()));
+            }
+        }
+        keyed.sort_by_key(|&(key, _)| key);
+        return Ok(Value::Array(keyed.into_iter().map(|(_, item)| item).collect()));
+    });
+}
+
+#[test]
+fn test_lambda_passed_to_host_function() {
+    let mut ctx = Context::new();
+    register_list_functions(&mut ctx);
+    let mut a = BTreeMap::new();
+    a.insert("name".to_string(), Value::from("a"));
+    a.insert("price".to_string(), Value::from(30));
+    let mut b = BTreeMap::new();
+    b.insert("name".to_string(), Value::from("b"));
+    b.insert("price".to_string(), Value::from(10));
+    ctx.set("items", vec![Value::Map(a), Value::Map(b)]);
+    let out = render("{{for item : sort_by(items, (x) => x.price)}}{{item.name}}{{/for}}|{{map([1, 2, 3], x => x * 2)}}", &mut ctx);
+    assert_eq!(out, "ba|2,4,6");
+}
+
+#[test]
+fn test_lambda_captures_scope() {
+    let mut ctx = Context::new();
+    register_list_functions(&mut ctx);
+    ctx.set("rows", vec![1, 2]);
+    let out = render("{{for r : rows}}{{map([10, 20], (x) => x + r + loop.index0)}};{{/for}}|{{((a, b) => a - b)(5, 3)}}|{{(() => 'k')()}}|{{(1 + 2) * 3}}", &mut ctx);
+    assert_eq!(out, "11,21;13,23;|2|k|9");
+}
+
+fn render_with(source: &str, context: &mut Context, undefined: Undefined) -> otpl::Result<String> {
+    let mut scanner = BytesScanner::new(source, "source".as_ref());
+    let root = Parser::new(&mut scanner).parse_all().expect("Parse Error");
+    let mut output = vec![];
+    {
+        let mut interpreter = Interpreter::new(context, &mut output);
+        interpreter.set_undefined(undefined);
+        interpreter.render(&root)?;
+    }
+    return Ok(String::from_utf8(output).unwrap());
+}
+
+fn render_strict(source: &str, context: &mut Context) -> otpl::Result<String> {
+    render_with(source, context, Undefined::Strict)
+}
+
+fn user_with_null_profile() -> Value {
+    let mut user = BTreeMap::new();
+    user.insert("name".to_string(), Value::from("tom"));
+    user.insert("profile".to_string(), Value::Null);
+    return Value::Map(user);
+}
+
+#[test]
+fn test_optional_chaining_lenient() {
+    let mut ctx = Context::new();
+    ctx.set("user", user_with_null_profile());
+    let out = render("{{user.profile.name}}|{{user?.profile?.name ?? 'anon'}}|{{nobody?['x']}}|{{user?['name']}}|{{nobody?.(1)}}|{{user.name?[0:1]}}", &mut ctx);
+    assert_eq!(out, "|anon||tom||t");
+}
+
+#[test]
+fn test_optional_chaining_strict() {
+    let mut ctx = Context::new();
+    ctx.set("user", user_with_null_profile());
+    let out = render_strict("{{user.profile?.name.first}}|{{user?.age?.x ?? 1}}|{{missing ?? 'x'}}|{{missing?.()}}|{{user.profile?[1:]}}", &mut ctx);
+    assert_eq!(out.unwrap(), "|1|x||");
+
+    let source = "{{user.profile.name}}";
+    match render_strict(source, &mut ctx) {
+        Err(otpl::Error::Visit(msg, offset)) => {
+            assert!(msg.contains("cannot read property name of null"), "{}", msg);
+            assert_eq!(offset, source.rfind('.').unwrap());
+        }
+        other => panic!("expected visit error, found {:?}", other),
+    }
+    match render_strict("{{ usr.name }}", &mut ctx) {
+        Err(otpl::Error::Visit(msg, offset)) => {
+            assert!(msg.contains("undefined variable usr"), "{}", msg);
+            assert_eq!(offset, 3);
+        }
+        other => panic!("expected visit error, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_undefined_debug_marker() {
+    let mut ctx = Context::new();
+    ctx.set("user", user_with_null_profile());
+    ctx.set("items", vec![1]);
+    let out = render_with("<p title=\"{{usr}}\">{{user.nmae}}|{{user.profile.name}}|{{items[3]}}|{{usr ?? 'x'}}|{{if usr}}y{{else}}n{{/if}}</p>", &mut ctx, Undefined::Debug);
+    assert_eq!(out.unwrap(), "<p title=\"[undefined: usr]\">[undefined: user.nmae]|[undefined: user.profile.name]|[undefined: items[3]]|x|n</p>");
+    let out = render_with("{{usr}}|{{user.nmae}}", &mut ctx, Undefined::Empty);
+    assert_eq!(out.unwrap(), "|");
+}
+
+#[test]
+fn test_literal_section_and_comments() {
+    let mut ctx = Context::new();
+    ctx.set("x", 1);
+    let out = render("a{{%}}{{x}} <b>{{%}}b{{// note }}{{/* block */}}{{x}}", &mut ctx);
+    assert_eq!(out, "a{{x}} <b>b1");
+}
+
+#[test]
+fn test_runtime_error_offsets() {
+    let mut ctx = Context::new();
+    ctx.set("s", "x");
+    ctx.register_function("fail", |_, _| Err(otpl::Error::Message(format!("failed"))));
+    let cases = vec![
+        ("<p>\n{{ 1/0 }}</p>", "1/0"),
+        ("<p>\n{{ s > 1 }}</p>", "s > 1"),
+        ("<p>\n{{ -s }}</p>", "s }"),
+        ("<p>\n{{ s ** 2 }}</p>", "s **"),
+        ("<p>\n{{for v : 1.5}}{{/for}}</p>", "v :"),
+        ("<p>\n{{ 1..s }}</p>", "s }"),
+        ("<p>\n{{ fail() }}</p>", "("),
+        ("<p>\n{{ {f: 1}.f() }}</p>", "()"),
+    ];
+    for (source, at) in cases {
+        match render_with(source, &mut ctx, Undefined::Empty) {
+            Err(otpl::Error::Visit(_, offset)) => assert_eq!(offset, source.find(at).unwrap(), "{}", source),
+            other => panic!("expected visit error for {}, found {:?}", source, other),
+        }
+    }
+}
+
+#[test]
+fn test_range_near_integer_bounds() {
+    let mut ctx = Context::new();
+    let out = render("{{for i : 9223372036854775806..=9223372036854775807}}{{i}},{{/for}}|{{(0..=9223372036854775807).length}}|{{9223372036854775807 in 0..=9223372036854775807}}", &mut ctx);
+    assert_eq!(out, "9223372036854775806,9223372036854775807,|9223372036854776000|true");
+    let out = render("{{for i : -9223372036854775807 - 1..=-9223372036854775807 step -1}}{{i}}{{/for}}|{{(0..=9223372036854775807)[-2:]}}|{{[1, 2, 3][::9223372036854775807]}}", &mut ctx);
+    assert_eq!(out, "|9223372036854775806..=9223372036854775807|1");
+}
+
+#[test]
+fn test_unbuffered_interpreter() {
+    let mut scanner = BytesScanner::new("<p>{{name}}</p>", "source".as_ref());
+    let root = Parser::new(&mut scanner).parse_all().expect("Parse Error");
+    let mut ctx = Context::new();
+    ctx.set("name", "otpl");
+    let mut output = vec![];
+    {
+        // 不经 render 直接访问节点时，内容同样已全部写出
+        let mut interpreter = Interpreter::new(&mut ctx, &mut output);
+        interpreter.visit_list(&root).expect("Render Error");
+    }
+    assert_eq!(String::from_utf8(output).unwrap(), "<p>otpl</p>");
+}
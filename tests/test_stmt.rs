@@ -0,0 +1,120 @@
+mod prelude;
+
+use self::prelude::*;
+use self::otpl::scanner::Tokenizer;
+
+fn parse(source: &str) -> NodeList {
+    let mut scanner = BytesScanner::new(source, "source".as_ref());
+    let mut parser = Parser::new(&mut scanner);
+    return parser.parse_all().expect("Parse Error");
+}
+
+fn first_in_statement(list: NodeList) -> Node {
+    match list.into_iter().next() {
+        Some(Node::Statement(mut body)) => body.remove(0),
+        other => panic!("expected statement, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_line_and_column() {
+    // 行号和列号都从 1 开始，列号按字节计，超出源的偏移为 0
+    let scanner = BytesScanner::new("ab\n  {{x}}\n\n名{{y}}", "source".as_ref());
+    for &(offset, line, column) in [(0, 1, 1), (1, 1, 2), (2, 1, 3), (3, 2, 1), (5, 2, 3), (11, 3, 1), (12, 4, 1), (15, 4, 4), (100, 0, 0)].iter() {
+        assert_eq!((scanner.line(offset), scanner.column(offset)), (line, column), "offset {}", offset);
+    }
+}
+
+#[test]
+fn test_with() {
+    let node = first_in_statement(parse("{{with order.customer as c}}{{c.name}}{{/with}}"));
+    match node {
+        Node::With(expr, alias, body, with_else) => {
+            match *expr {
+                Node::Property(..) => {}
+                other => panic!("unexpected expression {:?}", other),
+            }
+            assert_eq!(alias.value_str(), "c");
+            assert_eq!(body.len(), 1);
+            match *with_else {
+                Node::Empty => {}
+                other => panic!("unexpected else {:?}", other),
+            }
+        }
+        other => panic!("expected with, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_with_else() {
+    let node = first_in_statement(parse("{{with user as u}}{{u}}{{else}}guest{{/with}}"));
+    match node {
+        Node::With(_, _, _, with_else) => {
+            match *with_else {
+                Node::Else(ref body) => assert_eq!(body.len(), 1),
+                ref other => panic!("unexpected else {:?}", other),
+            }
+        }
+        other => panic!("expected with, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_range_and_slice() {
+    match first_in_statement(parse("{{0..=10 step 2}}")) {
+        Node::Print(body, _) => match *body {
+            Node::Range(_, _, step, inclusive) => {
+                assert!(inclusive);
+                match *step {
+                    Node::Const(_) => {}
+                    other => panic!("unexpected step {:?}", other),
+                }
+            }
+            other => panic!("expected range, found {:?}", other),
+        },
+        other => panic!("expected print, found {:?}", other),
+    }
+    match first_in_statement(parse("{{items[1:]}}")) {
+        Node::Print(body, _) => match *body {
+            Node::Slice(_, start, end, _, _) => {
+                match (*start, *end) {
+                    (Node::Const(_), Node::Empty) => {}
+                    other => panic!("unexpected bounds {:?}", other),
+                }
+            }
+            other => panic!("expected slice, found {:?}", other),
+        },
+        other => panic!("expected print, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_closing_delimiter_token() {
+    // 结束边界符的标记只包含边界符本身（贪婪匹配最后的 }}），之前是多字节字符时也不会切分字符
+    for &(source, offset) in [("{{a}}", 3), ("{{名}}", 5), ("{{ '中'}}}", 9)].iter() {
+        let mut scanner = BytesScanner::new(source, "source".as_ref());
+        let mut tokens = vec![];
+        while let Ok(tok) = scanner.scan() {
+            tokens.push(tok);
+        }
+        match tokens.iter().find(|tok| tok.kind() == &TokenKind::RDelimiter) {
+            Some(tok) => assert_eq!((tok.offset(), tok.value_str()), (offset, "}}"), "{}", source),
+            None => panic!("{}: {:?}", source, tokens),
+        }
+    }
+}
+
+#[test]
+fn test_unterminated_with() {
+    // 未结束的 with 命令定位到 with 关键字
+    for &(source, offset) in [("<p>x</p>\n{{with a as b}}x", 11), ("{{with a as b}}{{for v : a}}{{/for}}", 2)].iter() {
+        let mut scanner = BytesScanner::new(source, "source".as_ref());
+        match Parser::new(&mut scanner).parse_all() {
+            Err(otpl::Error::Parse(msg, pos)) => {
+                assert_eq!(pos, offset, "{}", source);
+                assert!(msg.starts_with("parse_with:with 命令未结束"), "{}", msg);
+            }
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+mod prelude;
+
+use self::prelude::*;
+use std::fs;
+use std::path::PathBuf;
+use otpl::watch::{Event, Watcher};
+
+fn workspace(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("otpl-watch-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("partials")).unwrap();
+    return dir;
+}
+
+#[test]
+fn test_watch_dependents() {
+    let dir = workspace("dependents");
+    fs::write(dir.join("page.html"), "<div>{{include 'partials/header.html'}}</div>").unwrap();
+    fs::write(dir.join("other.html"), "<p>{{a}}</p>").unwrap();
+    fs::write(dir.join("partials/header.html"), "<h1>{{include 'partials/logo.html'}}</h1>").unwrap();
+    fs::write(dir.join("partials/logo.html"), "<img/>").unwrap();
+
+    let mut watcher = Watcher::new(&dir);
+    let events = watcher.poll().unwrap();
+    assert_eq!(events.len(), 4);
+    assert!(events.iter().all(|e| if let &Event::Changed(_) = e { true } else { false }));
+    assert_eq!(watcher.poll().unwrap(), vec![]);
+    assert_eq!(watcher.dependents(&dir.join("partials/logo.html")), vec![dir.join("page.html"), dir.join("partials/header.html")]);
+
+    // 被引入的模板变化时，引入它的模板也受影响
+    fs::write(dir.join("partials/logo.html"), "<img src=\"a.png\"/>").unwrap();
+    let events = watcher.poll().unwrap();
+    assert_eq!(events, vec![
+        Event::Changed(dir.join("page.html")),
+        Event::Changed(dir.join("partials/header.html")),
+        Event::Changed(dir.join("partials/logo.html")),
+    ]);
+
+    // 解析错误只影响自身，被删除的模板使引入者失败
+    fs::write(dir.join("other.html"), "<p>{{a +}}</p>").unwrap();
+    fs::remove_file(dir.join("partials/logo.html")).unwrap();
+    let events = watcher.poll().unwrap();
+    assert_eq!(events.len(), 4);
+    match &events[0] {
+        &Event::Failed(ref path, ref diagnostics) => {
+            assert_eq!(path, &dir.join("other.html"));
+            assert!(diagnostics[0].contains("other.html:1:"), "{:?}", diagnostics);
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    match &events[2] {
+        &Event::Failed(ref path, ref diagnostics) => {
+            assert_eq!(path, &dir.join("partials/header.html"));
+            assert!(diagnostics[0].contains("\"partials/logo.html\" not found"));
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert_eq!(events[3], Event::Removed(dir.join("partials/logo.html")));
+    assert!(watcher.template(&dir.join("page.html")).is_some());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_watch_unreadable() {
+    let dir = workspace("unreadable");
+    fs::write(dir.join("page.html"), "<p>{{include 'loop.html'}}</p>").unwrap();
+    // 指向自身的链接无法读取状态
+    std::os::unix::fs::symlink(dir.join("loop.html"), dir.join("loop.html")).unwrap();
+
+    let mut watcher = Watcher::new(&dir);
+    let events = watcher.poll().unwrap();
+    assert_eq!(events.len(), 2);
+    match &events[0] {
+        &Event::Failed(ref path, ref diagnostics) => {
+            assert_eq!(path, &dir.join("loop.html"));
+            assert!(diagnostics[0].starts_with(&format!("{}: error:", path.display())), "{:?}", diagnostics);
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert_eq!(events[1], Event::Failed(dir.join("page.html"), vec![format!("{}: error: included template \"loop.html\" not found", dir.join("page.html").display())]));
+
+    // 整个目录被删除时，所有模板都视为已删除
+    fs::remove_dir_all(&dir).unwrap();
+    let events = watcher.poll().unwrap();
+    assert_eq!(events, vec![Event::Removed(dir.join("page.html"))]);
+}